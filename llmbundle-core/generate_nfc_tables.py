@@ -0,0 +1,56 @@
+#!/usr/bin/env python3
+"""Writes src/nfc/tables.rs, the Unicode data `nfc.rs` composes characters with, from the
+database of Python's unicodedata module. Run it again with a newer Python to update the tables
+to its version of Unicode."""
+
+import os
+import unicodedata
+
+# The Hangul syllables are composed algorithmically
+pairs = []
+for code in range(0x110000):
+    decomposition = unicodedata.decomposition(chr(code))
+    if not decomposition or decomposition.startswith("<"):
+        continue
+    parts = [int(part, 16) for part in decomposition.split()]
+    # Leaves out the singletons and the composition exclusions, which NFC doesn't compose
+    if len(parts) == 2 and unicodedata.normalize("NFC", chr(parts[0]) + chr(parts[1])) == chr(code):
+        pairs.append((parts[0], parts[1], code))
+pairs.sort()
+seconds = sorted({second for _, second, _ in pairs})
+
+classes = []
+for code in range(0x110000):
+    combining = unicodedata.combining(chr(code))
+    if not combining:
+        continue
+    if classes and classes[-1][1] == code - 1 and classes[-1][2] == combining:
+        classes[-1][1] = code
+    else:
+        classes.append([code, code, combining])
+
+
+def rows(items, per_line):
+    for i in range(0, len(items), per_line):
+        yield "    " + " ".join(items[i : i + per_line]) + "\n"
+
+
+path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "src", "nfc", "tables.rs")
+with open(path, "w") as out:
+    out.write(f"// Generated by generate_nfc_tables.py from Unicode {unicodedata.unidata_version}, don't edit.\n\n")
+    out.write("/// The canonical compositions NFC makes, as `(first, second, composed)` sorted by the pair\n")
+    out.write("#[rustfmt::skip]\n")
+    out.write(f"pub(super) const COMPOSITIONS: [(u32, u32, u32); {len(pairs)}] = [\n")
+    out.writelines(rows([f"(0x{a:X}, 0x{b:X}, 0x{c:X})," for a, b, c in pairs], 4))
+    out.write("];\n\n")
+    out.write("/// The characters composing with the one before, sorted\n")
+    out.write("#[rustfmt::skip]\n")
+    out.write(f"pub(super) const SECONDS: [u32; {len(seconds)}] = [\n")
+    out.writelines(rows([f"0x{b:X}," for b in seconds], 10))
+    out.write("];\n\n")
+    out.write("/// The ranges of characters with a non-zero canonical combining class, as\n")
+    out.write("/// `(first, last, class)` sorted\n")
+    out.write("#[rustfmt::skip]\n")
+    out.write(f"pub(super) const COMBINING_CLASSES: [(u32, u32, u8); {len(classes)}] = [\n")
+    out.writelines(rows([f"(0x{a:X}, 0x{b:X}, {c})," for a, b, c in classes], 5))
+    out.write("];\n")
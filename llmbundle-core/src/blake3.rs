@@ -0,0 +1,328 @@
+//! BLAKE3, used to fingerprint bundles and files: the state of the last bundle, the history,
+//! the index and the manifests. It's several times faster than SHA-256, which is kept for the
+//! formats checked with other tools, such as the checksums of `--checksums`.
+//!
+//! The portable implementation of the specification, hashing a chunk at a time without SIMD,
+//! giving 32-byte hashes.
+
+const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+pub fn digest(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Hasher::default();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// The BLAKE3 of `data`, as lowercase hex.
+pub fn hex(data: &[u8]) -> String {
+    to_hex(&digest(data))
+}
+
+fn to_hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Hashes data given piece by piece, as it's read.
+#[derive(Clone)]
+pub struct Hasher {
+    chunk: ChunkState,
+    /// Chaining values of the complete subtrees on the left of the current chunk, the largest
+    /// first
+    stack: Vec<[u32; 8]>,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher {
+            chunk: ChunkState::new(0),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // A chunk is only closed once more data follows, the last one being the root if alone
+            if self.chunk.len() == CHUNK_LEN {
+                let cv = self.chunk.output().chaining_value();
+                let total_chunks = self.chunk.counter + 1;
+                self.push_chunk(cv, total_chunks);
+                self.chunk = ChunkState::new(total_chunks);
+            }
+            let take = (CHUNK_LEN - self.chunk.len()).min(data.len());
+            self.chunk.update(&data[..take]);
+            data = &data[take..];
+        }
+    }
+
+    pub fn finalize(&self) -> [u8; 32] {
+        let mut output = self.chunk.output();
+        for left in self.stack.iter().rev() {
+            output = parent(*left, output.chaining_value());
+        }
+        let words = output.compress(ROOT);
+        let mut hash = [0; 32];
+        for (bytes, word) in hash.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        hash
+    }
+
+    /// The BLAKE3 of the data given so far, as lowercase hex.
+    pub fn hex(&self) -> String {
+        to_hex(&self.finalize())
+    }
+
+    /// Adds the chaining value of a chunk, merging the subtrees it completes: as many as the
+    /// trailing zero bits of the number of chunks so far.
+    fn push_chunk(&mut self, mut cv: [u32; 8], mut total_chunks: u64) {
+        while total_chunks & 1 == 0 {
+            let left = self
+                .stack
+                .pop()
+                .expect("a subtree per set bit of the chunks hashed");
+            cv = parent(left, cv).chaining_value();
+            total_chunks >>= 1;
+        }
+        self.stack.push(cv);
+    }
+}
+
+#[derive(Clone)]
+struct ChunkState {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: usize,
+}
+
+impl ChunkState {
+    fn new(counter: u64) -> Self {
+        ChunkState {
+            cv: IV,
+            counter,
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            blocks_compressed: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        match self.blocks_compressed {
+            0 => CHUNK_START,
+            _ => 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // Like the chunks, a full block is only compressed once more data follows
+            if self.block_len == BLOCK_LEN {
+                let words = compress(
+                    &self.cv,
+                    &words(&self.block),
+                    self.counter,
+                    BLOCK_LEN as u32,
+                    self.start_flag(),
+                );
+                self.cv.copy_from_slice(&words[..8]);
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+            let take = (BLOCK_LEN - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        Output {
+            cv: self.cv,
+            block: words(&self.block),
+            counter: self.counter,
+            block_len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+}
+
+/// The last compression of a chunk or a parent, kept uncompressed until known to be the root
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        let words = self.compress(0);
+        words[..8].try_into().expect("8 words")
+    }
+
+    fn compress(&self, flags: u32) -> [u32; 16] {
+        compress(
+            &self.cv,
+            &self.block,
+            self.counter,
+            self.block_len,
+            self.flags | flags,
+        )
+    }
+}
+
+/// The parent node of the subtrees of chaining values `left` and `right`.
+fn parent(left: [u32; 8], right: [u32; 8]) -> Output {
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&left);
+    block[8..].copy_from_slice(&right);
+    Output {
+        cv: IV,
+        block,
+        counter: 0,
+        block_len: BLOCK_LEN as u32,
+        flags: PARENT,
+    }
+}
+
+fn words(block: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().expect("4 bytes"));
+    }
+    words
+}
+
+fn compress(
+    cv: &[u32; 8],
+    block: &[u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+) -> [u32; 16] {
+    #[rustfmt::skip]
+    let mut state = [
+        cv[0], cv[1], cv[2], cv[3], cv[4], cv[5], cv[6], cv[7],
+        IV[0], IV[1], IV[2], IV[3],
+        counter as u32, (counter >> 32) as u32, block_len, flags,
+    ];
+    let mut block = *block;
+    for round in 0..7 {
+        if round > 0 {
+            block = MSG_PERMUTATION.map(|i| block[i]);
+        }
+        // Mixes the columns, then the diagonals
+        g(&mut state, 0, 4, 8, 12, block[0], block[1]);
+        g(&mut state, 1, 5, 9, 13, block[2], block[3]);
+        g(&mut state, 2, 6, 10, 14, block[4], block[5]);
+        g(&mut state, 3, 7, 11, 15, block[6], block[7]);
+        g(&mut state, 0, 5, 10, 15, block[8], block[9]);
+        g(&mut state, 1, 6, 11, 12, block[10], block[11]);
+        g(&mut state, 2, 7, 8, 13, block[12], block[13]);
+        g(&mut state, 3, 4, 9, 14, block[14], block[15]);
+    }
+    for i in 0..8 {
+        state[i] ^= state[i + 8];
+        state[i + 8] ^= cv[i];
+    }
+    state
+}
+
+fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The input of the official test vectors: the bytes 0 to 250, repeated.
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn matches_the_official_vectors() {
+        let vectors = [
+            (
+                0,
+                "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            ),
+            (
+                1,
+                "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            ),
+            (
+                1023,
+                "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+            (
+                2048,
+                "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+            ),
+            (
+                2049,
+                "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030",
+            ),
+            (
+                3072,
+                "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+            ),
+            (
+                31744,
+                "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+            ),
+        ];
+        for (len, expected) in vectors {
+            assert_eq!(hex(&input(len)), expected, "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn hashes_the_same_in_pieces() {
+        let data = input(5000);
+        for piece in [1, 63, 64, 1000, 1024, 1025] {
+            let mut hasher = Hasher::default();
+            for chunk in data.chunks(piece) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.hex(), hex(&data), "in pieces of {piece} bytes");
+        }
+    }
+}
@@ -0,0 +1,1173 @@
+//! The [`Bundler`], walking the root, reading the selected files and keeping them within the
+//! token budget.
+
+use crate::conflicts::{self, Conflicts};
+use crate::format::{
+    conflicted_stub, conflicts_header, format, group, render, Bundle, Format, ProcessedFile,
+};
+use crate::select::{build_glob_sets, sort_by, Sort};
+use crate::{paths, sha256};
+use anyhow::Result;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+#[cfg(feature = "fs")]
+use {
+    crate::cache::{self, ReadCache},
+    crate::deadline,
+    crate::format::{preamble, Layout},
+    crate::links,
+    crate::select::sort,
+    crate::spill::Spill,
+    crate::tokens,
+    crate::{blake3, mmap},
+    anyhow::bail,
+    ignore::{DirEntry, ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkState},
+    rayon::prelude::*,
+    std::borrow::Cow,
+    std::collections::HashMap,
+    std::io::{Read, Write},
+    std::sync::{Arc, Mutex},
+    std::time::Instant,
+};
+
+/// Hooks called as the bundler works, to show its progress or stop it early. Every method does
+/// nothing by default.
+pub trait Observer: Sync {
+    /// A file was found while walking the root.
+    fn found(&self, _path: &Path) {}
+
+    /// The walk is over.
+    fn walked(&self) {}
+
+    /// `count` files are about to be read.
+    fn reading(&self, _count: usize) {}
+
+    /// A file of `bytes` bytes was read.
+    fn read(&self, _path: &Path, _bytes: u64) {}
+
+    /// The file at `path` was read and formatted, taking `timings`. Not called for the files
+    /// reused from a [`ReadCache`].
+    fn processed(&self, _path: &Path, _timings: &FileTimings) {}
+
+    /// The file at `path` couldn't be read or transformed because of `error`, and is bundled
+    /// empty. Logs a warning by default.
+    fn failed(&self, path: &Path, error: &str) {
+        log::warn!(path:% = path.display(); "{error}");
+    }
+
+    /// The files were read.
+    fn done_reading(&self) {}
+
+    /// The files read were rendered into the bundle, by [`Bundler::bundle_files`].
+    fn rendered(&self) {}
+
+    /// Whether to stop reading, bundling the files already read.
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+impl Observer for () {}
+
+#[cfg(feature = "fs")]
+/// Bytes read at once from a file counted as it's read against the budget
+const READ_CHUNK: usize = 64 * 1024;
+
+#[cfg(feature = "fs")]
+/// Files read at once by each thread when streaming, the ones of a batch being held in memory
+/// until written
+const FILES_PER_THREAD: usize = 4;
+
+/// Changes the content of a file, given its path relative to the root, or fails with a message
+type Transform = dyn Fn(&Path, String) -> Result<String, String> + Send + Sync;
+
+type Header = dyn Fn(&Path, &str) -> Option<String> + Send + Sync;
+
+/// Builds bundles of the files under a root, configured with the methods named after the flags
+/// of the command.
+#[cfg_attr(not(feature = "fs"), allow(dead_code))]
+pub struct Bundler {
+    root: PathBuf,
+    patterns: Vec<String>,
+    max_depth: Option<usize>,
+    max_tokens: Option<usize>,
+    format: Format,
+    checksums: bool,
+    strict: bool,
+    sort: Sort,
+    reverse: bool,
+    group_by_dir: Option<usize>,
+    memory_limit: Option<u64>,
+    threads: Option<usize>,
+    timeout: Option<Duration>,
+    #[cfg(feature = "fs")]
+    cache: Option<Arc<ReadCache>>,
+    transform: Option<Box<Transform>>,
+    header: Option<Box<Header>>,
+    conflicts: Conflicts,
+    observer: Box<dyn Observer>,
+}
+
+impl Bundler {
+    /// A bundler of every file under `root` that isn't ignored, in the default format and
+    /// without budget.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Bundler {
+            root: root.into(),
+            patterns: Vec::new(),
+            max_depth: None,
+            max_tokens: None,
+            format: Format::default(),
+            checksums: false,
+            strict: false,
+            sort: Sort::default(),
+            reverse: false,
+            group_by_dir: None,
+            memory_limit: None,
+            threads: None,
+            timeout: None,
+            #[cfg(feature = "fs")]
+            cache: None,
+            transform: None,
+            header: None,
+            conflicts: Conflicts::default(),
+            observer: Box::new(()),
+        }
+    }
+
+    /// Glob patterns of the files to bundle, the ones starting with `!` leaving files out.
+    pub fn patterns(mut self, patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.patterns = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Maximum depth of the directories walked.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Token budget, the files that don't fit being left out.
+    pub fn max_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Adds the SHA-256 of each file to its header.
+    pub fn checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Counts the files that aren't valid UTF-8 as unreadable instead of replacing the invalid
+    /// bytes.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Order of the files, reversed with `reverse`.
+    pub fn sort(mut self, sort: Sort, reverse: bool) -> Self {
+        self.sort = sort;
+        self.reverse = reverse;
+        self
+    }
+
+    /// Groups the files by their first `depth` directories under headers.
+    pub fn group_by_dir(mut self, depth: Option<usize>) -> Self {
+        self.group_by_dir = depth;
+        self
+    }
+
+    /// Bytes of memory [`Bundler::stream`] holds the files in at most: the files read at once
+    /// are throttled by their size, and with [`Sort::Tokens`] the files waiting to be written are
+    /// spilled to a temp file past half of it. A file bigger than the limit is still read, alone.
+    pub fn memory_limit(mut self, bytes: Option<u64>) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Threads walking the directories, by default as many as the cores up to 12. The files are
+    /// read on the current rayon pool, sized with [`rayon::ThreadPoolBuilder::build_global`].
+    pub fn threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Longest a single file or directory may take to be looked up or read, the ones taking longer
+    /// being skipped rather than waited for, like the ones of a hung network mount. Without it
+    /// they're waited for however long.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reuses the files of `cache` that didn't change since they were read, adding the others.
+    #[cfg(feature = "fs")]
+    pub fn cache(mut self, cache: Arc<ReadCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Changes the content of each file once read, before it's formatted and counted. A file the
+    /// transform fails on is bundled empty and counted as unreadable, rather than as it was.
+    pub fn transform(
+        mut self,
+        transform: impl Fn(&Path, String) -> Result<String, String> + Send + Sync + 'static,
+    ) -> Self {
+        self.transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Adds the line `header` gives for a file, from its path and content, to its header after
+    /// its name and checksum, like `[file last commit]: ...`. The lines should start with
+    /// `[file ` for the bundle to be parsed back. The files reused from a [`ReadCache`] keep the
+    /// lines they were read with.
+    pub fn header(
+        mut self,
+        header: impl Fn(&Path, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.header = Some(Box::new(header));
+        self
+    }
+
+    /// What becomes of the files with unresolved merge conflicts, flagged in their header by
+    /// default.
+    pub fn conflicts(mut self, conflicts: Conflicts) -> Self {
+        self.conflicts = conflicts;
+        self
+    }
+
+    pub fn observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.observer = Box::new(observer);
+        self
+    }
+
+    /// Finds the files under the root matching the patterns, relative to the root.
+    #[cfg(feature = "fs")]
+    pub fn find_files(&self) -> Result<Vec<PathBuf>> {
+        self.select(&self.walk()?)
+    }
+
+    /// Lists every file under the root that isn't ignored, relative to the root, in no particular
+    /// order as the directories are walked in parallel.
+    #[cfg(feature = "fs")]
+    pub fn walk(&self) -> Result<Vec<PathBuf>> {
+        log::info!(root:% = self.root.display(); "Searching for files");
+
+        let walker = WalkBuilder::new(&self.root)
+            .max_depth(self.max_depth)
+            .threads(self.threads.unwrap_or(0))
+            .build_parallel();
+
+        let found = Mutex::new(Found::default());
+        let error = Mutex::new(None);
+        walker.visit(&mut Collectors {
+            bundler: self,
+            found: &found,
+            error: &error,
+        });
+        self.observer.walked();
+
+        if let Some(error) = error.into_inner().expect("walker threads don't panic") {
+            return Err(error.into());
+        }
+        let mut found = found.into_inner().expect("walker threads don't panic");
+        // Only the ones the patterns would have bundled are worth a warning
+        let matches = self.matcher()?;
+        found.specials.sort();
+        for (path, kind) in found.specials {
+            if matches(&path) {
+                log::warn!(path:% = path.display(); "Skipping the {kind}, which isn't a regular file");
+            }
+        }
+        if !found.timed_out.is_empty() {
+            found.timed_out.sort();
+            let paths: Vec<String> = found
+                .timed_out
+                .iter()
+                .map(|path| paths::display(path))
+                .collect();
+            let count = match paths.len() {
+                1 => "1 path".to_string(),
+                count => format!("{count} paths"),
+            };
+            log::warn!(
+                "Skipped {count} (timed out after {:?}): {}",
+                self.timeout.unwrap_or_default(),
+                paths.join(", ")
+            );
+        }
+        Ok(found.files)
+    }
+
+    /// Keeps the `candidates` matching the patterns, in order.
+    #[cfg(feature = "fs")]
+    pub fn select(&self, candidates: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let matches = self.matcher()?;
+        let mut matching_files: Vec<PathBuf> = candidates
+            .iter()
+            .filter(|path| matches(path))
+            .cloned()
+            .collect();
+        sort(&self.root, self.sort, self.reverse, &mut matching_files);
+
+        log::info!("Total matching files: {}", matching_files.len());
+
+        Ok(matching_files)
+    }
+
+    /// Whether a path is matched by the patterns.
+    fn matcher(&self) -> Result<impl Fn(&Path) -> bool> {
+        let (pos_globs, neg_globs) = build_glob_sets(&self.patterns)?;
+        Ok(move |path: &Path| {
+            let normalized = paths::normalized(path);
+            let matched = pos_globs.is_match(&normalized) && !neg_globs.is_match(&normalized);
+            if matched {
+                log::debug!(path:% = path.display(); "Matched file");
+            }
+            matched
+        })
+    }
+
+    /// Bundles the in-memory `files`, given as their path relative to the root and their content,
+    /// that match the patterns, without touching the filesystem: for hosts without one, such as
+    /// WebAssembly, and files that aren't on disk. Sorting by modification time keeps them by
+    /// path, as they have none.
+    pub fn bundle_virtual(
+        &self,
+        files: impl IntoIterator<Item = (PathBuf, String)>,
+    ) -> Result<Bundle> {
+        let matches = self.matcher()?;
+        let mut unreadable = Vec::new();
+        let mut files: Vec<ProcessedFile> = files
+            .into_iter()
+            .filter(|(path, _)| matches(path))
+            .map(|(path, content)| {
+                let content = self.transformed(&path, content).unwrap_or_else(|e| {
+                    self.observer.failed(&path, &e);
+                    unreadable.push(path.clone());
+                    String::new()
+                });
+                let formatted = self.formatted(&path, &content);
+                ProcessedFile::with_formatted(path, content, formatted)
+            })
+            .collect();
+        sort_by(
+            self.sort,
+            self.reverse,
+            &mut files,
+            |file| &file.path,
+            |file| file.content.len() as u64,
+            |_| None,
+        );
+        Ok(self.assemble(files, unreadable))
+    }
+
+    /// Finds the files matching the patterns and renders them into a single bundle.
+    #[cfg(feature = "fs")]
+    pub fn bundle(&self) -> Result<Bundle> {
+        Ok(self.bundle_files(&self.find_files()?))
+    }
+
+    /// Renders the given files (relative to the root) into a single bundle.
+    #[cfg(feature = "fs")]
+    pub fn bundle_files(&self, matching_files: &[PathBuf]) -> Bundle {
+        if let Some(cache) = &self.cache {
+            cache.retain(matching_files);
+        }
+        let originals = links::originals(&self.root, matching_files);
+        self.observer.reading(matching_files.len());
+        // Sorting by tokens needs every file read to know the ones the budget keeps
+        if let Some(max_tokens) = self.max_tokens.filter(|_| self.sort != Sort::Tokens) {
+            return self.bundle_within(matching_files, &originals, max_tokens);
+        }
+        let files: Vec<Option<(ProcessedFile, bool)>> = matching_files
+            .par_iter()
+            .map(|path| {
+                // Stop reading when cancelled, keeping the files already read
+                if self.observer.cancelled() {
+                    return None;
+                }
+                self.process(path, originals.get(path), None)
+            })
+            .collect();
+        self.observer.done_reading();
+        let mut unreadable = Vec::new();
+        let files: Vec<ProcessedFile> = files
+            .into_iter()
+            .flatten()
+            .map(|(file, read)| {
+                if !read {
+                    unreadable.push(file.path.clone());
+                }
+                file
+            })
+            .collect();
+        self.assemble(files, unreadable)
+    }
+
+    /// Like [`Bundler::bundle_files`] with a budget, reading the files in the order they're
+    /// bundled a batch at a time and counting them as they're read, so the ones that can't fit in
+    /// what's left of the budget aren't read at all.
+    #[cfg(feature = "fs")]
+    fn bundle_within(
+        &self,
+        matching_files: &[PathBuf],
+        originals: &HashMap<PathBuf, PathBuf>,
+        max_tokens: usize,
+    ) -> Bundle {
+        let mut paths = matching_files.to_vec();
+        if let Some(depth) = self.group_by_dir {
+            paths.sort_by_cached_key(|path| group(path, depth));
+        }
+        let (mut files, mut dropped, mut unreadable) = (Vec::new(), Vec::new(), Vec::new());
+        let mut total = 0;
+        for batch in self.batches(&paths, false) {
+            if self.observer.cancelled() {
+                break;
+            }
+            let read: Vec<Option<(ProcessedFile, bool)>> = batch
+                .par_iter()
+                .map(|path| self.process(path, originals.get(path), Some(max_tokens - total)))
+                .collect();
+            // Greedily, like `assemble`
+            for (path, file) in batch.iter().zip(read) {
+                let Some((file, read)) = file else {
+                    dropped.push(path.clone());
+                    continue;
+                };
+                if !read {
+                    unreadable.push(file.path.clone());
+                }
+                if total + file.token_count <= max_tokens {
+                    total += file.token_count;
+                    files.push(file);
+                } else {
+                    dropped.push(file.path);
+                }
+            }
+        }
+        self.observer.done_reading();
+        if !dropped.is_empty() {
+            log::info!(
+                "Left out {} files past the budget of {max_tokens} tokens",
+                dropped.len()
+            );
+        }
+        self.finish(files, dropped, unreadable)
+    }
+
+    /// Characters the header and footer of the file at `path` take up in the bundle, the
+    /// checksum having the same length whatever the content.
+    #[cfg(feature = "fs")]
+    fn header_chars(&self, path: &Path) -> usize {
+        self.formatted(path, "").chars().count()
+    }
+
+    /// The file at `path` as it appears in the bundle, with its checksum and header lines, or
+    /// its stub when left out for its merge conflicts.
+    fn formatted(&self, path: &Path, content: &str) -> String {
+        let conflicts = match self.conflicts {
+            Conflicts::Ignore => 0,
+            Conflicts::Flag | Conflicts::Exclude => conflicts::count(content),
+        };
+        if conflicts > 0 {
+            let plural = if conflicts == 1 { "" } else { "s" };
+            if self.conflicts == Conflicts::Exclude {
+                log::warn!(path:% = path.display(); "Left out, it has {conflicts} unresolved merge conflict{plural}");
+                return conflicted_stub(path);
+            }
+            log::warn!(path:% = path.display(); "Has {conflicts} unresolved merge conflict{plural}");
+        }
+
+        let checksum = self.checksums.then(|| sha256::hex(content.as_bytes()));
+        let mut header = self
+            .header
+            .as_ref()
+            .and_then(|header| header(path, content));
+        if conflicts > 0 {
+            let flag = conflicts_header(conflicts);
+            header = Some(match header {
+                Some(header) => format!("{flag}\n{header}"),
+                None => flag,
+            });
+        }
+        format(path, content, checksum.as_deref(), header.as_deref())
+    }
+
+    /// Fewest characters the content of the file at `path` has, known without reading it: one
+    /// for every 4 bytes after a possible byte order mark, in UTF-8 and UTF-16 alike, or none
+    /// with a transform, which can shorten it.
+    #[cfg(feature = "fs")]
+    fn min_chars(&self, path: &Path) -> usize {
+        if self.transform.is_some() {
+            return 0;
+        }
+        std::fs::metadata(self.root.join(path)).map_or(0, |metadata| {
+            usize::try_from(metadata.len().saturating_sub(2) / 4).unwrap_or(usize::MAX)
+        })
+    }
+
+    /// Orders the files read, keeps the ones within the budget and renders them.
+    fn assemble(&self, mut files: Vec<ProcessedFile>, unreadable: Vec<PathBuf>) -> Bundle {
+        // The paths were sorted by `select` for the other keys, which only needs the metadata
+        if self.sort == Sort::Tokens {
+            files.sort_by_key(|file| file.token_count);
+            if self.reverse {
+                files.reverse();
+            }
+        }
+
+        // Keeps the order within each group, the groups being sorted by path
+        if let Some(depth) = self.group_by_dir {
+            files.sort_by_cached_key(|file| group(&file.path, depth));
+        }
+
+        let mut dropped = Vec::new();
+        if let Some(max_tokens) = self.max_tokens {
+            // Greedily keep the files that still fit, in order, so a single huge file doesn't push
+            // out everything after it
+            let mut total = 0;
+            files.retain(|file| {
+                if total + file.token_count <= max_tokens {
+                    total += file.token_count;
+                    true
+                } else {
+                    dropped.push(file.path.clone());
+                    false
+                }
+            });
+        }
+        self.finish(files, dropped, unreadable)
+    }
+
+    /// Renders the ordered `files` kept within the budget into the bundle.
+    fn finish(
+        &self,
+        files: Vec<ProcessedFile>,
+        dropped: Vec<PathBuf>,
+        unreadable: Vec<PathBuf>,
+    ) -> Bundle {
+        let text = render(&files, self.format, self.group_by_dir);
+        self.observer.rendered();
+        Bundle {
+            text,
+            files,
+            dropped,
+            max_tokens: self.max_tokens,
+            format: self.format,
+            group_by_dir: self.group_by_dir,
+            unreadable,
+        }
+    }
+
+    /// Writes the bundle of the given files (relative to the root) to `out` as they're read, a
+    /// batch at a time, instead of rendering it in memory. The text is the same as the one of
+    /// [`Bundler::bundle_files`]. [`Sort::Tokens`] needs every file read before writing the first,
+    /// so it requires a [`Bundler::memory_limit`], past which they're spilled to disk.
+    #[cfg(feature = "fs")]
+    pub fn stream(&self, matching_files: &[PathBuf], out: &mut dyn Write) -> Result<Streamed> {
+        let by_tokens = self.sort == Sort::Tokens;
+        let spill_limit = match (by_tokens, self.memory_limit) {
+            (false, _) => None,
+            (true, Some(limit)) => Some(limit / 2),
+            (true, None) => bail!(
+                "Sorting by tokens needs every file read before writing the first one, so it \
+                 needs a memory limit to spill them to disk"
+            ),
+        };
+        // The same files as `bundle_files` get the content, whatever the order they're written in
+        let originals = links::originals(&self.root, matching_files);
+        let mut paths = matching_files.to_vec();
+        if let Some(depth) = self.group_by_dir.filter(|_| !by_tokens) {
+            paths.sort_by_cached_key(|path| group(path, depth));
+        }
+
+        out.write_all(preamble(self.format).as_bytes())?;
+        let mut sink = Sink {
+            out,
+            layout: Layout::new(self.group_by_dir),
+            before: String::new(),
+            max_tokens: self.max_tokens,
+            total: 0,
+            streamed: Streamed::default(),
+        };
+        let mut spill = Spill::new(spill_limit.unwrap_or(0));
+        let mut pending = Vec::new();
+        self.observer.reading(paths.len());
+        for batch in self.batches(&paths, spill_limit.is_some()) {
+            if self.observer.cancelled() {
+                break;
+            }
+            // In the order they're written, the ones that can't fit in the rest of the budget
+            // aren't read through
+            let remaining = self
+                .max_tokens
+                .filter(|_| !by_tokens)
+                .map(|max_tokens| max_tokens.saturating_sub(sink.total));
+            let files: Vec<Option<(ProcessedFile, bool)>> = batch
+                .par_iter()
+                .map(|path| self.process(path, originals.get(path), remaining))
+                .collect();
+            for (path, file) in batch.iter().zip(files) {
+                let Some((file, read)) = file else {
+                    sink.streamed.dropped.push(path.clone());
+                    continue;
+                };
+                if !read {
+                    sink.streamed.unreadable.push(file.path.clone());
+                }
+                let (stats, formatted) = FileStats::split(file);
+                match by_tokens {
+                    true => pending.push((stats, spill.hold(formatted)?)),
+                    false => sink.write(stats, &formatted)?,
+                }
+            }
+        }
+        self.observer.done_reading();
+
+        // Same order as `bundle_files`: by tokens, then grouped
+        pending.sort_by_key(|(stats, _)| stats.token_count);
+        if self.reverse {
+            pending.reverse();
+        }
+        if let Some(depth) = self.group_by_dir {
+            pending.sort_by_cached_key(|(stats, _)| group(&stats.path, depth));
+        }
+        for (stats, held) in pending {
+            let formatted = spill.take(held)?;
+            sink.write(stats, &formatted)?;
+        }
+        sink.out.flush()?;
+        Ok(sink.streamed)
+    }
+
+    /// Splits `paths` into the batches read at once when streaming, a few files per thread and,
+    /// with a memory limit, files whose content and formatted text fit in it (half of it if
+    /// `spilling`).
+    #[cfg(feature = "fs")]
+    fn batches<'p>(&self, paths: &'p [PathBuf], spilling: bool) -> Vec<&'p [PathBuf]> {
+        let per_batch = rayon::current_num_threads() * FILES_PER_THREAD;
+        let Some(limit) = self.memory_limit else {
+            return paths.chunks(per_batch).collect();
+        };
+        let budget = if spilling { limit / 2 } else { limit };
+
+        let mut batches = Vec::new();
+        let (mut start, mut bytes) = (0, 0);
+        for (i, path) in paths.iter().enumerate() {
+            let size = std::fs::metadata(self.root.join(path)).map_or(0, |metadata| metadata.len());
+            if i > start && (i - start == per_batch || bytes + 2 * size > budget) {
+                batches.push(&paths[start..i]);
+                (start, bytes) = (i, 0);
+            }
+            bytes += 2 * size;
+        }
+        if start < paths.len() {
+            batches.push(&paths[start..]);
+        }
+        batches
+    }
+
+    /// `content` of the file at `path` after the transform, or why it failed, a panic included.
+    fn transformed(&self, path: &Path, content: String) -> Result<String, String> {
+        let Some(transform) = &self.transform else {
+            return Ok(content);
+        };
+        std::panic::catch_unwind(AssertUnwindSafe(|| transform(path, content))).unwrap_or_else(
+            |panic| {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                Err(format!("The transform panicked: {message}"))
+            },
+        )
+    }
+
+    /// Reads and formats the file at `path`, telling whether it could be read, or stubs it when
+    /// it's the same file as the `original` one. With a `budget`, it's `None` when the file turns
+    /// out to take more tokens than that, its size or the part read telling so without reading
+    /// the rest.
+    #[cfg(feature = "fs")]
+    fn process(
+        &self,
+        path: &Path,
+        original: Option<&PathBuf>,
+        budget: Option<usize>,
+    ) -> Option<(ProcessedFile, bool)> {
+        if let Some(original) = original {
+            self.observer.read(path, 0);
+            let stub = ProcessedFile::duplicate(path.to_owned(), original);
+            return match budget.is_some_and(|budget| stub.token_count > budget) {
+                true => None,
+                false => Some((stub, true)),
+            };
+        }
+        // The content fits if the header and it take up 4 characters per token of the budget at
+        // most. Every file is read when `strict`, to fail on the invalid ones left out too
+        let max_chars = match budget.filter(|_| !self.strict) {
+            None => None,
+            Some(budget) => {
+                let max_chars = (budget * 4).checked_sub(self.header_chars(path));
+                if max_chars.is_none_or(|max_chars| self.min_chars(path) > max_chars) {
+                    self.observer.read(path, 0);
+                    return None;
+                }
+                max_chars.filter(|_| self.transform.is_none())
+            }
+        };
+
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| Some((cache, cache::stamp(&self.root.join(path))?)));
+        if let Some(file) = cached.and_then(|(cache, stamp)| cache.get(path, stamp)) {
+            self.observer.read(path, file.content.len() as u64);
+            return Some((file, true));
+        }
+
+        let start = Instant::now();
+        let content = match self.timeout {
+            None => read_file(&self.root, path, self.strict, max_chars),
+            Some(timeout) => {
+                let (root, owned, strict) = (self.root.clone(), path.to_owned(), self.strict);
+                deadline::run(timeout, move || read_file(&root, &owned, strict, max_chars))
+                    .unwrap_or_else(|| Err(format!("Timed out reading the file after {timeout:?}")))
+            }
+        };
+        let content = match content {
+            Ok(Some(read)) => Ok(read),
+            Ok(None) => {
+                log::debug!(path:% = path.display(); "Left the rest of the file unread, past the budget");
+                self.observer.read(path, 0);
+                return None;
+            }
+            Err(e) => Err(e),
+        };
+        let read_at = Instant::now();
+        let content = content.and_then(|(content, hash)| match self.transform {
+            None => Ok((content, hash)),
+            Some(_) => self.transformed(path, content).map(|content| {
+                let hash = blake3::hex(content.as_bytes());
+                (content, hash)
+            }),
+        });
+        let read = content.is_ok();
+        let (content, hash) = content.unwrap_or_else(|e| {
+            self.observer.failed(path, &e);
+            (String::new(), blake3::hex(b""))
+        });
+        let formatted = self.formatted(path, &content);
+        let formatted_at = Instant::now();
+        let file = ProcessedFile::hashed(path.to_owned(), content, hash, formatted);
+        let timings = FileTimings {
+            read: read_at - start,
+            transform: formatted_at - read_at,
+            tokenize: formatted_at.elapsed(),
+        };
+
+        if let Some((cache, stamp)) = cached.filter(|_| read) {
+            cache.insert(stamp, &file);
+        }
+        self.observer.read(path, file.content.len() as u64);
+        self.observer.processed(path, &timings);
+        Some((file, read))
+    }
+}
+
+/// What [`Bundler::stream`] wrote
+#[derive(Default)]
+pub struct Streamed {
+    pub files: Vec<FileStats>,
+    /// Files that matched but were left out to stay within the token budget
+    pub dropped: Vec<PathBuf>,
+    /// Files that couldn't be read, bundled with empty content
+    pub unreadable: Vec<PathBuf>,
+}
+
+/// The counts of a streamed file, whose content is gone once written
+pub struct FileStats {
+    pub path: PathBuf,
+    pub line_count: usize,
+    pub word_count: usize,
+    pub char_count: usize,
+    pub token_count: usize,
+}
+
+#[cfg(feature = "fs")]
+impl FileStats {
+    /// The counts of `file` and its formatted text.
+    fn split(file: ProcessedFile) -> (FileStats, String) {
+        let stats = FileStats {
+            path: file.path,
+            line_count: file.line_count,
+            word_count: file.word_count,
+            char_count: file.char_count,
+            token_count: file.token_count,
+        };
+        (stats, file.formatted)
+    }
+}
+
+/// Where [`Bundler::stream`] writes the files that fit in the budget
+#[cfg(feature = "fs")]
+struct Sink<'a> {
+    out: &'a mut dyn Write,
+    layout: Layout,
+    /// The separator and group header going before the file written, reused between the files
+    before: String,
+    max_tokens: Option<usize>,
+    total: usize,
+    streamed: Streamed,
+}
+
+#[cfg(feature = "fs")]
+impl Sink<'_> {
+    fn write(&mut self, stats: FileStats, formatted: &str) -> std::io::Result<()> {
+        if let Some(max_tokens) = self.max_tokens {
+            if self.total + stats.token_count > max_tokens {
+                self.streamed.dropped.push(stats.path);
+                return Ok(());
+            }
+            self.total += stats.token_count;
+        }
+        self.before.clear();
+        self.layout.push_before(&stats.path, &mut self.before);
+        self.out.write_all(self.before.as_bytes())?;
+        self.out.write_all(formatted.as_bytes())?;
+        self.streamed.files.push(stats);
+        Ok(())
+    }
+}
+
+/// Time spent on a file by the thread processing it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileTimings {
+    /// Reading it from disk and checking it's UTF-8
+    pub read: Duration,
+    /// Transforming it, computing its checksum and laying it out in the bundle format
+    pub transform: Duration,
+    /// Counting its lines, words, characters and tokens
+    pub tokenize: Duration,
+}
+
+impl FileTimings {
+    pub fn total(&self) -> Duration {
+        self.read + self.transform + self.tokenize
+    }
+}
+
+/// Makes a [`Collector`] for each thread of the walk.
+#[cfg(feature = "fs")]
+struct Collectors<'a> {
+    bundler: &'a Bundler,
+    found: &'a Mutex<Found>,
+    /// The first error met, which stops the walk
+    error: &'a Mutex<Option<ignore::Error>>,
+}
+
+/// What the walk found, relative to the root
+#[cfg(feature = "fs")]
+#[derive(Default)]
+struct Found {
+    files: Vec<PathBuf>,
+    /// The named pipes, sockets and devices, with what they are
+    specials: Vec<(PathBuf, &'static str)>,
+    /// The paths that took longer than the timeout to look up, skipped with what's under them
+    timed_out: Vec<PathBuf>,
+}
+
+#[cfg(feature = "fs")]
+impl Found {
+    fn append(&mut self, other: &mut Found) {
+        self.files.append(&mut other.files);
+        self.specials.append(&mut other.specials);
+        self.timed_out.append(&mut other.timed_out);
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<'s, 'a: 's> ParallelVisitorBuilder<'s> for Collectors<'a> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(Collector {
+            bundler: self.bundler,
+            found: Found::default(),
+            all_found: self.found,
+            error: self.error,
+        })
+    }
+}
+
+/// Gathers the files found by a thread of the walk, adding them to the shared list once the
+/// thread is done, so the threads don't contend for it on every file.
+#[cfg(feature = "fs")]
+struct Collector<'a> {
+    bundler: &'a Bundler,
+    found: Found,
+    all_found: &'a Mutex<Found>,
+    error: &'a Mutex<Option<ignore::Error>>,
+}
+
+#[cfg(feature = "fs")]
+impl ParallelVisitor for Collector<'_> {
+    fn visit(&mut self, entry: std::result::Result<DirEntry, ignore::Error>) -> WalkState {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                self.error
+                    .lock()
+                    .expect("walker threads don't panic")
+                    .get_or_insert(e);
+                return WalkState::Quit;
+            }
+        };
+        let path = entry
+            .path()
+            .strip_prefix(&self.bundler.root)
+            .unwrap_or(entry.path());
+        // Following symlinks, like the reads
+        let metadata = match self.bundler.timeout {
+            None => std::fs::metadata(entry.path()),
+            Some(timeout) => {
+                let owned = entry.path().to_owned();
+                match deadline::run(timeout, move || std::fs::metadata(owned)) {
+                    Some(metadata) => metadata,
+                    // Not descending into a directory that hangs
+                    None => {
+                        self.found.timed_out.push(path.to_owned());
+                        return WalkState::Skip;
+                    }
+                }
+            }
+        };
+        let Ok(metadata) = metadata else {
+            return WalkState::Continue;
+        };
+        if !metadata.is_file() {
+            // Reading them would block, or never end
+            if let Some(kind) = special_kind(&metadata.file_type()) {
+                self.found.specials.push((path.to_owned(), kind));
+            }
+            return WalkState::Continue;
+        }
+        self.bundler.observer.found(path);
+        self.found.files.push(path.to_owned());
+        WalkState::Continue
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Drop for Collector<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut found) = self.all_found.lock() {
+            found.append(&mut self.found);
+        }
+    }
+}
+
+/// Content of the file at `path` under `root` with its BLAKE3, or why it couldn't be read.
+/// Invalid UTF-8 is replaced, unless `strict` where it counts as a read failure. `None` if the
+/// file has more than `max_chars` characters, counted as it's read so the rest isn't.
+///
+/// The bytes are hashed as they're read, the content being hashed again only when decoding
+/// changed it.
+#[cfg(feature = "fs")]
+fn read_file(
+    root: &Path,
+    path: &Path,
+    strict: bool,
+    max_chars: Option<usize>,
+) -> Result<Option<(String, String)>, String> {
+    log::debug!(path:% = path.display(); "Reading file");
+
+    // A file replaced by a named pipe since the walk would block the open
+    if let Some(kind) = std::fs::metadata(root.join(path))
+        .ok()
+        .and_then(|metadata| special_kind(&metadata.file_type()))
+    {
+        return Err(format!("Skipping the {kind}, which isn't a regular file"));
+    }
+
+    // Large files are copied straight from the mapping into the content. One that changed while
+    // mapped is read again from the start, with a hash and count of its own
+    if let Some(content) = mmap::with_mapped(&root.join(path), |bytes| {
+        let mut progress = Progress::default();
+        match bytes
+            .chunks(READ_CHUNK)
+            .any(|chunk| progress.longer(chunk, max_chars))
+        {
+            true => Ok(None),
+            false => decode(Cow::Borrowed(bytes), strict)
+                .map(|decoded| Some(decoded.hashed(&progress.hasher))),
+        }
+    }) {
+        return content;
+    }
+    let mut progress = Progress::default();
+    let failed = |e| format!("Failed to read the file: {e}");
+    let mut file = std::fs::File::open(root.join(path)).map_err(failed)?;
+    let mut bytes = Vec::new();
+    loop {
+        let start = bytes.len();
+        bytes.resize(start + READ_CHUNK, 0);
+        match file.read(&mut bytes[start..]) {
+            Ok(0) => {
+                bytes.truncate(start);
+                break;
+            }
+            Ok(read) => {
+                bytes.truncate(start + read);
+                if progress.longer(&bytes[start..], max_chars) {
+                    return Ok(None);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => bytes.truncate(start),
+            Err(e) => return Err(failed(e)),
+        }
+    }
+    decode(Cow::Owned(bytes), strict).map(|decoded| Some(decoded.hashed(&progress.hasher)))
+}
+
+/// The hash and character count of the chunks of a file read so far.
+#[cfg(feature = "fs")]
+#[derive(Default)]
+struct Progress {
+    hasher: blake3::Hasher,
+    counter: tokens::Counter,
+}
+
+#[cfg(feature = "fs")]
+impl Progress {
+    /// Adds `chunk`, returning whether the file is now longer than `max_chars`.
+    fn longer(&mut self, chunk: &[u8], max_chars: Option<usize>) -> bool {
+        self.hasher.update(chunk);
+        max_chars.is_some_and(|max_chars| longer_than(&mut self.counter, chunk, max_chars))
+    }
+}
+
+/// Whether the characters `counter` counted with `chunk` are more than `max_chars`, the last one
+/// possibly being cut between two chunks.
+#[cfg(feature = "fs")]
+fn longer_than(counter: &mut tokens::Counter, chunk: &[u8], max_chars: usize) -> bool {
+    counter.add(chunk);
+    counter.count().saturating_sub(1) > max_chars
+}
+
+/// What the file of type `file_type` is if it's a named pipe, a socket or a device.
+#[cfg(all(feature = "fs", unix))]
+fn special_kind(file_type: &std::fs::FileType) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        Some("named pipe")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        Some("device file")
+    } else {
+        None
+    }
+}
+
+#[cfg(all(feature = "fs", not(unix)))]
+fn special_kind(_file_type: &std::fs::FileType) -> Option<&'static str> {
+    None
+}
+
+/// The text of a file made of `bytes`: as is when they're valid UTF-8, which is checked in place
+/// so read bytes aren't copied, transcoded when they start with a UTF-16 byte order mark, or with
+/// the invalid sequences replaced unless `strict`, where they're an error.
+#[cfg(feature = "fs")]
+fn decode(bytes: Cow<'_, [u8]>, strict: bool) -> Result<Decoded, String> {
+    let changed = |text| Decoded {
+        text,
+        verbatim: false,
+    };
+    if let Some(text) = decode_utf16(&bytes, strict) {
+        return text.map(changed);
+    }
+    let invalid = |e| format!("The file isn't valid UTF-8: {e}");
+    let verbatim = |text| Decoded {
+        text,
+        verbatim: true,
+    };
+    match bytes {
+        Cow::Owned(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => Ok(verbatim(text)),
+            Err(e) if strict => Err(invalid(e.utf8_error())),
+            Err(e) => Ok(changed(String::from_utf8_lossy(e.as_bytes()).into_owned())),
+        },
+        Cow::Borrowed(bytes) => match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(verbatim(text.to_owned())),
+            Err(e) if strict => Err(invalid(e)),
+            Err(_) => Ok(changed(String::from_utf8_lossy(bytes).into_owned())),
+        },
+    }
+}
+
+/// The text of a file made of `bytes` that weren't read from disk, such as the blobs of a git
+/// revision, decoded like the files read: transcoded from UTF-16, or with the invalid UTF-8
+/// replaced unless `strict`, where it's an error.
+#[cfg(feature = "fs")]
+pub fn decode_file(bytes: Vec<u8>, strict: bool) -> Result<String, String> {
+    decode(Cow::Owned(bytes), strict).map(|decoded| decoded.text)
+}
+
+/// The text of a file, and whether it's made of the very bytes read
+#[cfg(feature = "fs")]
+struct Decoded {
+    text: String,
+    verbatim: bool,
+}
+
+#[cfg(feature = "fs")]
+impl Decoded {
+    /// The text with its BLAKE3: the one of the bytes `hasher` was given as they were read,
+    /// unless they were decoded into something else.
+    fn hashed(self, hasher: &blake3::Hasher) -> (String, String) {
+        let hash = match self.verbatim {
+            true => hasher.hex(),
+            false => blake3::hex(self.text.as_bytes()),
+        };
+        (self.text, hash)
+    }
+}
+
+/// `bytes` transcoded from UTF-16 without their byte order mark, `None` if they don't start with
+/// one, which can't begin valid UTF-8.
+#[cfg(feature = "fs")]
+fn decode_utf16(bytes: &[u8], strict: bool) -> Option<Result<String, String>> {
+    let unit: fn([u8; 2]) -> u16 = match bytes {
+        [0xFF, 0xFE, ..] => u16::from_le_bytes,
+        [0xFE, 0xFF, ..] => u16::from_be_bytes,
+        _ => return None,
+    };
+    let invalid =
+        |e: &dyn std::fmt::Display| Some(Err(format!("The file isn't valid UTF-16: {e}")));
+    let units = bytes[2..].chunks_exact(2);
+    if strict && !units.remainder().is_empty() {
+        return invalid(&"odd number of bytes");
+    }
+    // A lone trailing byte is replaced like an unpaired surrogate
+    let trailing = (!units.remainder().is_empty()).then_some(0xD800);
+    let units = units.map(|pair| unit([pair[0], pair[1]])).chain(trailing);
+
+    let mut text = String::with_capacity(bytes.len());
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => text.push(c),
+            Err(e) if strict => return invalid(&e),
+            Err(_) => text.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+    Some(Ok(text))
+}
@@ -0,0 +1,64 @@
+//! The files read by previous bundles, reused while they don't change.
+
+use crate::format::ProcessedFile;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The modification time and size of a file, which change when it's written to
+pub(crate) type Stamp = (SystemTime, u64);
+
+/// Files read and formatted by the bundlers it's given to, keyed by their path, modification
+/// time and size, so bundling again only reads the files that changed. The bundlers sharing a
+/// cache must have the same root and format the files the same way.
+#[derive(Default)]
+pub struct ReadCache {
+    files: Mutex<HashMap<PathBuf, (Stamp, ProcessedFile)>>,
+}
+
+impl ReadCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of files in the cache.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The file at `path` if it was read when it had the given `stamp`.
+    pub(crate) fn get(&self, path: &Path, stamp: Stamp) -> Option<ProcessedFile> {
+        match self.lock().get(path) {
+            Some((cached, file)) if *cached == stamp => Some(file.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, stamp: Stamp, file: &ProcessedFile) {
+        self.lock().insert(file.path.clone(), (stamp, file.clone()));
+    }
+
+    /// Forgets the files that aren't in `paths`, such as the deleted ones.
+    pub(crate) fn retain(&self, paths: &[PathBuf]) {
+        let paths: HashSet<&PathBuf> = paths.iter().collect();
+        self.lock().retain(|path, _| paths.contains(path));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, (Stamp, ProcessedFile)>> {
+        // The map stays consistent even if a reading thread panicked
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The stamp of the file at `path`, `None` if it can't be read. It's taken before the file is
+/// read, so a file changing meanwhile is stored under a stamp it no longer has, and is read again
+/// next time.
+pub(crate) fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
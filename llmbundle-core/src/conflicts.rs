@@ -0,0 +1,40 @@
+//! Files left mid-merge, with the `<<<<<<<`, `=======` and `>>>>>>>` markers of unresolved
+//! conflicts in them: bundled without a word, the model takes both sides for the code.
+
+/// What becomes of the files with unresolved merge conflicts
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Conflicts {
+    /// Bundled with a line in their header telling they have conflicts, and a warning
+    #[default]
+    Flag,
+    /// Left out, replaced with a one-line note, with a warning
+    Exclude,
+    /// Bundled as they are, like files whose markers aren't conflicts
+    Ignore,
+}
+
+/// Number of conflicts in `content`: the `<<<<<<<` lines followed by a `=======` line, then by a
+/// `>>>>>>>` line.
+pub(crate) fn count(content: &str) -> usize {
+    if !content.contains("<<<<<<<") {
+        return 0;
+    }
+    let marker = |line: &str, marker: &str| {
+        line.strip_prefix(marker)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\r']))
+    };
+    // Where in the conflict the lines are: the ours side, then the theirs side
+    let (mut ours, mut theirs, mut conflicts) = (false, false, 0);
+    for line in content.lines() {
+        if marker(line, "<<<<<<<") {
+            (ours, theirs) = (true, false);
+        } else if ours && marker(line, "=======") {
+            (ours, theirs) = (false, true);
+        } else if theirs && marker(line, ">>>>>>>") {
+            theirs = false;
+            conflicts += 1;
+        }
+    }
+    conflicts
+}
@@ -0,0 +1,52 @@
+//! Deadlines on the filesystem calls that can hang, like the ones on a stale network mount or a
+//! stuck FUSE filesystem. They can't be interrupted, so they're made on a helper thread and given
+//! up on past the deadline, the helper being left to them and replaced.
+
+use std::cell::RefCell;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+thread_local! {
+    /// The helper of each thread, started on its first call
+    static HELPER: RefCell<Option<Sender<Job>>> = const { RefCell::new(None) };
+}
+
+/// What `f` returns, or `None` if it took longer than `timeout`.
+pub(crate) fn run<T: Send + 'static>(
+    timeout: Duration,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let (done, result) = mpsc::sync_channel(1);
+    let job: Job = Box::new(move || {
+        let _ = done.send(f());
+    });
+    HELPER.with_borrow_mut(|helper| {
+        let jobs = helper.get_or_insert_with(start);
+        if let Err(mpsc::SendError(job)) = jobs.send(job) {
+            // The helper panicked on a previous job
+            *jobs = start();
+            let _ = jobs.send(job);
+        }
+    });
+    match result.recv_timeout(timeout) {
+        Ok(value) => Some(value),
+        Err(RecvTimeoutError::Timeout) => {
+            // Still stuck in `f`, it exits once done, finding its jobs gone
+            HELPER.with_borrow_mut(Option::take);
+            None
+        }
+        Err(RecvTimeoutError::Disconnected) => None,
+    }
+}
+
+fn start() -> Sender<Job> {
+    let (jobs, received) = mpsc::channel::<Job>();
+    std::thread::spawn(move || {
+        for job in received {
+            job();
+        }
+    });
+    jobs
+}
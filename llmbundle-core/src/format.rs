@@ -0,0 +1,444 @@
+//! How the files are laid out in a bundle, and parsing bundles back into files.
+
+use crate::{blake3, paths, sha256, tokens};
+use std::path::{Path, PathBuf};
+
+/// Markers of a file in the bundle: its name, then its content between `BEGIN` and `END`
+const NAME: &str = "[file name]: ";
+const BEGIN: &str = "[file content begin]\n";
+const END: &str = "\n[file content end]";
+
+/// Instructions put before the files with `--format patchable`, so the model answers with diffs
+/// `apply` can patch the files with
+const PATCHABLE_INSTRUCTIONS: &str = r#"[instructions]
+When proposing changes to the files below, answer with unified diffs (like `diff -u` or `git diff`
+output) instead of whole files, one per changed file:
+--- a/<file name>
++++ b/<file name>
+@@ -<line>,<count> +<line>,<count> @@
+Keep the file names exactly as given and include 3 lines of unchanged context around each change.
+[instructions end]
+"#;
+
+/// Line put after the file name with `--checksums`, so truncated or mangled pastes can be detected
+const CHECKSUM: &str = "[file sha256]: ";
+
+/// Start of the other lines a header can have after the name, like the last commit of the file
+const HEADER_LINE: &str = "[file ";
+
+/// Replaces a file that didn't change since the last bundle with `--incremental`
+const UNCHANGED_STUB: &str = "[file name]: {file_name}\n[unchanged since last bundle]\n";
+
+/// Line put in the header of a file with unresolved merge conflicts
+const CONFLICTS: &str = "[file has merge conflicts]: ";
+
+/// Replaces a file with unresolved merge conflicts with `--conflicts exclude`
+const CONFLICTED_STUB: &str =
+    "[file name]: {file_name}\n[left out, it has unresolved merge conflicts]\n";
+
+/// Replaces a file that is a hard link or symlink to one bundled before it
+const DUPLICATE_STUB: &str = "[file name]: {file_name}\n[same file as {original}]\n";
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Format {
+    /// Each file between `[file content begin]` and `[file content end]` markers
+    #[default]
+    Default,
+    /// The default format preceded by instructions asking the model to answer with unified
+    /// diffs, which `apply` understands
+    Patchable,
+}
+
+#[derive(Clone)]
+pub struct ProcessedFile {
+    /// Path relative to the root
+    pub path: PathBuf,
+    /// Raw file content, before formatting
+    pub content: String,
+    /// BLAKE3 of the content as hex, hashed once read to tell the changes between bundles
+    pub hash: String,
+    /// The file as it appears in the bundle
+    pub formatted: String,
+    pub line_count: usize,
+    pub word_count: usize,
+    pub char_count: usize,
+    pub token_count: usize,
+}
+
+pub struct Bundle {
+    pub text: String,
+    pub files: Vec<ProcessedFile>,
+    /// Files that matched but were left out to stay within the token budget
+    pub dropped: Vec<PathBuf>,
+    pub max_tokens: Option<usize>,
+    pub format: Format,
+    /// Number of leading directories the files are grouped by with `--group-by-dir`
+    pub group_by_dir: Option<usize>,
+    /// Files that couldn't be read, bundled with empty content
+    pub unreadable: Vec<PathBuf>,
+}
+
+impl ProcessedFile {
+    pub fn new(path: PathBuf, content: String) -> Self {
+        let formatted = format(&path, &content, None, None);
+        Self::with_formatted(path, content, formatted)
+    }
+
+    /// Like [`ProcessedFile::new`], with the SHA-256 of the content in the header of the file.
+    pub fn with_checksum(path: PathBuf, content: String) -> Self {
+        let checksum = sha256::hex(content.as_bytes());
+        let formatted = format(&path, &content, Some(&checksum), None);
+        Self::with_formatted(path, content, formatted)
+    }
+
+    /// The stub of the file at `path` standing for the same file bundled at `original`, without
+    /// content.
+    pub fn duplicate(path: PathBuf, original: &Path) -> Self {
+        let stub = DUPLICATE_STUB
+            .replace("{file_name}", &paths::display(&path))
+            .replace("{original}", &paths::display(original));
+        Self::with_formatted(path, String::new(), stub)
+    }
+
+    /// A file appearing as `formatted` in the bundle, counting it.
+    pub(crate) fn with_formatted(path: PathBuf, content: String, formatted: String) -> Self {
+        let hash = blake3::hex(content.as_bytes());
+        Self::hashed(path, content, hash, formatted)
+    }
+
+    /// Like [`ProcessedFile::with_formatted`], with the `hash` of the content taken as it was
+    /// read.
+    pub(crate) fn hashed(path: PathBuf, content: String, hash: String, formatted: String) -> Self {
+        let mut file = ProcessedFile {
+            path,
+            hash,
+            content,
+            formatted: String::new(),
+            line_count: 0,
+            word_count: 0,
+            char_count: 0,
+            token_count: 0,
+        };
+        file.set_formatted(formatted);
+        file
+    }
+
+    /// Changes how the file appears in the bundle, updating the counts.
+    fn set_formatted(&mut self, formatted: String) {
+        let counts = tokens::count(&formatted);
+        self.line_count = counts.lines;
+        self.word_count = counts.words;
+        self.char_count = counts.chars;
+        self.token_count = counts.tokens();
+        self.formatted = formatted;
+    }
+}
+
+impl Bundle {
+    /// Rebuilds a bundle from previously rendered text, keeping the text byte-identical.
+    pub fn from_text(text: String) -> Self {
+        #[cfg(feature = "fs")]
+        let files = {
+            use rayon::prelude::*;
+            parse(&text)
+                .into_par_iter()
+                .map(|(path, content)| ProcessedFile::new(path, content))
+                .collect()
+        };
+        #[cfg(not(feature = "fs"))]
+        let files = parse(&text)
+            .into_iter()
+            .map(|(path, content)| ProcessedFile::new(path, content))
+            .collect();
+        Bundle {
+            text,
+            files,
+            dropped: Vec::new(),
+            max_tokens: None,
+            format: Format::Default,
+            group_by_dir: None,
+            unreadable: Vec::new(),
+        }
+    }
+
+    /// Replaces the files for which `unchanged` is true with a one-line stub, keeping their place
+    /// in the bundle. Returns the number of files replaced.
+    pub fn stub_unchanged(&mut self, mut unchanged: impl FnMut(&ProcessedFile) -> bool) -> usize {
+        let mut stubbed = 0;
+        for file in &mut self.files {
+            if unchanged(file) {
+                let stub = UNCHANGED_STUB.replace("{file_name}", &paths::display(&file.path));
+                file.set_formatted(stub);
+                stubbed += 1;
+            }
+        }
+        self.text = render(&self.files, self.format, self.group_by_dir);
+        stubbed
+    }
+}
+
+/// The header line of a file with `conflicts` unresolved merge conflicts.
+pub(crate) fn conflicts_header(conflicts: usize) -> String {
+    let plural = if conflicts == 1 { "" } else { "s" };
+    format!("{CONFLICTS}{conflicts} unresolved conflict{plural}, the content is mid-merge")
+}
+
+/// The stub replacing the file at `path`, left out for its merge conflicts.
+pub(crate) fn conflicted_stub(path: &Path) -> String {
+    CONFLICTED_STUB.replace("{file_name}", &paths::display(path))
+}
+
+/// The file at `path` as it appears in the bundle, with the `header` line after its name and
+/// checksum if any, written in a single buffer sized upfront.
+pub(crate) fn format(
+    path: &Path,
+    content: &str,
+    checksum: Option<&str>,
+    header: Option<&str>,
+) -> String {
+    let name = paths::display(path);
+    let checksum_len = checksum.map_or(0, |checksum| CHECKSUM.len() + checksum.len() + 1);
+    let header_len = header.map_or(0, |header| header.len() + 1);
+    let mut text = String::with_capacity(
+        NAME.len()
+            + name.len()
+            + 1
+            + checksum_len
+            + header_len
+            + BEGIN.len()
+            + content.len()
+            + END.len()
+            + 1,
+    );
+    text.push_str(NAME);
+    text.push_str(&name);
+    text.push('\n');
+    if let Some(checksum) = checksum {
+        text.push_str(CHECKSUM);
+        text.push_str(checksum);
+        text.push('\n');
+    }
+    if let Some(header) = header {
+        text.push_str(header);
+        text.push('\n');
+    }
+    text.push_str(BEGIN);
+    text.push_str(content);
+    text.push_str(END);
+    text.push('\n');
+    text
+}
+
+pub(crate) fn render(
+    files: &[ProcessedFile],
+    format: Format,
+    group_by_dir: Option<usize>,
+) -> String {
+    let mut text = preamble(format);
+    // The separators between the files, the group headers being rare enough to grow into
+    text.reserve(files.iter().map(|file| file.formatted.len() + 1).sum());
+    let mut layout = Layout::new(group_by_dir);
+    for file in files {
+        layout.push_before(&file.path, &mut text);
+        text.push_str(&file.formatted);
+    }
+    text
+}
+
+/// Text the bundle starts with.
+pub(crate) fn preamble(format: Format) -> String {
+    match format {
+        Format::Default => String::new(),
+        Format::Patchable => format!("{PATCHABLE_INSTRUCTIONS}\n"),
+    }
+}
+
+/// Separators and group headers put between the files, so bundles rendered at once and streamed
+/// are identical.
+pub(crate) struct Layout {
+    group_by_dir: Option<usize>,
+    current_group: Option<String>,
+    first: bool,
+}
+
+impl Layout {
+    pub(crate) fn new(group_by_dir: Option<usize>) -> Self {
+        Layout {
+            group_by_dir,
+            current_group: None,
+            first: true,
+        }
+    }
+
+    /// Adds the text going before the file at `path` to `text`.
+    pub(crate) fn push_before(&mut self, path: &Path, text: &mut String) {
+        if !std::mem::take(&mut self.first) {
+            text.push('\n');
+        }
+        if let Some(depth) = self.group_by_dir {
+            let group = group(path, depth);
+            if self.current_group.as_ref() != Some(&group) {
+                let name = if group.is_empty() { "." } else { &group };
+                text.push_str("== ");
+                text.push_str(name);
+                text.push_str(" ==\n\n");
+                self.current_group = Some(group);
+            }
+        }
+    }
+}
+
+/// The first `depth` directories of `path`, empty for the files directly under the root.
+pub(crate) fn group(path: &Path, depth: usize) -> String {
+    let path = paths::normalized(path);
+    let dirs: Vec<_> = path
+        .parent()
+        .into_iter()
+        .flat_map(|parent| parent.components())
+        .take(depth)
+        .map(|dir| dir.as_os_str().to_string_lossy())
+        .collect();
+    dirs.join("/")
+}
+
+/// Parses text in the bundle format back into `(path, content)` pairs, ignoring anything outside
+/// the file blocks (such as the prose around them in an LLM response).
+pub fn parse(text: &str) -> Vec<(PathBuf, String)> {
+    blocks(text)
+        .into_iter()
+        .filter_map(|block| Some((block.path, block.content?.to_string())))
+        .collect()
+}
+
+/// Paths of the files in `text` whose content doesn't match the checksum in their header, or
+/// whose content was cut off.
+pub fn corrupted(text: &str) -> Vec<PathBuf> {
+    blocks(text)
+        .into_iter()
+        .filter(|block| match (block.checksum, block.content) {
+            (Some(checksum), Some(content)) => sha256::hex(content.as_bytes()) != checksum,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+        .map(|block| block.path)
+        .collect()
+}
+
+struct Block<'a> {
+    path: PathBuf,
+    checksum: Option<&'a str>,
+    /// `None` if the end marker is missing
+    content: Option<&'a str>,
+}
+
+fn blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(NAME) {
+        rest = &rest[start + NAME.len()..];
+        let Some(name_end) = rest.find('\n') else {
+            break;
+        };
+        let path = PathBuf::from(rest[..name_end].trim());
+        let mut body = &rest[name_end + 1..];
+        let mut checksum = None;
+        if let Some(line) = body.strip_prefix(CHECKSUM) {
+            let Some(end) = line.find('\n') else {
+                break;
+            };
+            checksum = Some(line[..end].trim());
+            body = &line[end + 1..];
+        }
+        // The other lines of the header, which don't change the content
+        while body.starts_with(HEADER_LINE) && !body.starts_with(NAME) && !body.starts_with(BEGIN) {
+            let Some(end) = body.find('\n') else {
+                break;
+            };
+            body = &body[end + 1..];
+        }
+        let Some(body) = body.strip_prefix(BEGIN) else {
+            continue;
+        };
+        let Some(content_end) = body.find(END) else {
+            blocks.push(Block {
+                path,
+                checksum,
+                content: None,
+            });
+            break;
+        };
+        blocks.push(Block {
+            path,
+            checksum,
+            content: Some(&body[..content_end]),
+        });
+        rest = &body[content_end + END.len()..];
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatted_files_parse_back() {
+        let files = [
+            ("src/main.rs", "fn main() {}\n"),
+            ("empty.txt", ""),
+            ("no newline.txt", "last line"),
+            (
+                "nested/notes.md",
+                "[file name]: not a header\n\nblank lines\n\n",
+            ),
+        ];
+        let text: String = files
+            .iter()
+            .map(|(path, content)| format(Path::new(path), content, None, None))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parsed = parse(&text);
+        let expected: Vec<_> = files
+            .iter()
+            .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+            .collect();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn headers_are_skipped_when_parsing() {
+        let content = "fn a() {}\n";
+        let checksum = sha256::hex(content.as_bytes());
+        let text = format(
+            Path::new("a.rs"),
+            content,
+            Some(&checksum),
+            Some("[file last commit]: abc123 Fix a"),
+        );
+        assert_eq!(parse(&text), [(PathBuf::from("a.rs"), content.to_string())]);
+        assert!(corrupted(&text).is_empty());
+    }
+
+    #[test]
+    fn altered_and_truncated_files_are_corrupted() {
+        let content = "fn a() {}\n";
+        let checksum = sha256::hex(content.as_bytes());
+        let text = format(Path::new("a.rs"), content, Some(&checksum), None);
+        let altered = text.replace("fn a", "fn b");
+        assert_eq!(corrupted(&altered), [PathBuf::from("a.rs")]);
+        let truncated = &text[..text.len() - END.len()];
+        assert_eq!(corrupted(truncated), [PathBuf::from("a.rs")]);
+        assert!(parse(truncated).is_empty());
+    }
+
+    #[test]
+    fn prose_around_the_files_is_ignored() {
+        let file = format(Path::new("a.rs"), "fn a() {}\n", None, None);
+        let response = format!("Here's the updated file:\n\n{file}\nLet me know if it works.\n");
+        assert_eq!(
+            parse(&response),
+            [(PathBuf::from("a.rs"), "fn a() {}\n".to_string())]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! The bundling logic of llmbundle, for editor plugins and other tools embedding it instead of
+//! running the command: finding the files under a root that aren't ignored and match glob
+//! patterns, reading and formatting them into a single text and keeping them within a token
+//! budget.
+//!
+//! ```no_run
+//! # #[cfg(feature = "fs")] {
+//! use llmbundle_core::{Bundler, Format};
+//!
+//! let bundle = Bundler::new(".")
+//!     .patterns(["src/**/*.rs", "!src/generated/**"])
+//!     .format(Format::Patchable)
+//!     .max_tokens(Some(50_000))
+//!     .bundle()?;
+//! println!("{}", bundle.text);
+//! # }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Without the default `fs` feature, the crate doesn't walk or read the filesystem and builds
+//! for `wasm32-unknown-unknown`, [`Bundler::bundle_virtual`] bundling files given in memory:
+//!
+//! ```
+//! use llmbundle_core::Bundler;
+//! use std::path::PathBuf;
+//!
+//! let files = [
+//!     (PathBuf::from("src/main.rs"), "fn main() {}".to_string()),
+//!     (PathBuf::from("README.md"), "# Demo".to_string()),
+//! ];
+//! let bundle = Bundler::new(".").patterns(["*.rs"]).bundle_virtual(files)?;
+//! assert_eq!(bundle.files.len(), 1);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod blake3;
+mod bundler;
+#[cfg(feature = "fs")]
+mod cache;
+mod conflicts;
+#[cfg(feature = "fs")]
+mod deadline;
+mod format;
+#[cfg(feature = "fs")]
+pub mod links;
+#[cfg(feature = "fs")]
+pub mod mmap;
+mod nfc;
+pub mod paths;
+mod select;
+pub mod sha256;
+#[cfg(feature = "fs")]
+mod spill;
+pub mod tokens;
+
+#[cfg(feature = "fs")]
+pub use bundler::decode_file;
+pub use bundler::{Bundler, FileStats, FileTimings, Observer, Streamed};
+#[cfg(feature = "fs")]
+pub use cache::ReadCache;
+pub use conflicts::Conflicts;
+pub use format::{corrupted, parse, Bundle, Format, ProcessedFile};
+pub use select::{build_glob_sets, resolve_pattern, Sort};
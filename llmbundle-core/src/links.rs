@@ -0,0 +1,45 @@
+//! Files reached through several paths, by hard links or symlinks, which are bundled once: the
+//! first path in order gets the content and the others a stub naming it.
+//!
+//! Files are told apart by their device and inode, so this only applies on Unix.
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The paths of `paths` (relative to `root`, in bundle order) that are the same file as one
+/// before them, mapped to that first one.
+pub fn originals(root: &Path, paths: &[PathBuf]) -> HashMap<PathBuf, PathBuf> {
+    let ids: Vec<Option<(u64, u64)>> = paths.par_iter().map(|path| id(&root.join(path))).collect();
+    let mut first: HashMap<(u64, u64), &PathBuf> = HashMap::new();
+    let mut originals = HashMap::new();
+    for (path, id) in paths.iter().zip(ids) {
+        let Some(id) = id else {
+            continue;
+        };
+        match first.get(&id) {
+            Some(&original) => {
+                log::debug!(path:% = path.display(), original:% = original.display(); "Same file as another");
+                originals.insert(path.clone(), original.clone());
+            }
+            None => {
+                first.insert(id, path);
+            }
+        }
+    }
+    originals
+}
+
+/// The device and inode of the file at `path`, following symlinks.
+#[cfg(unix)]
+fn id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
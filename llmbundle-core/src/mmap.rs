@@ -0,0 +1,150 @@
+//! Memory-mapped reads of large files, which are copied once from the page cache instead of
+//! being read into a buffer first.
+//!
+//! A file truncated by another process while mapped would crash the process with `SIGBUS` when
+//! the missing pages are touched, like with any mapping, so only large files are mapped, where
+//! the saved copy matters.
+
+use std::fs::File;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Files at least this big are mapped instead of read
+pub const THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// Calls `f` with the contents of the file at `path` mapped in memory. Returns `None`, for the
+/// file to be read normally, if it's smaller than [`THRESHOLD`], can't be mapped, or changed
+/// while mapped since `f` may then have seen a mix of the old and new contents.
+pub fn with_mapped<T>(path: &Path, f: impl FnOnce(&[u8]) -> T) -> Option<T> {
+    let file = File::open(path).ok()?;
+    let before = stamp(&file)?;
+    if before.0 < THRESHOLD {
+        return None;
+    }
+    let map = Map::new(&file, usize::try_from(before.0).ok()?)?;
+    let result = f(map.bytes());
+    drop(map);
+    match stamp(&file) == Some(before) {
+        true => Some(result),
+        false => {
+            log::debug!(path:% = path.display(); "The file changed while mapped, reading it again");
+            None
+        }
+    }
+}
+
+/// The size and modification time of `file`, which change when it's written to.
+fn stamp(file: &File) -> Option<(u64, SystemTime)> {
+    let metadata = file.metadata().ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+#[cfg(unix)]
+struct Map {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Map {
+    fn new(file: &File, len: usize) -> Option<Map> {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: a fresh read-only private mapping of an open file, which is checked for failure
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        (ptr != libc::MAP_FAILED).then_some(Map { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`
+        unsafe { std::slice::from_raw_parts(self.ptr.cast(), self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Map {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was made by `Map::new` and isn't used after this
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+/// Files are only mapped on Unix, for lack of a dependency wrapping the other platforms
+#[cfg(not(unix))]
+struct Map;
+
+#[cfg(not(unix))]
+impl Map {
+    fn new(_file: &File, _len: usize) -> Option<Map> {
+        None
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &[]
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// A file of `len` bytes in the temp directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, len: u64) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("llmbundle-mmap-{name}-{}", std::process::id()));
+            let content: Vec<u8> = (0..len).map(|i| b'a' + (i % 26) as u8).collect();
+            std::fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn maps_large_files() {
+        let file = TempFile::new("large", THRESHOLD);
+        let mapped = with_mapped(&file.0, |bytes| bytes.to_vec());
+        assert_eq!(mapped, Some(std::fs::read(&file.0).unwrap()));
+    }
+
+    #[test]
+    fn leaves_small_files_to_be_read() {
+        let file = TempFile::new("small", THRESHOLD - 1);
+        assert_eq!(with_mapped(&file.0, |bytes| bytes.len()), None);
+    }
+
+    #[test]
+    fn falls_back_when_the_file_changes_while_mapped() {
+        let file = TempFile::new("changed", THRESHOLD);
+        let mapped = with_mapped(&file.0, |bytes| {
+            let mut appended = File::options().append(true).open(&file.0).unwrap();
+            appended.write_all(b"more").unwrap();
+            bytes.len()
+        });
+        assert_eq!(mapped, None);
+    }
+
+    #[test]
+    fn falls_back_when_the_file_is_missing() {
+        let path = std::env::temp_dir().join("llmbundle-mmap-missing-file");
+        assert_eq!(with_mapped(&path, |bytes| bytes.len()), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! Unicode normalization form C, the precomposed form of the characters most platforms write
+//! names in, while macOS file systems have long returned them decomposed (`e` followed by a
+//! combining acute accent for `é`).
+//!
+//! Only the canonical composition NFC ends with is made: decomposed and precomposed text both
+//! come out composed, but combining marks out of canonical order aren't reordered first.
+
+mod tables;
+
+use std::borrow::Cow;
+use tables::{COMBINING_CLASSES, COMPOSITIONS, SECONDS};
+
+// Hangul syllables are composed from their jamo arithmetically
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const S_COUNT: u32 = L_COUNT * V_COUNT * T_COUNT;
+
+/// `text` in normalization form C, borrowed when nothing composes.
+pub(crate) fn compose(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() || !text.chars().any(is_second) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut composed: Vec<char> = Vec::with_capacity(text.len());
+    // The last starter, and the highest combining class of the characters left after it
+    let mut starter = None;
+    let mut after = 0;
+    for c in text.chars() {
+        let class = combining_class(c);
+        if let Some(i) = starter {
+            // Blocked by a character as high in class between them
+            let blocked = composed.len() > i + 1 && (class == 0 || after >= class);
+            if let Some(composite) = (!blocked).then(|| pair(composed[i], c)).flatten() {
+                composed[i] = composite;
+                continue;
+            }
+        }
+        if class == 0 {
+            starter = Some(composed.len());
+            after = 0;
+        } else {
+            after = after.max(class);
+        }
+        composed.push(c);
+    }
+    Cow::Owned(composed.into_iter().collect())
+}
+
+/// Whether `c` can compose with the character before it.
+fn is_second(c: char) -> bool {
+    let c = u32::from(c);
+    (V_BASE..V_BASE + V_COUNT).contains(&c)
+        || (T_BASE + 1..T_BASE + T_COUNT).contains(&c)
+        || SECONDS.binary_search(&c).is_ok()
+}
+
+/// The character `first` and `second` compose into, if any.
+fn pair(first: char, second: char) -> Option<char> {
+    let (first, second) = (u32::from(first), u32::from(second));
+    if (L_BASE..L_BASE + L_COUNT).contains(&first) && (V_BASE..V_BASE + V_COUNT).contains(&second) {
+        let index = (first - L_BASE) * V_COUNT + (second - V_BASE);
+        return char::from_u32(S_BASE + index * T_COUNT);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&first)
+        && (first - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&second)
+    {
+        return char::from_u32(first + second - T_BASE);
+    }
+    let i = COMPOSITIONS
+        .binary_search_by(|&(a, b, _)| (a, b).cmp(&(first, second)))
+        .ok()?;
+    char::from_u32(COMPOSITIONS[i].2)
+}
+
+fn combining_class(c: char) -> u8 {
+    let c = u32::from(c);
+    match COMBINING_CLASSES.binary_search_by(|&(first, last, _)| {
+        if last < c {
+            std::cmp::Ordering::Less
+        } else if first > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(i) => COMBINING_CLASSES[i].2,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composes_decomposed_letters() {
+        assert_eq!(compose("caf\u{65}\u{301}"), "caf\u{e9}");
+        assert_eq!(compose("A\u{30a}ngstr\u{f6}m"), "\u{c5}ngstr\u{f6}m");
+    }
+
+    #[test]
+    fn composes_several_marks_in_canonical_order() {
+        // With a circumflex then an acute, and a dot below then a circumflex
+        assert_eq!(compose("o\u{302}\u{301}"), "\u{1ed1}");
+        assert_eq!(compose("a\u{323}\u{302}"), "\u{1ead}");
+    }
+
+    #[test]
+    fn composes_hangul_syllables() {
+        assert_eq!(compose("\u{1100}\u{1161}"), "\u{ac00}");
+        assert_eq!(compose("\u{1100}\u{1161}\u{11a8}"), "\u{ac01}");
+    }
+
+    #[test]
+    fn borrows_text_with_nothing_to_compose() {
+        assert!(matches!(compose("src/main.rs"), Cow::Borrowed(_)));
+        assert!(matches!(compose("caf\u{e9}"), Cow::Borrowed(_)));
+        // A lone combining mark has no starter to compose with
+        assert_eq!(compose("\u{301}x"), "\u{301}x");
+    }
+}
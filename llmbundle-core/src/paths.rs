@@ -0,0 +1,93 @@
+//! Paths as they appear in bundles and the output, the same on every platform.
+//!
+//! Windows extended-length paths (`\\?\C:\dir`, `\\?\UNC\server\share\dir`), which deep trees
+//! need and `canonicalize` returns, are kept for the filesystem calls but shown without their
+//! prefix.
+//!
+//! Names are matched, sorted and shown in Unicode normalization form C, so a file named with
+//! decomposed characters, as macOS file systems return them, is bundled the same as on Linux.
+
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// Longest path Windows takes without the extended-length prefix
+const MAX_PATH: usize = 260;
+
+/// `path` as shown in bundle headers and summaries: without the extended-length prefix, in NFC
+/// and with `/` separators on Windows too, so bundles made on every platform are identical.
+pub fn display(path: &Path) -> String {
+    let text = match strip_verbatim(path) {
+        Some(path) => path.display().to_string(),
+        None => path.display().to_string(),
+    };
+    let text = match nfc(&text) {
+        Cow::Borrowed(_) => text,
+        Cow::Owned(composed) => composed,
+    };
+    // A backslash is a valid character of file names elsewhere
+    if cfg!(windows) {
+        text.replace('\\', "/")
+    } else {
+        text
+    }
+}
+
+/// `text` in Unicode normalization form C, with its decomposed characters composed.
+pub fn nfc(text: &str) -> Cow<'_, str> {
+    crate::nfc::compose(text)
+}
+
+/// `path` in normalization form C, to match and compare it; a path that isn't valid Unicode is
+/// returned as is. The filesystem is still given the path it returned.
+pub fn normalized(path: &Path) -> Cow<'_, Path> {
+    match path.to_str().map(nfc) {
+        Some(Cow::Owned(composed)) => Cow::Owned(PathBuf::from(composed)),
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// `path` without the extended-length prefix when Windows takes it without: `\\?\C:\dir` becomes
+/// `C:\dir` and `\\?\UNC\server\share` becomes `\\server\share`, unless too long or made of
+/// names only the prefix allows. Other paths are returned as is.
+pub fn simplify(path: &Path) -> PathBuf {
+    match strip_verbatim(path) {
+        Some(simple) if simple.as_os_str().len() < MAX_PATH => simple,
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `path` without its extended-length prefix, `None` if it has none or needs it for its names.
+fn strip_verbatim(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return None;
+    };
+    let mut simple = match prefix.kind() {
+        Prefix::VerbatimDisk(drive) => PathBuf::from(format!("{}:\\", char::from(drive))),
+        Prefix::VerbatimUNC(server, share) => {
+            let mut root = OsString::from(r"\\");
+            root.push(server);
+            root.push(r"\");
+            root.push(share);
+            root.push(r"\");
+            PathBuf::from(root)
+        }
+        _ => return None,
+    };
+    for component in components {
+        match component {
+            Component::RootDir => {}
+            // Names Windows would otherwise trim or reinterpret
+            Component::Normal(name)
+                if name
+                    .to_str()
+                    .is_some_and(|name| !name.ends_with(['.', ' ']) && !name.contains(':')) =>
+            {
+                simple.push(name)
+            }
+            _ => return None,
+        }
+    }
+    Some(simple)
+}
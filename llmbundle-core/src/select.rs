@@ -0,0 +1,201 @@
+//! Matching the files with the glob patterns and ordering them.
+
+use crate::paths;
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::cmp::Ordering;
+use std::path::Path;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Order of the files in the bundle
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Sort {
+    /// By path
+    #[default]
+    Path,
+    /// By path, numbers compared by value (`file2` before `file10`) and the files of a directory
+    /// before its subdirectories
+    Natural,
+    /// By size, smallest first
+    Size,
+    /// By modification time, oldest first
+    Mtime,
+    /// By estimated tokens, fewest first
+    Tokens,
+    /// In the order the files are found, which changes from run to run as the directories are
+    /// walked in parallel
+    None,
+}
+
+/// Sorts `paths` (relative to `root`) by `order`, leaving them by path for `tokens`, which is
+/// applied once the files are read.
+#[cfg(feature = "fs")]
+pub(crate) fn sort(root: &Path, order: Sort, reverse: bool, paths: &mut [PathBuf]) {
+    let metadata = |path: &PathBuf| std::fs::metadata(root.join(path)).ok();
+    sort_by(
+        order,
+        reverse,
+        paths,
+        |path| path,
+        |path| metadata(path).map_or(0, |metadata| metadata.len()),
+        |path| metadata(path).and_then(|metadata| metadata.modified().ok()),
+    );
+}
+
+/// Sorts `items` by `order` like [`sort`], with the path, size and modification time of each
+/// given by the functions.
+pub(crate) fn sort_by<T>(
+    order: Sort,
+    reverse: bool,
+    items: &mut [T],
+    path: impl Fn(&T) -> &Path,
+    size: impl Fn(&T) -> u64,
+    mtime: impl Fn(&T) -> Option<SystemTime>,
+) {
+    match order {
+        Sort::Path | Sort::Tokens => items.sort_by(|a, b| path_cmp(path(a), path(b))),
+        Sort::Natural => items
+            .sort_by(|a, b| natural_cmp(&paths::normalized(path(a)), &paths::normalized(path(b)))),
+        Sort::Size => {
+            items.sort_by(|a, b| path_cmp(path(a), path(b)));
+            items.sort_by_cached_key(size);
+        }
+        Sort::Mtime => {
+            items.sort_by(|a, b| path_cmp(path(a), path(b)));
+            items.sort_by_cached_key(mtime);
+        }
+        Sort::None => {}
+    }
+    if reverse && order != Sort::Tokens {
+        items.reverse();
+    }
+}
+
+/// Compares paths in NFC, so they're in the same order on every platform.
+fn path_cmp(a: &Path, b: &Path) -> Ordering {
+    paths::normalized(a).cmp(&paths::normalized(b))
+}
+
+/// Compares paths directory by directory, files before subdirectories and names with
+/// [`natural_cmp_names`].
+fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let (a, b): (Vec<_>, Vec<_>) = (a.components().collect(), b.components().collect());
+    for (i, (x, y)) in a.iter().zip(&b).enumerate() {
+        if x == y {
+            continue;
+        }
+        let (x_is_file, y_is_file) = (i == a.len() - 1, i == b.len() - 1);
+        return y_is_file.cmp(&x_is_file).then_with(|| {
+            natural_cmp_names(
+                &x.as_os_str().to_string_lossy(),
+                &y.as_os_str().to_string_lossy(),
+            )
+        });
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Compares names by their runs of digits and non-digits, the digits by value and the rest
+/// ignoring case, falling back to the plain order to break ties.
+fn natural_cmp_names(a: &str, b: &str) -> Ordering {
+    fn chunks(name: &str) -> impl Iterator<Item = &str> {
+        let mut rest = name;
+        std::iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (chunk, tail) = rest.split_at(end);
+            rest = tail;
+            Some(chunk)
+        })
+    }
+
+    let mut x = chunks(a);
+    let mut y = chunks(b);
+    loop {
+        let ordering = match (x.next(), y.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y))
+                if x.starts_with(|c: char| c.is_ascii_digit())
+                    && y.starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => x.to_lowercase().cmp(&y.to_lowercase()),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// The glob a pattern (without its `!`) is matched with: normalized, then with the tilde and the
+/// environment variables expanded, in NFC like the paths it's matched against.
+pub fn resolve_pattern(pattern: &str) -> Result<String> {
+    let pattern = normalize_pattern(pattern);
+    let expanded = shellexpand::full(&pattern)
+        .with_context(|| format!("Failed to expand pattern: {}", pattern))?;
+    Ok(paths::nfc(&expanded).into_owned())
+}
+
+/// `pattern` with `/` separators, matched anywhere in the tree unless it has some.
+fn normalize_pattern(pattern: &str) -> String {
+    // The paths are matched with `/` separators on Windows too
+    let pattern = match cfg!(windows) {
+        true => pattern.replace('\\', "/"),
+        false => pattern.to_string(),
+    };
+    if pattern.contains('/') {
+        pattern
+    } else {
+        format!("**/{}", pattern)
+    }
+}
+
+/// Sets matching the positive patterns (every file when there are none) and the negative ones.
+pub fn build_glob_sets(patterns: &[String]) -> Result<(GlobSet, GlobSet)> {
+    let (mut pos, mut neg) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+
+    let mut pos_builder_is_empty = true;
+
+    for pattern in patterns {
+        let pattern = pattern.trim();
+
+        let (pattern, builder) = {
+            if pattern.starts_with('!') {
+                let pattern = pattern.trim_start_matches('!');
+                if pattern.is_empty() {
+                    continue;
+                }
+                // If the pattern starts with a '!', add to negative patterns
+                (pattern, &mut neg)
+            } else {
+                // Otherwise add to positive patterns
+                pos_builder_is_empty = false;
+                (pattern, &mut pos)
+            }
+        };
+
+        let expanded = resolve_pattern(pattern)?;
+        let glob = Glob::new(&expanded)
+            .with_context(|| format!("Invalid glob pattern after expansion: {}", expanded))?;
+
+        builder.add(glob);
+    }
+
+    if pos_builder_is_empty {
+        pos.add(Glob::new("**").expect("** is valid pattern"));
+    }
+
+    let pos_set = pos.build().context("Failed to build positive glob set")?;
+    let neg_set = neg.build().context("Failed to build negative glob set")?;
+
+    Ok((pos_set, neg_set))
+}
@@ -0,0 +1,103 @@
+//! Formatted files waiting to be written, set aside in a temp file once holding them in memory
+//! would go over the memory limit.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Holds texts in memory up to `limit` bytes and writes the others to a temp file, which is
+/// removed when dropped.
+pub(crate) struct Spill {
+    limit: u64,
+    held: u64,
+    file: Option<(PathBuf, File)>,
+    len: u64,
+}
+
+/// A text held by a [`Spill`]
+pub(crate) enum Held {
+    Memory(String),
+    Spilled { offset: u64, len: usize },
+}
+
+impl Spill {
+    pub(crate) fn new(limit: u64) -> Self {
+        Spill {
+            limit,
+            held: 0,
+            file: None,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn hold(&mut self, text: String) -> std::io::Result<Held> {
+        let len = text.len() as u64;
+        if self.held + len <= self.limit {
+            self.held += len;
+            return Ok(Held::Memory(text));
+        }
+        let file = match &mut self.file {
+            Some((_, file)) => file,
+            None => &mut self.file.insert(create()?).1,
+        };
+        file.write_all(text.as_bytes())?;
+        let held = Held::Spilled {
+            offset: self.len,
+            len: text.len(),
+        };
+        self.len += len;
+        Ok(held)
+    }
+
+    /// The text back, read from the temp file if it was spilled.
+    pub(crate) fn take(&mut self, held: Held) -> std::io::Result<String> {
+        let (offset, len) = match held {
+            Held::Memory(text) => {
+                self.held -= text.len() as u64;
+                return Ok(text);
+            }
+            Held::Spilled { offset, len } => (offset, len),
+        };
+        let (_, file) = self.file.as_mut().expect("spilled texts have a file");
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0; len];
+        file.read_exact(&mut bytes)?;
+        String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Some((path, _)) = &self.file {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Creates a new, uniquely named file in the system temp directory.
+fn create() -> std::io::Result<(PathBuf, File)> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    for attempt in 0u32.. {
+        let name = format!("llmbundle-spill-{}-{nanos:x}-{attempt}", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        match File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => {
+                log::debug!(path:% = path.display(); "Spilling the files over the memory limit");
+                return Ok((path, file));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of temp file names")
+}
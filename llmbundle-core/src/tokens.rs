@@ -0,0 +1,160 @@
+/// Estimates the number of tokens `text` takes up for a typical LLM tokenizer.
+///
+/// Uses the common approximation of four characters per token, which is close enough for
+/// budgeting and warnings without shipping a tokenizer vocabulary.
+pub fn estimate(text: &str) -> usize {
+    estimate_chars(text.chars().count())
+}
+
+/// Estimates the number of tokens of a text of `chars` characters.
+pub fn estimate_chars(chars: usize) -> usize {
+    chars.div_ceil(4)
+}
+
+/// Texts at least this big are counted in chunks on the rayon pool
+#[cfg(feature = "fs")]
+const PARALLEL_THRESHOLD: usize = 1024 * 1024;
+
+/// Bytes of the chunks counted in parallel
+#[cfg(feature = "fs")]
+const CHUNK: usize = 256 * 1024;
+
+/// The lines, words and characters of a text, as [`str::lines`], [`str::split_whitespace`] and
+/// [`str::chars`] count them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+}
+
+impl Counts {
+    /// The estimated tokens of the text, as [`estimate`] gives them.
+    pub fn tokens(&self) -> usize {
+        estimate_chars(self.chars)
+    }
+}
+
+/// Counts `text` in a single pass, large texts being split in chunks counted in parallel on the
+/// current rayon pool.
+pub fn count(text: &str) -> Counts {
+    #[cfg(feature = "fs")]
+    let pieces = match text.len() < PARALLEL_THRESHOLD {
+        true => vec![Piece::of(text)],
+        false => {
+            use rayon::prelude::*;
+            chunks(text)
+                .par_iter()
+                .map(|chunk| Piece::of(chunk))
+                .collect()
+        }
+    };
+    #[cfg(not(feature = "fs"))]
+    let pieces = vec![Piece::of(text)];
+
+    let mut counts = Counts::default();
+    let mut newlines = 0;
+    let mut in_word = false;
+    for piece in pieces {
+        newlines += piece.newlines;
+        counts.chars += piece.chars;
+        // A word cut between two chunks is counted by both
+        counts.words += piece.words - usize::from(in_word && piece.starts_in_word);
+        in_word = piece.ends_in_word;
+    }
+    // The last line doesn't need to end with a newline
+    counts.lines = newlines + usize::from(!text.is_empty() && !text.ends_with('\n'));
+    counts
+}
+
+/// `text` cut in chunks of about [`CHUNK`] bytes, between characters.
+#[cfg(feature = "fs")]
+fn chunks(text: &str) -> Vec<&str> {
+    let mut chunks = Vec::with_capacity(text.len() / CHUNK + 1);
+    let mut rest = text;
+    while rest.len() > CHUNK {
+        let end = (CHUNK..rest.len())
+            .find(|&end| rest.is_char_boundary(end))
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+/// The counts of a chunk of a text, and whether it starts and ends in a word, to join them
+#[derive(Default)]
+struct Piece {
+    newlines: usize,
+    words: usize,
+    chars: usize,
+    starts_in_word: bool,
+    ends_in_word: bool,
+}
+
+impl Piece {
+    fn of(text: &str) -> Piece {
+        let mut piece = Piece {
+            starts_in_word: text.chars().next().is_some_and(|c| !c.is_whitespace()),
+            ..Piece::default()
+        };
+        for c in text.chars() {
+            piece.chars += 1;
+            piece.newlines += usize::from(c == '\n');
+            let in_word = !c.is_whitespace();
+            piece.words += usize::from(in_word && !piece.ends_in_word);
+            piece.ends_in_word = in_word;
+        }
+        piece
+    }
+}
+
+/// Counts the characters of a file given chunk by chunk, decoding it like the bundle: as UTF-8,
+/// or as UTF-16 after a byte order mark. Bytes that aren't valid aren't counted, so the count
+/// is at most the one of the content the invalid bytes are replaced in.
+#[derive(Default)]
+pub struct Counter {
+    /// Bytes given so far
+    offset: usize,
+    /// With UTF-16, whether the high byte of each unit comes second (little endian) or first
+    high_byte_second: Option<bool>,
+    /// UTF-8 bytes starting a character, or UTF-16 units continuing one
+    count: usize,
+}
+
+impl Counter {
+    pub fn add(&mut self, bytes: &[u8]) {
+        if self.offset == 0 {
+            self.high_byte_second = match bytes {
+                [0xFF, 0xFE, ..] => Some(true),
+                [0xFE, 0xFF, ..] => Some(false),
+                _ => None,
+            };
+        }
+        self.count += match self.high_byte_second {
+            // Every byte but the continuation bytes of UTF-8 starts a character
+            None => bytes.iter().filter(|&&byte| byte & 0xC0 != 0x80).count(),
+            // The low surrogates continue the character of the unit before
+            Some(second) => bytes
+                .iter()
+                .enumerate()
+                .filter(|(i, &byte)| {
+                    (self.offset + i) % 2 == usize::from(second) && (0xDC..=0xDF).contains(&byte)
+                })
+                .count(),
+        };
+        self.offset += bytes.len();
+    }
+
+    /// The characters of the bytes given so far. Until the last chunk is given, a UTF-16
+    /// character cut between two chunks may be counted twice.
+    pub fn count(&self) -> usize {
+        match self.high_byte_second {
+            None => self.count,
+            // Without the byte order mark, a lone trailing byte being replaced by a character
+            Some(_) => self.offset.div_ceil(2) - 1 - self.count,
+        }
+    }
+}
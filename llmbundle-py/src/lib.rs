@@ -0,0 +1,224 @@
+//! Python bindings of llmbundle-core, so agent frameworks can select, format and budget files the
+//! way llmbundle does without running the command.
+
+use llmbundle_core::{tokens, Bundle, Bundler, Format, Sort};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use std::path::PathBuf;
+
+/// The arguments the functions share, named after the flags of the command
+struct Options {
+    patterns: Vec<String>,
+    root: String,
+    max_tokens: Option<usize>,
+    max_depth: Option<usize>,
+    format: Format,
+    checksums: bool,
+    strict: bool,
+    sort: Sort,
+    reverse: bool,
+    group_by_dir: Option<usize>,
+}
+
+impl Options {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        patterns: Option<Vec<String>>,
+        root: String,
+        max_tokens: Option<usize>,
+        max_depth: Option<usize>,
+        format: &str,
+        checksums: bool,
+        strict: bool,
+        sort: &str,
+        reverse: bool,
+        group_by_dir: Option<usize>,
+    ) -> PyResult<Self> {
+        let format = match format {
+            "default" => Format::Default,
+            "patchable" => Format::Patchable,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid format {format:?}, expected \"default\" or \"patchable\""
+                )))
+            }
+        };
+        let sort = match sort {
+            "path" => Sort::Path,
+            "natural" => Sort::Natural,
+            "size" => Sort::Size,
+            "mtime" => Sort::Mtime,
+            "tokens" => Sort::Tokens,
+            "none" => Sort::None,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid sort {sort:?}, expected \"path\", \"natural\", \"size\", \"mtime\", \
+                     \"tokens\" or \"none\""
+                )))
+            }
+        };
+        Ok(Options {
+            patterns: patterns.unwrap_or_default(),
+            root,
+            max_tokens,
+            max_depth,
+            format,
+            checksums,
+            strict,
+            sort,
+            reverse,
+            group_by_dir,
+        })
+    }
+
+    /// Bundles the files, letting the other Python threads run while they're read.
+    fn bundle(self, py: Python<'_>) -> PyResult<Bundle> {
+        py.allow_threads(move || {
+            Bundler::new(self.root)
+                .patterns(self.patterns)
+                .max_depth(self.max_depth)
+                .max_tokens(self.max_tokens)
+                .format(self.format)
+                .checksums(self.checksums)
+                .strict(self.strict)
+                .sort(self.sort, self.reverse)
+                .group_by_dir(self.group_by_dir)
+                .bundle()
+        })
+        .map_err(|e| PyRuntimeError::new_err(format!("{e:#}")))
+    }
+}
+
+/// Bundles the files under `root` matching the glob `patterns` (every file that isn't ignored
+/// when there are none, the ones starting with `!` leaving files out) into a single text.
+#[pyfunction]
+#[pyo3(signature = (
+    patterns = None,
+    root = ".".to_string(),
+    *,
+    max_tokens = None,
+    max_depth = None,
+    format = "default",
+    checksums = false,
+    strict = false,
+    sort = "path",
+    reverse = false,
+    group_by_dir = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn bundle(
+    py: Python<'_>,
+    patterns: Option<Vec<String>>,
+    root: String,
+    max_tokens: Option<usize>,
+    max_depth: Option<usize>,
+    format: &str,
+    checksums: bool,
+    strict: bool,
+    sort: &str,
+    reverse: bool,
+    group_by_dir: Option<usize>,
+) -> PyResult<String> {
+    let options = Options::new(
+        patterns,
+        root,
+        max_tokens,
+        max_depth,
+        format,
+        checksums,
+        strict,
+        sort,
+        reverse,
+        group_by_dir,
+    )?;
+    Ok(options.bundle(py)?.text)
+}
+
+/// Like `bundle`, returning a dict with the `text` of the bundle, its `files` with their `path`,
+/// `lines`, `words`, `characters` and `tokens`, the total `tokens`, and the paths `dropped` to
+/// stay within `max_tokens` and `unreadable`.
+#[pyfunction]
+#[pyo3(signature = (
+    patterns = None,
+    root = ".".to_string(),
+    *,
+    max_tokens = None,
+    max_depth = None,
+    format = "default",
+    checksums = false,
+    strict = false,
+    sort = "path",
+    reverse = false,
+    group_by_dir = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn bundle_stats<'py>(
+    py: Python<'py>,
+    patterns: Option<Vec<String>>,
+    root: String,
+    max_tokens: Option<usize>,
+    max_depth: Option<usize>,
+    format: &str,
+    checksums: bool,
+    strict: bool,
+    sort: &str,
+    reverse: bool,
+    group_by_dir: Option<usize>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let options = Options::new(
+        patterns,
+        root,
+        max_tokens,
+        max_depth,
+        format,
+        checksums,
+        strict,
+        sort,
+        reverse,
+        group_by_dir,
+    )?;
+    let bundle = options.bundle(py)?;
+
+    let files = PyList::empty(py);
+    for file in &bundle.files {
+        let entry = PyDict::new(py);
+        entry.set_item("path", file.path.display().to_string())?;
+        entry.set_item("lines", file.line_count)?;
+        entry.set_item("words", file.word_count)?;
+        entry.set_item("characters", file.char_count)?;
+        entry.set_item("tokens", file.token_count)?;
+        files.append(entry)?;
+    }
+    let stats = PyDict::new(py);
+    stats.set_item("files", files)?;
+    stats.set_item(
+        "tokens",
+        bundle.files.iter().map(|file| file.token_count).sum::<usize>(),
+    )?;
+    stats.set_item("dropped", paths(&bundle.dropped))?;
+    stats.set_item("unreadable", paths(&bundle.unreadable))?;
+    stats.set_item("text", bundle.text)?;
+    Ok(stats)
+}
+
+/// Estimated number of tokens of `text`, the way the budget counts them.
+#[pyfunction]
+fn estimate_tokens(text: &str) -> usize {
+    tokens::estimate(text)
+}
+
+fn paths(paths: &[PathBuf]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+#[pymodule]
+fn llmbundle(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(bundle, m)?)?;
+    m.add_function(wrap_pyfunction!(bundle_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_tokens, m)?)?;
+    Ok(())
+}
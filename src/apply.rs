@@ -0,0 +1,516 @@
+//! Applies the files of an LLM response back to the working tree, after showing what changes.
+
+use crate::unbundle::{join_paths, read_source};
+use crate::{blake3, bundle, diff, editor, merge, parse, patch, state};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use llmbundle_core::paths;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    /// Response to apply: a file, `-` for stdin or `clipboard`, unless a file of that name exists
+    #[arg(default_value = "clipboard")]
+    source: String,
+
+    /// Directory the paths in the response are relative to
+    #[arg(long, default_value = ".", help = "Root directory of the working tree")]
+    root: PathBuf,
+
+    /// Apply every change without asking
+    #[arg(short, long, help = "Accept all changes")]
+    yes: bool,
+
+    /// Only show the changes, without writing anything
+    #[arg(long, help = "Show the changes without applying them")]
+    dry_run: bool,
+
+    /// Replace files edited since they were bundled instead of merging the response with the
+    /// local changes
+    #[arg(long, help = "Don't merge with local changes")]
+    no_merge: bool,
+
+    /// Ask about each hunk of the changes instead of each file, like `git add -p`
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["yes", "dry_run"],
+        help = "Pick the changes hunk by hunk"
+    )]
+    interactive: bool,
+}
+
+/// How [`apply`] handles the proposals.
+pub struct Options {
+    /// Accept every change without asking
+    pub yes: bool,
+    /// Only show the changes
+    pub dry_run: bool,
+    /// Merge the files edited since the last bundle with the response
+    pub merge: bool,
+    /// Ask about each hunk instead of each file
+    pub interactive: bool,
+}
+
+/// What the response proposes for a file.
+pub enum Proposal {
+    /// The whole new content
+    Content(String),
+    /// A diff against the current content
+    Patch(patch::FilePatch),
+}
+
+struct Change {
+    path: PathBuf,
+    target: PathBuf,
+    content: String,
+}
+
+pub fn run(args: &ApplyArgs) -> Result<()> {
+    let text = read_source(&args.source)?;
+    warn_corrupted(&text);
+    let proposals = proposals(&text);
+    if proposals.is_empty() {
+        bail!("No files or diffs found in {}", args.source);
+    }
+
+    let mut prompt = if args.yes || args.dry_run {
+        None
+    } else {
+        Some(Prompt::open(&args.source)?)
+    };
+    let options = Options {
+        yes: args.yes,
+        dry_run: args.dry_run,
+        merge: !args.no_merge,
+        interactive: args.interactive,
+    };
+    apply(&args.root, proposals, prompt.as_mut(), &options)
+}
+
+/// Warns about the files of `text` not matching their checksum. The model may have edited them
+/// without updating it, so it's not an error.
+pub fn warn_corrupted(text: &str) {
+    let corrupted = bundle::corrupted(text);
+    if !corrupted.is_empty() {
+        eprintln!(
+            "{}",
+            format!(
+                "The checksums of {} don't match their content, check the response wasn't \
+                 truncated or altered on the way",
+                join_paths(&corrupted)
+            )
+            .yellow()
+        );
+    }
+}
+
+/// The changes proposed in `text`: its unified diffs if it has any, its files otherwise.
+pub fn proposals(text: &str) -> Vec<(PathBuf, Proposal)> {
+    let patches = patch::parse(text);
+    if patches.is_empty() {
+        parse::files(text)
+            .into_iter()
+            .map(|(path, content)| (path, Proposal::Content(content)))
+            .collect()
+    } else {
+        patches
+            .into_iter()
+            .map(|patch| (patch.path.clone(), Proposal::Patch(patch)))
+            .collect()
+    }
+}
+
+/// Shows the diff of each proposal against the files under `root` and writes the ones accepted
+/// through `prompt` (all of them with `yes`, none of them without a prompt).
+///
+/// With `merge`, files edited since the last bundle (according to its manifest) get a three-way
+/// merge of the local changes and the response, which was written against the bundled version.
+pub fn apply(
+    root: &Path,
+    proposals: Vec<(PathBuf, Proposal)>,
+    mut prompt: Option<&mut Prompt>,
+    options: &Options,
+) -> Result<()> {
+    let manifest = match options.merge {
+        true => state::manifest(root)?.filter(|manifest| manifest.is_of(root)),
+        false => None,
+    };
+    let mut accept_all = options.yes;
+    let mut accepted = Vec::new();
+    let mut failed = Vec::new();
+
+    for (path, proposal) in proposals {
+        // A file that can't be changed doesn't keep the others from being applied
+        if let Proposal::Patch(patch) = &proposal {
+            if patch.deletes_file() {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "The diff deletes {}, which apply doesn't do, skipping it",
+                        paths::display(&path)
+                    )
+                    .red()
+                );
+                failed.push(path);
+                continue;
+            }
+        }
+        let target = match bundle::resolve(root, &path) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("{}", format!("{e:#}, skipping it").red());
+                failed.push(path);
+                continue;
+            }
+        };
+        let current = match std::fs::read(&target) {
+            Ok(current) => Some(String::from_utf8_lossy(&current).into_owned()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Failed to read {}: {e}, skipping it",
+                        paths::display(&target)
+                    )
+                    .red()
+                );
+                failed.push(path);
+                continue;
+            }
+        };
+        let edited = manifest
+            .as_ref()
+            .zip(current.as_ref())
+            .filter(|(manifest, current)| {
+                manifest
+                    .hash(&path)
+                    .is_some_and(|hash| hash != blake3::hex(current.as_bytes()))
+            });
+        let base = edited.and_then(|(manifest, _)| manifest.content(&path));
+        if edited.is_some() && base.is_none() {
+            println!(
+                "{}",
+                format!(
+                    "{} was edited since it was bundled, but its bundled content wasn't kept \
+                     (bundle with --merge-base to merge the local changes)",
+                    paths::display(&path)
+                )
+                .yellow()
+            );
+        }
+        let content = match proposal {
+            Proposal::Content(content) => content,
+            Proposal::Patch(patch) if current.is_none() && !patch.creates_file() => {
+                eprintln!(
+                    "{}",
+                    format!("{} doesn't exist, skipping its diff", paths::display(&path)).red()
+                );
+                failed.push(path);
+                continue;
+            }
+            Proposal::Patch(patch) => {
+                match patch.apply(base.as_deref().or(current.as_deref()).unwrap_or("")) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        eprintln!("{}", format!("{e:#}, skipping it").red());
+                        failed.push(path);
+                        continue;
+                    }
+                }
+            }
+        };
+        let content = match (&base, &current) {
+            (Some(base), Some(current)) => {
+                let merged = merge::merge(base, current, &content);
+                println!(
+                    "{}",
+                    format!(
+                        "{} was edited since it was bundled, merging the local changes",
+                        paths::display(&path)
+                    )
+                    .yellow()
+                );
+                if merged.conflicts > 0 {
+                    println!(
+                        "{}",
+                        format!(
+                            "{} conflicts in {}, marked with <<<<<<< and >>>>>>>",
+                            merged.conflicts,
+                            paths::display(&path)
+                        )
+                        .red()
+                        .bold()
+                    );
+                }
+                merged.text
+            }
+            _ => content,
+        };
+        if current.as_deref() == Some(content.as_str()) {
+            println!("{} {}", "Unchanged".black(), paths::display(&path));
+            continue;
+        }
+
+        if let (true, Some(prompt)) = (options.interactive, &mut prompt) {
+            print_header(&path, current.is_some());
+            let (content, quit) = select_hunks(prompt, current.as_deref().unwrap_or(""), &content)?;
+            if current.as_deref() != Some(content.as_str()) {
+                accepted.push(Change {
+                    path,
+                    target,
+                    content,
+                });
+            }
+            if quit {
+                break;
+            }
+            continue;
+        }
+
+        print_diff(&path, current.as_deref(), &content);
+
+        let decision = match &mut prompt {
+            _ if accept_all => Decision::Yes,
+            None => Decision::No,
+            Some(prompt) => prompt.ask(&format!("Apply changes to {}?", paths::display(&path)))?,
+        };
+        match decision {
+            Decision::Yes => {}
+            Decision::All => accept_all = true,
+            Decision::No => continue,
+            Decision::Quit => break,
+        }
+        accepted.push(Change {
+            path,
+            target,
+            content,
+        });
+    }
+
+    if !options.dry_run {
+        write(&accepted)?;
+    }
+    if !failed.is_empty() {
+        bail!("Failed to apply the changes to {}", join_paths(&failed));
+    }
+    Ok(())
+}
+
+fn write(accepted: &[Change]) -> Result<()> {
+    for change in accepted {
+        if let Some(parent) = change.target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", paths::display(parent)))?;
+        }
+        std::fs::write(&change.target, &change.content)
+            .with_context(|| format!("Failed to write {}", paths::display(&change.target)))?;
+    }
+    if !accepted.is_empty() {
+        println!(
+            "Applied changes to {}: {}.",
+            format!("{} files", accepted.len()).bold(),
+            accepted
+                .iter()
+                .map(|change| paths::display(&change.path))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Asks about each hunk of the diff from `old` to `new`, returning `old` with the accepted hunks
+/// applied and whether the user chose to stop altogether.
+fn select_hunks(prompt: &mut Prompt, old: &str, new: &str) -> Result<(String, bool)> {
+    let (old_lines, new_lines) = (diff::lines(old), diff::lines(new));
+    let ops = diff::diff(&old_lines, &new_lines);
+    let hunks = diff::hunks(&ops, 3);
+
+    let mut result = String::new();
+    let mut position = 0;
+    // Answer given for the remaining hunks with `a`, `d` or `q`
+    let mut rest = None;
+    let mut quit = false;
+    for (n, hunk) in hunks.iter().enumerate() {
+        result.extend(old_lines[position..hunk.old_start].iter().copied());
+        position = hunk.old_start + hunk.old_len;
+        let old_side = || hunk.lines.iter().filter(|(op, _)| *op != diff::Op::Insert);
+        let new_side = || hunk.lines.iter().filter(|(op, _)| *op != diff::Op::Delete);
+
+        let decision = match rest {
+            Some(decision) => decision,
+            None => {
+                hunk.print();
+                prompt.ask_hunk(&format!("Apply this hunk ({}/{})?", n + 1, hunks.len()))?
+            }
+        };
+        match decision {
+            HunkDecision::Yes | HunkDecision::All => {
+                result.extend(new_side().map(|(_, line)| *line))
+            }
+            HunkDecision::No | HunkDecision::Done | HunkDecision::Quit => {
+                result.extend(old_side().map(|(_, line)| *line))
+            }
+            HunkDecision::Edit => {
+                let proposed: String = new_side().map(|(_, line)| *line).collect();
+                result.push_str(&editor::edit(&proposed)?);
+            }
+        }
+        match decision {
+            HunkDecision::All => rest = Some(HunkDecision::Yes),
+            HunkDecision::Done => rest = Some(HunkDecision::No),
+            HunkDecision::Quit => {
+                rest = Some(HunkDecision::No);
+                quit = true;
+            }
+            _ => {}
+        }
+    }
+    result.extend(old_lines[position..].iter().copied());
+    Ok((result, quit))
+}
+
+fn print_header(path: &Path, exists: bool) {
+    println!();
+    match exists {
+        true => println!("{}", format!("--- a/{}", paths::display(path)).bold()),
+        false => println!("{}", "--- /dev/null".bold()),
+    }
+    println!("{}", format!("+++ b/{}", paths::display(path)).bold());
+}
+
+fn print_diff(path: &Path, current: Option<&str>, content: &str) {
+    let (old, new) = (diff::lines(current.unwrap_or("")), diff::lines(content));
+    print_header(path, current.is_some());
+    for hunk in diff::hunks(&diff::diff(&old, &new), 3) {
+        hunk.print();
+    }
+}
+
+pub enum Decision {
+    Yes,
+    No,
+    /// Yes to this and every following question
+    All,
+    /// No to this and every following question
+    Quit,
+}
+
+#[derive(Clone, Copy)]
+pub enum HunkDecision {
+    Yes,
+    No,
+    /// Edit the new lines of the hunk before applying them
+    Edit,
+    /// Yes to this and the following hunks of the file
+    All,
+    /// No to this and the following hunks of the file
+    Done,
+    /// No to this and every following hunk
+    Quit,
+}
+
+/// Asks the user questions on the terminal, even if stdin carries the response being applied.
+pub struct Prompt {
+    input: Box<dyn BufRead>,
+}
+
+impl Prompt {
+    pub fn open(source: &str) -> Result<Self> {
+        if source != "-" && std::io::stdin().is_terminal() {
+            return Ok(Prompt {
+                input: Box::new(BufReader::new(std::io::stdin())),
+            });
+        }
+        #[cfg(unix)]
+        if let Ok(tty) = std::fs::File::open("/dev/tty") {
+            return Ok(Prompt {
+                input: Box::new(BufReader::new(tty)),
+            });
+        }
+        bail!("No terminal to ask for confirmation, pass --yes to apply every change");
+    }
+
+    pub fn ask(&mut self, question: &str) -> Result<Decision> {
+        loop {
+            let Some(answer) = self.answer(question, "[y,n,a,q,?]")? else {
+                return Ok(Decision::Quit);
+            };
+            match answer.as_str() {
+                "y" | "yes" => return Ok(Decision::Yes),
+                "n" | "no" => return Ok(Decision::No),
+                "a" | "all" => return Ok(Decision::All),
+                "q" | "quit" => return Ok(Decision::Quit),
+                _ => println!(
+                    "y - apply this change\nn - skip this change\na - apply this and all \
+                     remaining changes\nq - skip this and all remaining changes"
+                ),
+            }
+        }
+    }
+
+    pub fn ask_hunk(&mut self, question: &str) -> Result<HunkDecision> {
+        loop {
+            let Some(answer) = self.answer(question, "[y,n,e,a,d,q,?]")? else {
+                return Ok(HunkDecision::Quit);
+            };
+            match answer.as_str() {
+                "y" | "yes" => return Ok(HunkDecision::Yes),
+                "n" | "no" => return Ok(HunkDecision::No),
+                "e" | "edit" => return Ok(HunkDecision::Edit),
+                "a" | "all" => return Ok(HunkDecision::All),
+                "d" | "done" => return Ok(HunkDecision::Done),
+                "q" | "quit" => return Ok(HunkDecision::Quit),
+                _ => println!(
+                    "y - apply this hunk\nn - skip this hunk\ne - edit the new lines of this hunk \
+                     before applying them\na - apply this and the remaining hunks of the file\nd - \
+                     skip this and the remaining hunks of the file\nq - skip this and all \
+                     remaining hunks"
+                ),
+            }
+        }
+    }
+
+    /// Asks `question`, returning the trimmed answer or `None` if the input ended.
+    fn answer(&mut self, question: &str, choices: &str) -> Result<Option<String>> {
+        print!("{} {} ", question.blue().bold(), choices.blue());
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(answer.trim().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_the_proposals_that_cant_be_applied() {
+        let root = std::env::temp_dir().join(format!("llmbundle-apply-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("kept.txt"), "old\n").unwrap();
+        let response = "--- a/gone.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-gone\n\
+                        --- a/../outside.txt\n+++ b/../outside.txt\n@@ -0,0 +1 @@\n+outside\n\
+                        --- a/kept.txt\n+++ b/kept.txt\n@@ -1 +1 @@\n-old\n+new\n";
+        let options = Options {
+            yes: true,
+            dry_run: false,
+            merge: false,
+            interactive: false,
+        };
+        let result = apply(&root, proposals(response), None, &options);
+        let kept = std::fs::read_to_string(root.join("kept.txt")).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("gone.txt") && error.contains("outside.txt"));
+        assert_eq!(kept, "new\n");
+    }
+}
@@ -0,0 +1,218 @@
+//! Writes the bundled files into a zip or tar(.gz) archive, preserving their relative paths.
+
+use crate::bundle::ProcessedFile;
+use crate::{state, temp};
+use anyhow::{bail, Context, Result};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes `files` to an archive at `path`, picking the format from its extension
+/// (`.zip`, `.tar`, `.tar.gz` or `.tgz`).
+pub fn write(path: &Path, files: &[ProcessedFile]) -> Result<()> {
+    let name = path.to_string_lossy().to_lowercase();
+    if ![".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+    {
+        bail!(
+            "Unknown archive format for {}, expected .zip, .tar, .tar.gz or .tgz",
+            path.display()
+        );
+    }
+    let mut writer = BufWriter::new(temp::Replacement::create(path)?);
+
+    let result = if name.ends_with(".zip") {
+        write_zip(&mut writer, files)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+        write_tar(&mut encoder, files).and_then(|()| Ok(encoder.finish()?.flush()?))
+    } else {
+        write_tar(&mut writer, files).and_then(|()| Ok(writer.flush()?))
+    };
+
+    result
+        .and_then(|()| writer.into_inner().map_err(|e| e.into_error())?.commit())
+        .with_context(|| format!("Failed to write archive {}", path.display()))
+}
+
+/// Path of the file inside the archive, always using `/` as the separator.
+fn entry_name(file: &ProcessedFile) -> String {
+    file.path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn write_tar(writer: &mut impl Write, files: &[ProcessedFile]) -> Result<()> {
+    let mtime = unix_now();
+    for file in files {
+        let name = entry_name(file);
+        let data = file.content.as_bytes();
+
+        let mut header = [0u8; 512];
+        let (prefix, name) = split_tar_name(&name)?;
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        write_octal(&mut header[100..108], 0o644);
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], data.len() as u64);
+        write_octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        // The checksum is computed with the checksum field itself filled with spaces
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|&b| b as u64).sum();
+        write_octal(&mut header[148..155], checksum);
+
+        writer.write_all(&header)?;
+        writer.write_all(data)?;
+        let padding = (512 - data.len() % 512) % 512;
+        writer.write_all(&vec![0; padding])?;
+    }
+    // Two empty blocks mark the end of the archive
+    writer.write_all(&[0; 1024])?;
+    Ok(())
+}
+
+/// Splits a path into the ustar prefix (up to 155 bytes) and name (up to 100 bytes) fields.
+fn split_tar_name(name: &str) -> Result<(&str, &str)> {
+    if name.len() <= 100 {
+        return Ok(("", name));
+    }
+    for (i, _) in name.match_indices('/') {
+        let (prefix, rest) = (&name[..i], &name[i + 1..]);
+        if prefix.len() <= 155 && rest.len() <= 100 {
+            return Ok((prefix, rest));
+        }
+    }
+    bail!("Path is too long to be stored in a tar archive: {name}")
+}
+
+/// Writes `value` as a NUL-terminated, zero-padded octal number filling `field`.
+fn write_octal(field: &mut [u8], value: u64) {
+    let (last, digits_field) = field.split_last_mut().expect("field is not empty");
+    let digits = format!("{value:0width$o}", width = digits_field.len());
+    digits_field.copy_from_slice(digits.as_bytes());
+    *last = 0;
+}
+
+fn write_zip(mut writer: impl Write, files: &[ProcessedFile]) -> Result<()> {
+    if files.len() > u16::MAX as usize {
+        bail!("Too many files for a zip archive ({})", files.len());
+    }
+
+    let (dos_time, dos_date) = dos_datetime(unix_now());
+    let mut central_directory = Vec::new();
+    let mut offset = 0u64;
+
+    for file in files {
+        let name = entry_name(file);
+        let data = file.content.as_bytes();
+        let crc = crc32fast::hash(data);
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        if data.len() > u32::MAX as usize || offset > u32::MAX as u64 {
+            bail!("Archive is too large for the zip format");
+        }
+
+        let mut local_header = Vec::with_capacity(30 + name.len());
+        local_header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        let common = zip_common_fields(
+            dos_time,
+            dos_date,
+            crc,
+            compressed.len() as u32,
+            data.len() as u32,
+            name.len() as u16,
+        );
+        local_header.extend_from_slice(&common);
+        local_header.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        local_header.extend_from_slice(name.as_bytes());
+
+        central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&0x0314u16.to_le_bytes()); // version made by: unix
+        central_directory.extend_from_slice(&common);
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central_directory.extend_from_slice(&(0o100644u32 << 16).to_le_bytes());
+        central_directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+
+        writer.write_all(&local_header)?;
+        writer.write_all(&compressed)?;
+        offset += (local_header.len() + compressed.len()) as u64;
+    }
+
+    if offset > u32::MAX as u64 {
+        bail!("Archive is too large for the zip format");
+    }
+
+    let mut end = Vec::with_capacity(22);
+    end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk number
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk with the central directory
+    end.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    end.extend_from_slice(&(offset as u32).to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+    writer.write_all(&central_directory)?;
+    writer.write_all(&end)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fields shared by the local file header and the central directory entry, starting at the
+/// "version needed to extract" field.
+fn zip_common_fields(
+    dos_time: u16,
+    dos_date: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    name_len: u16,
+) -> Vec<u8> {
+    let mut fields = Vec::with_capacity(24);
+    fields.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+    fields.extend_from_slice(&0x0800u16.to_le_bytes()); // flags: UTF-8 file names
+    fields.extend_from_slice(&8u16.to_le_bytes()); // compression: deflate
+    fields.extend_from_slice(&dos_time.to_le_bytes());
+    fields.extend_from_slice(&dos_date.to_le_bytes());
+    fields.extend_from_slice(&crc.to_le_bytes());
+    fields.extend_from_slice(&compressed_size.to_le_bytes());
+    fields.extend_from_slice(&size.to_le_bytes());
+    fields.extend_from_slice(&name_len.to_le_bytes());
+    fields
+}
+
+/// Converts a unix timestamp (UTC) to the MS-DOS time and date used by zip headers.
+fn dos_datetime(secs: u64) -> (u16, u16) {
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = state::civil_date(secs);
+    let year = year.clamp(1980, 2107) - 1980;
+    let date = ((year as u16) << 9) | ((month as u16) << 5) | day as u16;
+    let time = ((secs_of_day / 3600) as u16) << 11
+        | ((secs_of_day % 3600 / 60) as u16) << 5
+        | (secs_of_day % 60 / 2) as u16;
+    (time, date)
+}
@@ -0,0 +1,219 @@
+use crate::http::Request;
+use crate::json::Value;
+use crate::{tokens, BundleArgs};
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use std::io::Write;
+
+#[derive(Debug, Args)]
+pub struct AskArgs {
+    #[command(flatten)]
+    pub bundle: BundleArgs,
+
+    /// Question or instruction sent to the model after the bundled files
+    #[arg(long, short, help = "Prompt to send along with the bundle")]
+    prompt: String,
+
+    /// API flavour of the endpoint the request is sent to
+    #[arg(long, value_enum, default_value_t = Backend::Openai, help = "Choose the LLM API backend")]
+    backend: Backend,
+
+    /// Model name (defaults to a recent general purpose model of the chosen backend)
+    #[arg(long, help = "Model to use")]
+    model: Option<String>,
+
+    /// Base URL of the API, for OpenAI/Anthropic-compatible endpoints
+    #[arg(long, help = "Override the API base URL")]
+    api_base: Option<String>,
+
+    /// Maximum number of tokens the model may generate
+    #[arg(
+        long,
+        default_value_t = 4096,
+        help = "Maximum number of response tokens"
+    )]
+    max_response_tokens: usize,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum Backend {
+    /// OpenAI chat completions API (`OPENAI_API_KEY`)
+    Openai,
+    /// Anthropic messages API (`ANTHROPIC_API_KEY`)
+    Anthropic,
+    /// Local Ollama server, no API key needed
+    Ollama,
+}
+
+impl Backend {
+    fn default_model(self) -> &'static str {
+        match self {
+            Backend::Openai => "gpt-4o",
+            Backend::Anthropic => "claude-3-5-sonnet-latest",
+            Backend::Ollama => "qwen2.5-coder",
+        }
+    }
+
+    fn default_api_base(self) -> &'static str {
+        match self {
+            Backend::Openai => "https://api.openai.com/v1",
+            Backend::Anthropic => "https://api.anthropic.com/v1",
+            Backend::Ollama => "http://localhost:11434",
+        }
+    }
+
+    fn api_key_var(self) -> Option<&'static str> {
+        match self {
+            Backend::Openai => Some("OPENAI_API_KEY"),
+            Backend::Anthropic => Some("ANTHROPIC_API_KEY"),
+            Backend::Ollama => None,
+        }
+    }
+
+    fn api_key(self) -> Result<String> {
+        match self.api_key_var() {
+            Some(var) => std::env::var(var).with_context(|| format!("{var} is not set")),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+pub fn run(args: &AskArgs, bundle: &str) -> Result<()> {
+    let model = args
+        .model
+        .as_deref()
+        .unwrap_or(args.backend.default_model());
+    let api_base = args
+        .api_base
+        .as_deref()
+        .unwrap_or(args.backend.default_api_base())
+        .trim_end_matches('/');
+    let api_key = args.backend.api_key()?;
+
+    log::info!("Asking {model} at {api_base}");
+
+    let content = format!("{bundle}\n\n{}", args.prompt);
+    let prompt_tokens = tokens::estimate(&content);
+    let messages = Value::Array(vec![Value::object([
+        ("role", "user".into()),
+        ("content", content.into()),
+    ])]);
+
+    let request = match args.backend {
+        Backend::Openai => Request::post(format!("{api_base}/chat/completions"))
+            .header("Authorization", &format!("Bearer {api_key}")),
+        Backend::Anthropic => Request::post(format!("{api_base}/messages"))
+            .header("x-api-key", &api_key)
+            .header("anthropic-version", "2023-06-01"),
+        Backend::Ollama => Request::post(format!("{api_base}/api/chat")),
+    };
+    let body = match args.backend {
+        Backend::Openai | Backend::Anthropic => Value::object([
+            ("model", model.into()),
+            ("max_tokens", args.max_response_tokens.into()),
+            ("stream", true.into()),
+            ("messages", messages),
+        ]),
+        Backend::Ollama => {
+            let num_ctx =
+                ollama_num_ctx(api_base, model, prompt_tokens + args.max_response_tokens)?;
+            Value::object([
+                ("model", model.into()),
+                ("stream", true.into()),
+                ("messages", messages),
+                (
+                    "options",
+                    Value::object([
+                        ("num_predict", args.max_response_tokens.into()),
+                        ("num_ctx", num_ctx.into()),
+                    ]),
+                ),
+            ])
+        }
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    request
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .stream_lines(|line| {
+            // Ollama streams one JSON object per line, the others use server-sent events
+            let data = match args.backend {
+                Backend::Ollama => line.trim(),
+                Backend::Openai | Backend::Anthropic => match line.strip_prefix("data:") {
+                    Some(data) => data.trim(),
+                    None => return Ok(()),
+                },
+            };
+            if data.is_empty() {
+                return Ok(());
+            }
+            if data == "[DONE]" {
+                return Ok(());
+            }
+            let event = Value::parse(data).context("Invalid event in response stream")?;
+            if let Some(error) = event.get("error") {
+                bail!("API returned an error: {error}");
+            }
+            if let Some(text) = delta_text(args.backend, &event) {
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()?;
+            }
+            Ok(())
+        })?;
+    writeln!(stdout)?;
+
+    Ok(())
+}
+
+fn delta_text(backend: Backend, event: &Value) -> Option<&str> {
+    match backend {
+        Backend::Openai => event
+            .get("choices")?
+            .index(0)?
+            .get("delta")?
+            .get("content")?
+            .as_str(),
+        Backend::Anthropic => event.get("delta")?.get("text")?.as_str(),
+        Backend::Ollama => event.get("message")?.get("content")?.as_str(),
+    }
+}
+
+/// Picks the context size to request from Ollama, which otherwise silently truncates prompts to
+/// its small default context. Warns when the prompt doesn't fit the model's context length.
+fn ollama_num_ctx(api_base: &str, model: &str, needed: usize) -> Result<usize> {
+    let response = Request::post(format!("{api_base}/api/show"))
+        .header("Content-Type", "application/json")
+        .body(Value::object([("model", model.into())]).to_string())
+        .send()
+        .with_context(|| format!("Failed to query model {model} from Ollama"))?;
+    let info = Value::parse(&response).context("Invalid response from Ollama")?;
+
+    let context_length = match info.get("model_info") {
+        Some(Value::Object(members)) => members
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64()),
+        _ => None,
+    };
+
+    let Some(context_length) = context_length.map(|len| len as usize) else {
+        log::info!("Could not find the context length of {model}, requesting {needed} tokens");
+        return Ok(needed);
+    };
+
+    log::info!("Model {model} has a context length of {context_length} tokens");
+    if needed > context_length {
+        eprintln!(
+            "{}",
+            format!(
+                "Warning: the prompt needs about {needed} tokens but {model} only supports \
+                 {context_length}, it will be truncated."
+            )
+            .yellow()
+        );
+    }
+
+    Ok(needed.min(context_length))
+}
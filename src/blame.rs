@@ -0,0 +1,145 @@
+//! `--blame`: the lines of each file annotated with the commit that last changed them, from
+//! `git blame`, so the questions about who changed some logic and when come with their answer.
+
+use crate::{git, state};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How the lines are annotated
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Blame {
+    /// A `[abc1234 Jane Doe, 2024-05-01]` line before each run of lines from the same commit
+    Hunks,
+    /// Each line prefixed with the short SHA and the author of its commit
+    Lines,
+}
+
+/// The commit that last changed a line
+struct Commit {
+    sha: String,
+    author: String,
+    time: u64,
+}
+
+impl Commit {
+    /// Whether the line was changed in the working tree, and not committed yet.
+    fn uncommitted(&self) -> bool {
+        self.sha.bytes().all(|byte| byte == b'0')
+    }
+
+    fn short_sha(&self) -> &str {
+        &self.sha[..self.sha.len().min(7)]
+    }
+}
+
+/// `content` of the file at `path` (relative to `root`) annotated as `mode` says, with the
+/// commits of `rev` or of the working tree. Left as is when git knows nothing of the file, like
+/// when it isn't committed, or when its lines don't match the content.
+pub fn annotate(
+    root: &Path,
+    rev: Option<&str>,
+    path: &Path,
+    content: String,
+    mode: Blame,
+) -> String {
+    let (commits, blamed) = match blame(root, rev, path) {
+        Ok(blame) => blame,
+        Err(e) => {
+            log::debug!(path:% = path.display(); "Not blamed: {e:#}");
+            return content;
+        }
+    };
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    if lines.len() != blamed.len() {
+        log::debug!(path:% = path.display(); "Not blamed, the lines don't match the content");
+        return content;
+    }
+
+    let label = |commit: &Commit| match commit.uncommitted() {
+        true => "uncommitted".to_string(),
+        false => format!("{} {}", commit.short_sha(), commit.author),
+    };
+    let mut annotated = String::with_capacity(content.len() * 2);
+    match mode {
+        Blame::Hunks => {
+            let mut previous = None;
+            for (line, sha) in lines.iter().zip(&blamed) {
+                if previous != Some(sha) {
+                    let commit = &commits[sha];
+                    match commit.uncommitted() {
+                        true => annotated.push_str("[uncommitted]\n"),
+                        false => {
+                            let (year, month, day) = state::civil_date(commit.time);
+                            annotated.push_str(&format!(
+                                "[{}, {year:04}-{month:02}-{day:02}]\n",
+                                label(commit)
+                            ));
+                        }
+                    }
+                    previous = Some(sha);
+                }
+                annotated.push_str(line);
+            }
+        }
+        Blame::Lines => {
+            let labels: Vec<String> = blamed.iter().map(|sha| label(&commits[sha])).collect();
+            let width = labels.iter().map(|label| label.chars().count()).max();
+            for (line, label) in lines.iter().zip(&labels) {
+                annotated.push_str(&format!(
+                    "{label:<width$} | {line}",
+                    width = width.unwrap_or(0)
+                ));
+            }
+        }
+    }
+    annotated
+}
+
+/// The commits of the blame of the file at `path`, and the SHA of the commit of each line.
+fn blame(
+    root: &Path,
+    rev: Option<&str>,
+    path: &Path,
+) -> Result<(HashMap<String, Commit>, Vec<String>)> {
+    let path = llmbundle_core::paths::display(path);
+    let mut args = git::with_revision(vec!["blame", "--porcelain"], rev);
+    args.extend(["--", &path]);
+    let output = git::output(root, &args)?;
+    let output = String::from_utf8_lossy(&output);
+
+    // A header line `<sha> <line> <final line> [<lines>]` for each line, followed by the details
+    // of its commit the first time it appears, then by the line after a tab
+    let mut commits: HashMap<String, Commit> = HashMap::new();
+    let mut blamed = Vec::new();
+    let mut current: Option<String> = None;
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            blamed.extend(current.clone());
+            continue;
+        }
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        // SHA-1, or SHA-256 in the repositories using it
+        if matches!(key.len(), 40 | 64) && key.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            commits.entry(key.to_string()).or_insert_with(|| Commit {
+                sha: key.to_string(),
+                author: String::new(),
+                time: 0,
+            });
+            current = Some(key.to_string());
+            continue;
+        }
+        let Some(commit) = current.as_ref().and_then(|sha| commits.get_mut(sha)) else {
+            continue;
+        };
+        match key {
+            "author" => commit.author = value.to_string(),
+            "author-time" => commit.time = value.parse().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    Ok((commits, blamed))
+}
@@ -0,0 +1,509 @@
+use crate::failure::Failure;
+use crate::json::Value;
+use crate::progress::Progress;
+use crate::timings::{self, Phase};
+use crate::{blame, commits, errors, git, git_log, interrupt, lfs, plugin, BundleArgs};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use llmbundle_core::{paths, tokens, Bundler, FileTimings, Observer, ReadCache};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Default `--max-files`
+pub const MAX_FILES: usize = 5000;
+
+/// Threads walking and reading with `--jobs`
+static JOBS: OnceLock<usize> = OnceLock::new();
+
+/// Longest a file may take to be looked up or read, with `--timeout`
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+pub use llmbundle_core::{
+    build_glob_sets, corrupted, parse, resolve_pattern, Bundle, Conflicts, Format, ProcessedFile,
+    Sort,
+};
+
+/// The bundler configured by `args`, showing its progress and stopping on Ctrl-C.
+pub fn bundler(args: &BundleArgs) -> Bundler {
+    let bundler = Bundler::new(&args.root)
+        .patterns(&args.patterns)
+        .max_depth(args.max_depth)
+        .max_tokens(args.max_tokens)
+        .format(args.format)
+        .checksums(args.checksums)
+        .strict(args.strict)
+        .sort(args.sort, args.reverse)
+        .group_by_dir(args.group_by_dir)
+        .conflicts(args.conflicts)
+        .threads(JOBS.get().copied())
+        .timeout(TIMEOUT.get().copied())
+        .observer(Feedback::default());
+    if !transforms(args) {
+        return bundler;
+    }
+    // Fetched first, then blamed, as the plugins may change the lines
+    let (root, rev, blame) = (PathBuf::from(&args.root), revision(args), args.blame);
+    let (lfs_fetch, strict) = (args.lfs_fetch, args.strict);
+    bundler.transform(move |path, content| {
+        let content = match lfs_fetch {
+            true => lfs::fetch(&root, path, content, strict),
+            false => content,
+        };
+        let content = match blame {
+            Some(mode) => blame::annotate(&root, rev.as_deref(), path, content, mode),
+            None => content,
+        };
+        match plugin::transforms() {
+            true => plugin::transform(path, content),
+            false => Ok(content),
+        }
+    })
+}
+
+/// The bundler of `files` configured by `args`, noting the LFS pointers in their header, and the
+/// last commit of each file with `--with-git-log`.
+pub fn files_bundler(args: &BundleArgs, files: &[PathBuf]) -> Result<Bundler> {
+    let commits = match args.with_git_log {
+        true => Some(
+            git_log::headers(Path::new(&args.root), revision(args).as_deref(), files)
+                .context("Failed to find the last commit of the files")?,
+        ),
+        false => None,
+    };
+    let diff = args
+        .between
+        .as_ref()
+        .filter(|_| args.diff)
+        .map(|range| range.to_string());
+    Ok(bundler(args).header(move |path, content| {
+        let lines: Vec<String> = [
+            diff.as_ref().map(|range| format!("[file diff]: {range}")),
+            lfs::note(content),
+            commits
+                .as_ref()
+                .and_then(|commits| commits.get(path).cloned()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        Some(lines.join("\n")).filter(|lines| !lines.is_empty())
+    }))
+}
+
+/// Whether the files are read from git rather than from the working tree, with `--rev`,
+/// `--between` or `--commits`.
+pub fn from_git(args: &BundleArgs) -> bool {
+    args.rev.is_some() || args.between.is_some() || args.commits.is_some()
+}
+
+/// The revision the files are read from: the one of `--rev`, or the second one of `--between`.
+pub fn revision(args: &BundleArgs) -> Option<String> {
+    match &args.between {
+        Some(range) => Some(range.to.clone()),
+        None => args.rev.clone(),
+    }
+}
+
+/// Whether the content of the files is changed once read, by `--lfs-fetch`, `--blame` or a
+/// plugin, so their counts need them read.
+pub fn transforms(args: &BundleArgs) -> bool {
+    args.lfs_fetch || args.blame.is_some() || plugin::transforms()
+}
+
+/// Walks the directories and reads the files with `jobs` threads, before any bundling.
+pub fn set_jobs(jobs: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build_global()
+        .context("Failed to start the reading threads")?;
+    let _ = JOBS.set(jobs);
+    Ok(())
+}
+
+/// Skips the files and directories taking longer than `timeout` to be looked up or read.
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+/// Parses a `--timeout` like `500ms`, `10s` or `2m`, in seconds without a unit.
+pub fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let (amount, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid timeout: {s}"))?;
+    let timeout = match unit {
+        "ms" => Duration::from_millis(amount),
+        "s" | "" => Duration::from_secs(amount),
+        "m" => Duration::from_secs(amount * 60),
+        _ => return Err(format!("invalid timeout unit in {s}, expected ms, s or m")),
+    };
+    if timeout.is_zero() {
+        return Err("the timeout can't be zero".to_string());
+    }
+    Ok(timeout)
+}
+
+/// Progress lines of the walk and the reads, and Ctrl-C
+#[derive(Default)]
+struct Feedback {
+    scanning: OnceLock<Progress>,
+    reading: OnceLock<Progress>,
+}
+
+impl Observer for Feedback {
+    fn found(&self, _path: &Path) {
+        self.scanning
+            .get_or_init(|| Progress::new("Scanning", None))
+            .inc(0);
+    }
+
+    fn walked(&self) {
+        if let Some(progress) = self.scanning.get() {
+            progress.finish();
+        }
+    }
+
+    fn reading(&self, count: usize) {
+        timings::start(Phase::Reading);
+        let _ = self.reading.set(Progress::new("Reading", Some(count)));
+    }
+
+    fn read(&self, _path: &Path, bytes: u64) {
+        if let Some(progress) = self.reading.get() {
+            progress.inc(bytes);
+        }
+    }
+
+    fn processed(&self, path: &Path, file: &FileTimings) {
+        timings::file(path, file);
+    }
+
+    fn failed(&self, path: &Path, error: &str) {
+        errors::record(path, error);
+    }
+
+    fn done_reading(&self) {
+        if let Some(progress) = self.reading.get() {
+            progress.finish();
+        }
+        timings::stop(Phase::Reading);
+        timings::start(Phase::Format);
+    }
+
+    fn rendered(&self) {
+        timings::stop(Phase::Format);
+    }
+
+    fn cancelled(&self) -> bool {
+        interrupt::interrupted()
+    }
+}
+
+/// Finds the files under the root matching `args`, relative to the root.
+pub fn find_files(args: &BundleArgs) -> Result<Vec<PathBuf>> {
+    // Checked once rather than failing on every file
+    if args.blame.is_some() || args.with_git_log {
+        let flag = match args.blame {
+            Some(_) => "--blame",
+            None => "--with-git-log",
+        };
+        git::output(Path::new(&args.root), &["rev-parse", "--git-dir"])
+            .with_context(|| format!("{flag} needs the root to be in a git repository"))?;
+    }
+    select(args, &walk(args)?)
+}
+
+/// Lists every file under the root that isn't ignored, relative to the root, or the files of the
+/// revision given with `--rev`.
+pub fn walk(args: &BundleArgs) -> Result<Vec<PathBuf>> {
+    let root = Path::new(&args.root);
+    timings::time(Phase::Walk, || {
+        match (&args.commits, &args.between, &args.rev) {
+            (Some(range), _, _) => commits::files(root, range, args.max_depth),
+            (None, Some(range), _) => git::changed(root, range, args.diff, args.max_depth),
+            (None, None, Some(rev)) => git::files(root, rev, args.max_depth),
+            (None, None, None) => bundler(args).walk(),
+        }
+    })
+}
+
+/// Keeps the `candidates` matching the patterns of `args`.
+pub fn select(args: &BundleArgs, candidates: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let files = timings::time(Phase::Filter, || bundler(args).select(candidates))
+        .context(Failure::Pattern)?;
+    let files = plugin::filter(files)?;
+    // The pointers of a revision are told once read
+    Ok(match args.lfs {
+        lfs::Lfs::Skip if !from_git(args) => lfs::without_pointers(Path::new(&args.root), files),
+        _ => files,
+    })
+}
+
+/// Finds the files matching `args` and renders them into a single bundle.
+pub fn bundle(args: &BundleArgs) -> Result<Bundle> {
+    let files = find_files(args)?;
+    confirm_count(args, &files)?;
+    checked(args, bundle_files(args, &files)?)
+}
+
+/// Asks whether to go on when more `files` than `--max-files` matched, failing if not, or warns
+/// when there is no one to ask.
+pub fn confirm_count(args: &BundleArgs, files: &[PathBuf]) -> Result<()> {
+    use std::io::IsTerminal;
+    if args.max_files == 0 || files.len() <= args.max_files {
+        return Ok(());
+    }
+    let count = separated(files.len());
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        log::warn!(
+            "The patterns matched {count} files, more than --max-files {}.",
+            args.max_files
+        );
+        return Ok(());
+    }
+    eprint!("The patterns matched {count} files, continue? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        bail!(
+            "Cancelled bundling {count} files (more than --max-files {})",
+            args.max_files
+        );
+    }
+    Ok(())
+}
+
+/// `count` with its thousands separated by commas.
+fn separated(count: usize) -> String {
+    let digits = count.to_string();
+    let mut text = String::with_capacity(digits.len() * 4 / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            text.push(',');
+        }
+        text.push(digit);
+    }
+    text
+}
+
+/// Like [`bundle`], reading only the files that changed since they were put in `cache`.
+pub fn bundle_cached(args: &BundleArgs, cache: &Arc<ReadCache>) -> Result<Bundle> {
+    let files = find_files(args)?;
+    let bundler = files_bundler(args, &files)?;
+    // The last commits change without the files
+    let bundler = match args.with_git_log {
+        true => bundler,
+        false => bundler.cache(cache.clone()),
+    };
+    checked(args, bundler.bundle_files(&files))
+}
+
+/// `bundle`, or the error listing its unreadable files with `--strict`.
+fn checked(args: &BundleArgs, bundle: Bundle) -> Result<Bundle> {
+    if args.strict && !bundle.unreadable.is_empty() {
+        return Err(unreadable_error(&bundle.unreadable));
+    }
+    Ok(bundle)
+}
+
+/// Error listing the files that couldn't be read.
+pub fn unreadable_error(files: &[PathBuf]) -> anyhow::Error {
+    let files: Vec<String> = files.iter().map(|path| paths::display(path)).collect();
+    anyhow::anyhow!("Failed to read {}", files.join(", ")).context(Failure::Unreadable)
+}
+
+/// Renders the given files (relative to the root) into a single bundle, as they are at the
+/// revision of `--rev` or `--between` if given, their diffs with `--between --diff`, or the
+/// commits changing them with `--commits`.
+pub fn bundle_files(args: &BundleArgs, matching_files: &[PathBuf]) -> Result<Bundle> {
+    let root = Path::new(&args.root);
+    let read = |contents: Vec<Result<Vec<u8>, String>>| {
+        matching_files
+            .iter()
+            .cloned()
+            .zip(contents)
+            .collect::<Vec<_>>()
+    };
+    let contents = match (&args.commits, &args.between, revision(args)) {
+        (Some(range), _, _) => timings::time(Phase::Reading, || {
+            commits::patches(root, range, matching_files)
+        })?,
+        (None, Some(range), _) if args.diff => read(timings::time(Phase::Reading, || {
+            git::diffs(root, range, matching_files)
+        })?),
+        (None, _, Some(rev)) => read(timings::time(Phase::Reading, || {
+            git::read(root, &rev, matching_files)
+        })?),
+        (None, _, None) => {
+            return Ok(files_bundler(args, matching_files)?.bundle_files(matching_files))
+        }
+    };
+    let mut unreadable = Vec::new();
+    let files = contents.into_iter().map(|(path, content)| {
+        let content = content.and_then(|bytes| llmbundle_core::decode_file(bytes, args.strict));
+        let content = content.unwrap_or_else(|e| {
+            errors::record(&path, &e);
+            unreadable.push(path.clone());
+            String::new()
+        });
+        (path, content)
+    });
+    let mut files: Vec<_> = files.collect();
+    if args.lfs == lfs::Lfs::Skip {
+        let before = files.len();
+        files.retain(|(_, content)| !lfs::is_pointer(content));
+        if files.len() < before {
+            log::info!("Left out {} LFS pointers", before - files.len());
+        }
+    }
+    let bundler = files_bundler(args, matching_files)?;
+    // The patches were made of the files matching the patterns, which their names don't
+    let bundler = match args.commits {
+        Some(_) => bundler.patterns(Vec::<String>::new()),
+        None => bundler,
+    };
+    let mut bundle = bundler.bundle_virtual(files)?;
+    bundle.unreadable.append(&mut unreadable);
+    bundle.unreadable.sort();
+    Ok(bundle)
+}
+
+/// The files of the bundle with their size, the totals and the files left out, as JSON.
+pub fn summary(bundle: &Bundle) -> Value {
+    let files = bundle.files.iter().map(|file| {
+        Value::object([
+            ("path", paths::display(&file.path).into()),
+            ("bytes", file.formatted.len().into()),
+            ("lines", file.line_count.into()),
+            ("words", file.word_count.into()),
+            ("tokens", file.token_count.into()),
+        ])
+    });
+    let dropped: Vec<String> = bundle
+        .dropped
+        .iter()
+        .map(|path| paths::display(path))
+        .collect();
+    let counts = tokens::count(&bundle.text);
+    Value::object([
+        ("files", Value::Array(files.collect())),
+        (
+            "totals",
+            Value::object([
+                ("files", bundle.files.len().into()),
+                ("bytes", bundle.text.len().into()),
+                ("lines", counts.lines.into()),
+                ("words", counts.words.into()),
+                (
+                    "tokens",
+                    bundle
+                        .files
+                        .iter()
+                        .map(|file| file.token_count)
+                        .sum::<usize>()
+                        .into(),
+                ),
+            ]),
+        ),
+        ("dropped", dropped.into()),
+        ("max_tokens", bundle.max_tokens.into()),
+        ("errors", errors::json()),
+    ])
+}
+
+/// Warns about the files left out to stay within the token budget.
+pub fn warn_dropped(bundle: &Bundle) {
+    warn_left_out(&bundle.dropped, bundle.max_tokens);
+}
+
+/// Warns about the `dropped` files, left out to stay within `max_tokens`.
+pub fn warn_left_out(dropped: &[PathBuf], max_tokens: Option<usize>) {
+    if dropped.is_empty() {
+        return;
+    }
+    let message = format!(
+        "Left out {} files to stay within the budget of {} tokens:",
+        dropped.len(),
+        max_tokens.unwrap_or_default()
+    );
+    eprintln!("{}", message.yellow());
+    for path in dropped {
+        eprintln!("{} {}", "-".yellow(), paths::display(path));
+    }
+}
+
+/// Joins a path taken from a bundle onto `root`, refusing absolute paths and paths that would
+/// escape the root, through `..` or through a symlink already in the tree.
+pub fn resolve(root: &Path, path: &Path) -> Result<PathBuf> {
+    use std::path::Component;
+
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        bail!(
+            "Refusing to write outside the root: {}",
+            paths::display(path)
+        );
+    }
+    let target = root.join(path);
+    if !real_path(&target)?.starts_with(real_path(root)?) {
+        bail!(
+            "Refusing to write outside the root: {}",
+            paths::display(path)
+        );
+    }
+    Ok(target)
+}
+
+/// `path` with its deepest existing ancestor canonicalized, so the symlinks on the way are
+/// followed, and the part that doesn't exist yet appended as is.
+fn real_path(path: &Path) -> Result<PathBuf> {
+    // `symlink_metadata` rather than `exists`, so a dangling symlink fails to canonicalize
+    // instead of being taken for a file still to be created
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.symlink_metadata().is_ok())
+        .unwrap_or(Path::new("."));
+    let real = existing
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", paths::display(existing)))?;
+    let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+    Ok(real.join(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_to_write_through_a_symlink_out_of_the_root() {
+        let dir = std::env::temp_dir().join(format!("llmbundle-resolve-{}", std::process::id()));
+        let (root, outside) = (dir.join("root"), dir.join("outside"));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::os::unix::fs::symlink(root.join("src"), root.join("inside")).unwrap();
+
+        let escaping = resolve(&root, Path::new("link/file.txt"));
+        let nested = resolve(&root, Path::new("link/new/file.txt"));
+        let within = resolve(&root, Path::new("inside/new/file.txt"));
+        let missing = resolve(&root, Path::new("src/new/file.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(escaping.is_err() && nested.is_err());
+        assert_eq!(within.unwrap(), root.join("inside/new/file.txt"));
+        assert_eq!(missing.unwrap(), root.join("src/new/file.txt"));
+    }
+
+    #[test]
+    fn refuses_paths_that_leave_the_root() {
+        let root = Path::new(".");
+        assert!(resolve(root, Path::new("../file.txt")).is_err());
+        assert!(resolve(root, Path::new("/etc/passwd")).is_err());
+        assert!(resolve(root, Path::new("")).is_err());
+    }
+}
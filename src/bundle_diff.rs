@@ -0,0 +1,94 @@
+//! Compares two bundles file by file, to tell a model what changed since the last message.
+
+use crate::unbundle::read_source;
+use crate::{bundle, diff, parse, saved, Cli};
+use anyhow::{bail, Result};
+use clap::Args;
+use colored::Colorize;
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Older bundle: a file, `-` for stdin or `clipboard`
+    #[arg(required_unless_present = "against")]
+    old: Option<String>,
+
+    /// Newer bundle: a file, `-` for stdin or `clipboard`
+    #[arg(required_unless_present = "against")]
+    new: Option<String>,
+
+    /// Compare the output stored with `save --render` to a fresh bundle of the same definition
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["old", "new"], help = "Compare against a saved bundle")]
+    against: Option<String>,
+
+    /// Also print a unified diff of each changed file
+    #[arg(long, short, help = "Show the changes within files")]
+    patch: bool,
+}
+
+pub fn run(args: &DiffArgs) -> Result<()> {
+    let (old, new) = match (&args.against, &args.old, &args.new) {
+        (Some(name), _, _) => {
+            let cli = Cli::parse_args(saved::arguments(name)?)?;
+            if cli.command.is_some() {
+                bail!("Saved bundle {name} runs a subcommand, it can't be compared");
+            }
+            (
+                saved::rendered(name)?,
+                bundle::bundle(&cli.run.bundle)?.text,
+            )
+        }
+        (None, Some(old), Some(new)) => (read_source(old)?, read_source(new)?),
+        _ => unreachable!("clap requires both bundles without --against"),
+    };
+
+    let old_files = parse::files(&old);
+    let new_files = parse::files(&new);
+    let find = |files: &[(PathBuf, String)], path: &PathBuf| {
+        files
+            .iter()
+            .find(|(other, _)| other == path)
+            .map(|(_, content)| content.clone())
+    };
+
+    let mut changes = 0;
+    for (path, content) in &new_files {
+        match find(&old_files, path) {
+            None => {
+                changes += 1;
+                println!("{} {}", "+".green(), path.display());
+            }
+            Some(old_content) if old_content != *content => {
+                changes += 1;
+                let ops = diff::diff(&diff::lines(&old_content), &diff::lines(content));
+                let count = |op| ops.iter().filter(|(other, _)| *other == op).count();
+                let info = format!(
+                    "(+{} -{} lines)",
+                    count(diff::Op::Insert),
+                    count(diff::Op::Delete)
+                )
+                .black();
+                println!("{} {} {info}", "~".yellow(), path.display());
+                if args.patch {
+                    println!("{}", format!("--- a/{}", path.display()).bold());
+                    println!("{}", format!("+++ b/{}", path.display()).bold());
+                    for hunk in diff::hunks(&ops, 3) {
+                        hunk.print();
+                    }
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, _) in &old_files {
+        if find(&new_files, path).is_none() {
+            changes += 1;
+            println!("{} {}", "-".red(), path.display());
+        }
+    }
+
+    if changes == 0 {
+        println!("{}", "The bundles have the same files.".blue());
+    }
+    Ok(())
+}
@@ -0,0 +1,322 @@
+use crate::pipe;
+use anyhow::{bail, Context, Result};
+use arboard::Clipboard;
+use clap::ValueEnum;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Once;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum ClipboardSelection {
+    /// The regular clipboard used by explicit copy/paste
+    Clipboard,
+    /// The X11/Wayland primary selection used by middle-click paste
+    Primary,
+    /// Both the clipboard and the primary selection
+    Both,
+}
+
+impl ClipboardSelection {
+    fn clipboard(self) -> bool {
+        matches!(
+            self,
+            ClipboardSelection::Clipboard | ClipboardSelection::Both
+        )
+    }
+
+    fn primary(self) -> bool {
+        matches!(self, ClipboardSelection::Primary | ClipboardSelection::Both)
+    }
+}
+
+struct FallbackTool {
+    program: &'static str,
+    clipboard_args: &'static [&'static str],
+    /// Arguments to target the primary selection, `None` if the tool doesn't support it
+    primary_args: Option<&'static [&'static str]>,
+}
+
+/// External clipboard tools tried in order when arboard is unavailable.
+const FALLBACK_TOOLS: &[FallbackTool] = &[
+    FallbackTool {
+        program: "wl-copy",
+        clipboard_args: &[],
+        primary_args: Some(&["--primary"]),
+    },
+    FallbackTool {
+        program: "xclip",
+        clipboard_args: &["-selection", "clipboard"],
+        primary_args: Some(&["-selection", "primary"]),
+    },
+    FallbackTool {
+        program: "xsel",
+        clipboard_args: &["--clipboard", "--input"],
+        primary_args: Some(&["--primary", "--input"]),
+    },
+    FallbackTool {
+        program: "pbcopy",
+        clipboard_args: &[],
+        primary_args: None,
+    },
+];
+
+/// External tools tried in order to read the clipboard when arboard is unavailable.
+const FALLBACK_PASTE_TOOLS: &[FallbackTool] = &[
+    FallbackTool {
+        program: "wl-paste",
+        clipboard_args: &["--no-newline"],
+        primary_args: Some(&["--primary", "--no-newline"]),
+    },
+    FallbackTool {
+        program: "xclip",
+        clipboard_args: &["-selection", "clipboard", "-o"],
+        primary_args: Some(&["-selection", "primary", "-o"]),
+    },
+    FallbackTool {
+        program: "xsel",
+        clipboard_args: &["--clipboard", "--output"],
+        primary_args: Some(&["--primary", "--output"]),
+    },
+    FallbackTool {
+        program: "pbpaste",
+        clipboard_args: &[],
+        primary_args: None,
+    },
+];
+
+/// Copies `text` to the clipboard, along with `html` as a rich-text flavor if given.
+///
+/// If `command` is set, the text is piped to that command instead. Otherwise arboard is used,
+/// falling back to the first external clipboard tool found in `PATH` if it can't be initialized.
+/// The HTML flavor is only supported through arboard.
+pub fn copy(
+    text: String,
+    html: Option<String>,
+    selection: ClipboardSelection,
+    command: Option<&str>,
+) -> Result<()> {
+    if html.is_some() && command.is_some() {
+        eprintln!("The HTML clipboard flavor is not supported with --clipboard-cmd, copying plain text only.");
+    }
+    if let Some(command) = command {
+        if command.trim().is_empty() {
+            bail!("Clipboard command is empty");
+        }
+        // Through the shell, so quoted arguments and pipes work as typed
+        return pipe_to(pipe::shell_command(command), command, &text);
+    }
+
+    match Clipboard::new() {
+        Ok(mut clipboard) => copy_with_arboard(&mut clipboard, text, html, selection),
+        Err(err) => {
+            eprintln!("Failed to initialize clipboard ({err}), trying external clipboard tools.");
+            if html.is_some() {
+                eprintln!("The HTML clipboard flavor is not supported by external tools, copying plain text only.");
+            }
+            copy_with_fallback_tool(&text, selection)
+        }
+    }
+}
+
+fn copy_with_arboard(
+    clipboard: &mut Clipboard,
+    text: String,
+    html: Option<String>,
+    selection: ClipboardSelection,
+) -> Result<()> {
+    match selection {
+        ClipboardSelection::Clipboard => set_clipboard(clipboard, text, html),
+        ClipboardSelection::Primary => set_primary(clipboard, text, html),
+        ClipboardSelection::Both => {
+            set_clipboard(clipboard, text.clone(), html.clone())?;
+            set_primary(clipboard, text, html)
+        }
+    }
+}
+
+fn set_clipboard(clipboard: &mut Clipboard, text: String, html: Option<String>) -> Result<()> {
+    match html {
+        Some(html) => clipboard
+            .set_html(html, Some(text))
+            .context("Failed to set clipboard HTML"),
+        None => clipboard
+            .set_text(text)
+            .context("Failed to set clipboard text"),
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn set_primary(clipboard: &mut Clipboard, text: String, html: Option<String>) -> Result<()> {
+    use arboard::{LinuxClipboardKind, SetExtLinux};
+
+    let set = clipboard.set().clipboard(LinuxClipboardKind::Primary);
+    match html {
+        Some(html) => set
+            .html(html, Some(text))
+            .context("Failed to set primary selection HTML"),
+        None => set
+            .text(text)
+            .context("Failed to set primary selection text"),
+    }
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn set_primary(clipboard: &mut Clipboard, text: String, html: Option<String>) -> Result<()> {
+    eprintln!("Primary selection is not supported on this platform, using the clipboard instead.");
+    set_clipboard(clipboard, text, html)
+}
+
+/// Reads the text currently on the clipboard, or on the primary selection if only that one is
+/// selected. An empty clipboard reads as an empty string.
+pub fn read(selection: ClipboardSelection, command: Option<&str>) -> Result<String> {
+    if command.is_some() {
+        bail!("Reading the clipboard is not supported with --clipboard-cmd");
+    }
+
+    let primary = selection == ClipboardSelection::Primary;
+    match Clipboard::new() {
+        Ok(mut clipboard) => {
+            let text = if primary {
+                get_primary(&mut clipboard)
+            } else {
+                clipboard.get_text()
+            };
+            match text {
+                Ok(text) => Ok(text),
+                Err(arboard::Error::ContentNotAvailable) => Ok(String::new()),
+                Err(e) => Err(e).context("Failed to read the clipboard"),
+            }
+        }
+        Err(err) => {
+            // Only once, as the clipboard may be polled
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                eprintln!(
+                    "Failed to initialize clipboard ({err}), trying external clipboard tools."
+                )
+            });
+            let tool = find_fallback_tool(FALLBACK_PASTE_TOOLS)?;
+            let args = match tool.primary_args {
+                Some(primary_args) if primary => primary_args,
+                _ => tool.clipboard_args,
+            };
+            let output = Command::new(tool.program)
+                .args(args)
+                .stderr(Stdio::null())
+                .output()
+                .with_context(|| format!("Failed to run clipboard command: {}", tool.program))?;
+            // The tools fail when the clipboard is empty, which is not an error here
+            if !output.status.success() {
+                return Ok(String::new());
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+fn get_primary(clipboard: &mut Clipboard) -> Result<String, arboard::Error> {
+    use arboard::{GetExtLinux, LinuxClipboardKind};
+
+    clipboard
+        .get()
+        .clipboard(LinuxClipboardKind::Primary)
+        .text()
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn get_primary(clipboard: &mut Clipboard) -> Result<String, arboard::Error> {
+    eprintln!("Primary selection is not supported on this platform, using the clipboard instead.");
+    clipboard.get_text()
+}
+
+fn find_fallback_tool(tools: &'static [FallbackTool]) -> Result<&'static FallbackTool> {
+    tools
+        .iter()
+        .find(|tool| find_in_path(tool.program).is_some())
+        .with_context(|| {
+            format!(
+                "No clipboard available and none of the fallback tools were found: {}",
+                tools
+                    .iter()
+                    .map(|tool| tool.program)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+fn copy_with_fallback_tool(text: &str, selection: ClipboardSelection) -> Result<()> {
+    let tool = find_fallback_tool(FALLBACK_TOOLS)?;
+
+    if selection.clipboard() {
+        pipe_to_command(tool.program, tool.clipboard_args, text)?;
+    }
+    if selection.primary() {
+        match tool.primary_args {
+            Some(primary_args) => pipe_to_command(tool.program, primary_args, text)?,
+            None => {
+                eprintln!(
+                    "{} does not support the primary selection, using the clipboard instead.",
+                    tool.program
+                );
+                if !selection.clipboard() {
+                    pipe_to_command(tool.program, tool.clipboard_args, text)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let program = format!("{program}{}", std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&program))
+        .find(|path| path.is_file())
+}
+
+fn pipe_to_command(program: &str, args: &[&str], text: &str) -> Result<()> {
+    let mut command = Command::new(program);
+    command.args(args);
+    pipe_to(command, program, text)
+}
+
+/// Runs `command`, named `program` in the errors, with `text` on its stdin.
+fn pipe_to(mut command: Command, program: &str, text: &str) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run clipboard command: {program}"))?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())
+        .with_context(|| format!("Failed to write to clipboard command: {program}"))?;
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for clipboard command: {program}"))?;
+    if !status.success() {
+        bail!("Clipboard command {program} exited with {status}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! `--commits`: the commits of a range in the order they were made, each as its message and its
+//! diff, so the model follows how a change came to be, for changelogs or reviewing the history
+//! of a pull request.
+
+use crate::git::{self, Range};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Longest part of the subject in the name of a commit, like `git format-patch`
+const MAX_SLUG: usize = 52;
+
+/// The name of the patch of a commit, and the patch or why it couldn't be shown
+pub type Patch = (PathBuf, Result<Vec<u8>, String>);
+
+/// A commit of the range, and the files under the root it changes
+struct Commit {
+    sha: String,
+    subject: String,
+    files: Vec<PathBuf>,
+}
+
+/// Lists the files under `root` the commits of `range` change, relative to the root, leaving out
+/// the hidden ones like the walk does.
+pub fn files(root: &Path, range: &Range, max_depth: Option<usize>) -> Result<Vec<PathBuf>> {
+    let mut seen = HashSet::new();
+    let files = log(root, range)?
+        .into_iter()
+        .flat_map(|commit| commit.files)
+        .filter(|path| max_depth.is_none_or(|depth| path.components().count() <= depth))
+        .filter(|path| seen.insert(path.clone()))
+        .collect();
+    Ok(files)
+}
+
+/// Each commit of `range` changing some of `files` (relative to `root`), oldest first, named like
+/// the patches of `git format-patch`: its message then its diff of those files, or why it
+/// couldn't be shown. Merges are left out, their changes being the ones of the merged commits.
+pub fn patches(root: &Path, range: &Range, files: &[PathBuf]) -> Result<Vec<Patch>> {
+    let files: HashSet<&PathBuf> = files.iter().collect();
+    let commits: Vec<(Commit, Vec<&PathBuf>)> = log(root, range)?
+        .into_iter()
+        .filter_map(|commit| {
+            let matched: Vec<_> = commit
+                .files
+                .iter()
+                .filter_map(|path| files.get(path).copied())
+                .collect();
+            (!matched.is_empty()).then_some((commit, matched))
+        })
+        .collect();
+    let patches = commits
+        .par_iter()
+        .enumerate()
+        .map(|(i, (commit, paths))| {
+            let slug = match slug(&commit.subject) {
+                slug if slug.is_empty() => commit.sha.chars().take(7).collect(),
+                slug => slug,
+            };
+            let name = PathBuf::from(format!("{:04}-{slug}.patch", i + 1));
+            let pathspecs: Vec<String> = paths
+                .iter()
+                .map(|path| format!(":(literal){}", llmbundle_core::paths::display(path)))
+                .collect();
+            let mut args = vec![
+                "show",
+                "--format=medium",
+                "--no-color",
+                "--no-ext-diff",
+                "--no-renames",
+                "--relative",
+                &commit.sha,
+                "--",
+            ];
+            args.extend(pathspecs.iter().map(String::as_str));
+            let patch = git::output(root, &args)
+                .map_err(|e| format!("Failed to show the commit {}: {e:#}", commit.sha));
+            (name, patch)
+        })
+        .collect();
+    Ok(patches)
+}
+
+/// The commits of `range` changing files under `root`, oldest first, merges left out.
+fn log(root: &Path, range: &Range) -> Result<Vec<Commit>> {
+    let (from, to) = range.commits(root)?;
+    let revisions = format!("{from}..{to}");
+    // `\x01<sha>\0<subject>\0\n<path>\0<path>\0...` for each commit
+    let listing = git::output(
+        root,
+        &[
+            "log",
+            "--reverse",
+            "--no-merges",
+            "--no-renames",
+            "--format=%x01%H%x00%s",
+            "--name-only",
+            "-z",
+            "--relative",
+            &revisions,
+            "--",
+            ".",
+        ],
+    )
+    .with_context(|| format!("Failed to list the commits of {range}"))?;
+    let listing = String::from_utf8_lossy(&listing);
+    let commits = listing
+        .split('\x01')
+        .filter_map(|record| {
+            let mut fields = record.split('\0');
+            let (sha, subject) = (fields.next()?, fields.next()?);
+            let files = fields
+                .map(|path| path.trim_start_matches('\n'))
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .filter(|path| !git::hidden(path))
+                .collect();
+            Some(Commit {
+                sha: sha.to_string(),
+                subject: subject.to_string(),
+                files,
+            })
+        })
+        .collect();
+    Ok(commits)
+}
+
+/// `subject` as the name of a file: its words in lowercase, joined by dashes.
+fn slug(subject: &str) -> String {
+    let words: Vec<String> = subject
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let slug = words.join("-");
+    match slug.char_indices().nth(MAX_SLUG) {
+        Some((end, _)) => slug[..end].trim_end_matches('-').to_string(),
+        None => slug,
+    }
+}
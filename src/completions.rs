@@ -0,0 +1,414 @@
+//! Shell completion scripts generated from the definition of the command line, completing the
+//! names of profiles, aliases and saved bundles by calling back into `llmbundle __complete`.
+
+use crate::config::Config;
+use crate::{saved, Cli};
+use anyhow::Result;
+use clap::{Arg, ArgAction, Args, CommandFactory, ValueEnum};
+use std::fmt::Write;
+use std::path::Path;
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+#[derive(Debug, Args)]
+pub struct CompleteArgs {
+    /// Kind of names to list
+    #[arg(value_enum)]
+    names: Names,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Names only known at runtime, completed by the scripts calling `llmbundle __complete <names>`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum Names {
+    /// Profiles of the configuration
+    Profiles,
+    /// Aliases of the configuration
+    Aliases,
+    /// Bundles saved with `save`
+    Bundles,
+}
+
+pub fn run(args: &CompletionsArgs) -> Result<()> {
+    let commands = commands();
+    let script = match args.shell {
+        Shell::Bash => bash(&commands),
+        Shell::Zsh => zsh(&commands),
+        Shell::Fish => fish(&commands),
+    };
+    print!("{script}");
+    Ok(())
+}
+
+pub fn complete(args: &CompleteArgs) -> Result<()> {
+    let names = match args.names {
+        Names::Profiles => config()?.profiles(),
+        Names::Aliases => config()?.aliases(),
+        Names::Bundles => saved::names(),
+    };
+    for name in names {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+fn config() -> Result<Config> {
+    Config::find(Path::new(&crate::default_root()))
+}
+
+/// A command of the command line, the top-level one having an empty name.
+struct Command {
+    name: String,
+    about: String,
+    options: Vec<Arg>,
+    /// Names completing the positional arguments, instead of files
+    positional: Option<Names>,
+}
+
+fn commands() -> Vec<Command> {
+    let mut cli = Cli::command();
+    cli.build();
+    let visible = |command: &&clap::Command| !command.is_hide_set() && command.get_name() != "help";
+    let root = Command {
+        name: String::new(),
+        about: String::new(),
+        options: options(&cli),
+        positional: None,
+    };
+    std::iter::once(root)
+        .chain(cli.get_subcommands().filter(visible).map(|command| {
+            Command {
+                name: command.get_name().to_string(),
+                about: command
+                    .get_about()
+                    .map(|about| about.to_string())
+                    .unwrap_or_default(),
+                options: options(command),
+                positional: (command.get_name() == "load").then_some(Names::Bundles),
+            }
+        }))
+        .collect()
+}
+
+fn options(command: &clap::Command) -> Vec<Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .cloned()
+        .collect()
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+/// Possible values of an option, with their help.
+fn values(arg: &Arg) -> Vec<(String, String)> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| {
+            let help = value.get_help().map(|help| help.to_string());
+            (value.get_name().to_string(), help.unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Names completing the value of an option.
+fn dynamic(arg: &Arg) -> Option<Names> {
+    (arg.get_id() == "profile").then_some(Names::Profiles)
+}
+
+fn help(arg: &Arg) -> String {
+    let help = arg
+        .get_help()
+        .map(|help| help.to_string())
+        .unwrap_or_default();
+    // Only the first line, the rest is the list of possible values
+    help.lines().next().unwrap_or_default().to_string()
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    let short = arg.get_short().map(|short| format!("-{short}"));
+    let long = arg.get_long().map(|long| format!("--{long}"));
+    short.into_iter().chain(long).collect()
+}
+
+fn names_command(names: Names) -> String {
+    let names = names.to_possible_value().expect("names aren't skipped");
+    format!("llmbundle __complete {} 2>/dev/null", names.get_name())
+}
+
+/// Quotes `s` as a single-quoted shell word.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn bash(commands: &[Command]) -> String {
+    let names: Vec<&str> = commands[1..].iter().map(|c| c.name.as_str()).collect();
+    let mut script = String::from("_llmbundle() {\n");
+    script.push_str(
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+    );
+    script.push_str("    local command=\"\"\n");
+    let _ = writeln!(
+        script,
+        "    if (( COMP_CWORD > 1 )) && [[ \" {} \" == *\" ${{COMP_WORDS[1]}} \"* ]]; then",
+        names.join(" ")
+    );
+    script.push_str("        command=\"${COMP_WORDS[1]}\"\n    fi\n\n");
+
+    // Values of the option before the cursor
+    script.push_str("    case \"$command:$prev\" in\n");
+    for command in commands {
+        for arg in command.options.iter().filter(|arg| takes_value(arg)) {
+            let patterns: Vec<String> = flags(arg)
+                .iter()
+                .map(|flag| format!("{}:{flag}", command.name))
+                .collect();
+            let words = match (dynamic(arg), values(arg)) {
+                (Some(names), _) => format!("$({})", names_command(names)),
+                (None, values) if !values.is_empty() => {
+                    let values: Vec<String> = values.into_iter().map(|(name, _)| name).collect();
+                    values.join(" ")
+                }
+                _ => {
+                    let _ = writeln!(
+                        script,
+                        "        {})\n            COMPREPLY=($(compgen -f -- \"$cur\"))\n            return ;;",
+                        patterns.join("|")
+                    );
+                    continue;
+                }
+            };
+            let _ = writeln!(
+                script,
+                "        {})\n            COMPREPLY=($(compgen -W \"{words}\" -- \"$cur\"))\n            return ;;",
+                patterns.join("|")
+            );
+        }
+    }
+    script.push_str("    esac\n\n");
+
+    // Options, or the positional arguments
+    script.push_str("    if [[ $cur == -* ]]; then\n        case \"$command\" in\n");
+    for command in commands {
+        let options: Vec<String> = command.options.iter().flat_map(flags).collect();
+        let _ = writeln!(
+            script,
+            "            {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;",
+            quote(&command.name),
+            options.join(" ")
+        );
+    }
+    script.push_str("        esac\n        return\n    fi\n\n    case \"$command\" in\n");
+    let _ = writeln!(
+        script,
+        "        '')\n            if (( COMP_CWORD == 1 )); then\n                COMPREPLY=($(compgen -W \"{} $({})\" -- \"$cur\"))\n            fi\n            COMPREPLY+=($(compgen -f -- \"$cur\")) ;;",
+        names.join(" "),
+        names_command(Names::Aliases)
+    );
+    for command in &commands[1..] {
+        if let Some(names) = command.positional {
+            let _ = writeln!(
+                script,
+                "        {}) COMPREPLY=($(compgen -W \"$({})\" -- \"$cur\")) ;;",
+                command.name,
+                names_command(names)
+            );
+        }
+    }
+    script.push_str("        *) COMPREPLY=($(compgen -f -- \"$cur\")) ;;\n    esac\n}\n\n");
+    script.push_str("complete -o filenames -F _llmbundle llmbundle\n");
+    script
+}
+
+/// Escapes the characters `_arguments` gives a meaning to in descriptions.
+fn zsh_escape(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('[', r"\[")
+        .replace(']', r"\]")
+        .replace(':', r"\:")
+}
+
+fn zsh(commands: &[Command]) -> String {
+    let mut script = String::from("#compdef llmbundle\n\n");
+    for names in Names::value_variants() {
+        let name = names.to_possible_value().expect("names aren't skipped");
+        let _ = writeln!(
+            script,
+            "_llmbundle_{0}() {{\n    local -a names\n    names=(${{(f)\"$({1})\"}})\n    compadd -a names\n}}\n",
+            name.get_name(),
+            names_command(*names)
+        );
+    }
+
+    script.push_str("_llmbundle_commands() {\n    local -a commands\n    commands=(\n");
+    for command in &commands[1..] {
+        let description = format!("{}:{}", command.name, command.about);
+        let _ = writeln!(script, "        {}", quote(&description));
+    }
+    script.push_str("    )\n    _describe command commands\n    _llmbundle_aliases\n}\n\n");
+    script.push_str(
+        "_llmbundle_patterns() {\n    (( CURRENT == 2 )) && _llmbundle_commands\n    _files\n}\n\n",
+    );
+
+    script.push_str("_llmbundle() {\n");
+    let names: Vec<&str> = commands[1..].iter().map(|c| c.name.as_str()).collect();
+    let _ = writeln!(
+        script,
+        "    if (( CURRENT > 2 )) && [[ ${{words[2]}} == ({}) ]]; then",
+        names.join("|")
+    );
+    script.push_str("        local command=${words[2]}\n        shift words\n        (( CURRENT-- ))\n        case $command in\n");
+    for command in &commands[1..] {
+        let positional = match command.positional {
+            Some(names) => format!(
+                "_llmbundle_{}",
+                names.to_possible_value().unwrap().get_name()
+            ),
+            None => "_files".to_string(),
+        };
+        let _ = writeln!(
+            script,
+            "            {})\n                _arguments -s \\",
+            command.name
+        );
+        zsh_options(&mut script, &command.options, "                    ");
+        let _ = writeln!(script, "                    '*: :{positional}' ;;");
+    }
+    script.push_str("        esac\n    else\n        _arguments -s \\\n");
+    zsh_options(&mut script, &commands[0].options, "            ");
+    script.push_str("            '*: :_llmbundle_patterns'\n    fi\n}\n\n_llmbundle \"$@\"\n");
+    script
+}
+
+fn zsh_options(script: &mut String, options: &[Arg], indent: &str) {
+    for arg in options {
+        let flags = flags(arg);
+        let repeatable = matches!(arg.get_action(), ArgAction::Append | ArgAction::Count);
+        let exclusive = match (repeatable, flags.len()) {
+            (true, _) => "*".to_string(),
+            (false, 1) => String::new(),
+            (false, _) => format!("({})", flags.join(" ")),
+        };
+        let value = if takes_value(arg) {
+            let value_name = arg
+                .get_value_names()
+                .and_then(|names| names.first())
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| arg.get_id().to_string().to_uppercase());
+            let action = match (dynamic(arg), values(arg)) {
+                (Some(names), _) => {
+                    format!(
+                        "_llmbundle_{}",
+                        names.to_possible_value().unwrap().get_name()
+                    )
+                }
+                (None, values) if !values.is_empty() => {
+                    let values: Vec<String> = values
+                        .iter()
+                        .map(|(name, help)| {
+                            format!("{name}\\:{}", zsh_escape(help).replace(' ', r"\ "))
+                        })
+                        .collect();
+                    format!("(({}))", values.join(" "))
+                }
+                _ => "_files".to_string(),
+            };
+            format!(":{value_name}:{action}")
+        } else {
+            String::new()
+        };
+        let help = zsh_escape(&help(arg));
+        for flag in &flags {
+            let flag = match (takes_value(arg), flag.starts_with("--")) {
+                (true, true) => format!("{flag}="),
+                _ => flag.clone(),
+            };
+            let spec = format!("{exclusive}{flag}[{help}]{value}");
+            let _ = writeln!(script, "{indent}{} \\", quote(&spec));
+        }
+    }
+}
+
+fn fish(commands: &[Command]) -> String {
+    let names: Vec<&str> = commands[1..].iter().map(|c| c.name.as_str()).collect();
+    let mut script = String::new();
+    let _ = writeln!(
+        script,
+        "function __llmbundle_command\n    set -l tokens (commandline -opc)\n    test (count $tokens) -ge 2; and contains -- $tokens[2] {}; and echo $tokens[2]\nend\n",
+        names.join(" ")
+    );
+    script.push_str(
+        "function __llmbundle_using\n    set -l command (__llmbundle_command); and test $command = $argv[1]\nend\n\n",
+    );
+    script
+        .push_str("function __llmbundle_first\n    test (count (commandline -opc)) -eq 1\nend\n\n");
+
+    for command in &commands[1..] {
+        let _ = writeln!(
+            script,
+            "complete -c llmbundle -n __llmbundle_first -f -a {} -d {}",
+            command.name,
+            quote(&command.about)
+        );
+    }
+    let _ = writeln!(
+        script,
+        "complete -c llmbundle -n __llmbundle_first -f -a {} -d alias",
+        quote(&format!("({})", names_command(Names::Aliases)))
+    );
+
+    for command in commands {
+        let condition = match command.name.as_str() {
+            "" => quote("not __llmbundle_command"),
+            name => quote(&format!("__llmbundle_using {name}")),
+        };
+        if let Some(names) = command.positional {
+            let _ = writeln!(
+                script,
+                "complete -c llmbundle -n {condition} -f -a {}",
+                quote(&format!("({})", names_command(names)))
+            );
+        }
+        for arg in &command.options {
+            let mut line = format!("complete -c llmbundle -n {condition}");
+            if let Some(short) = arg.get_short() {
+                let _ = write!(line, " -s {short}");
+            }
+            if let Some(long) = arg.get_long() {
+                let _ = write!(line, " -l {long}");
+            }
+            let values = values(arg);
+            match dynamic(arg) {
+                _ if !takes_value(arg) => {}
+                Some(names) => {
+                    let names = quote(&format!("({})", names_command(names)));
+                    let _ = write!(line, " -x -a {names}");
+                }
+                // One line per value, each with its own description
+                None if !values.is_empty() => {
+                    for (value, help) in values {
+                        let _ = writeln!(script, "{line} -x -a {value} -d {}", quote(&help));
+                    }
+                    continue;
+                }
+                None => line.push_str(" -r"),
+            }
+            let _ = writeln!(script, "{line} -d {}", quote(&help(arg)));
+        }
+    }
+    script
+}
@@ -0,0 +1,63 @@
+//! Compressed encodings of the bundle, for transports with tight size limits where the receiver
+//! decompresses it before use.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use std::io::Write;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum Compression {
+    /// Gzip, then base64 encoded and wrapped at 76 columns
+    GzipBase64,
+}
+
+const BEGIN: &str = "-----BEGIN LLMBUNDLE GZIP BASE64-----";
+const END: &str = "-----END LLMBUNDLE GZIP BASE64-----";
+
+/// Compresses `text`, prefixed by a short note telling the receiver how to decode it.
+pub fn compress(text: &str, compression: Compression) -> Result<String> {
+    match compression {
+        Compression::GzipBase64 => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(text.as_bytes())?;
+            let encoded = base64(&encoder.finish()?);
+
+            let mut out = String::from(
+                "The following is a gzip-compressed, base64-encoded bundle of files. Decode it \
+                 with `base64 -d | gunzip` after removing the BEGIN and END lines.\n",
+            );
+            out.push_str(BEGIN);
+            out.push('\n');
+            for line in encoded.as_bytes().chunks(76) {
+                out.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+                out.push('\n');
+            }
+            out.push_str(END);
+            out.push('\n');
+            Ok(out)
+        }
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,630 @@
+//! Configuration files providing defaults for the command line flags: the project's
+//! `.llmbundle.toml`, found by walking up from the root, layered over the user's
+//! `~/.config/llmbundle/config.toml`. `LLMBUNDLE_*` environment variables take precedence over
+//! both, e.g. `LLMBUNDLE_MAX_TOKENS=50000`.
+//!
+//! Each key is named after a flag (`max_tokens = 50000` for `--max-tokens 50000`), with
+//! `patterns` and `exclude` (prefixed with `!`) giving the default patterns. Values are turned
+//! into arguments inserted before the ones given on the command line, skipping the flags the
+//! command line already sets. Keys in a table named after a subcommand (`[ask]`) only apply to it,
+//! and the ones in `[profile.<name>]` only when selected with `--profile <name>`. The `[alias]`
+//! table defines commands expanding to a list of arguments.
+
+use crate::json::Value;
+use crate::{bundle, toml, wizard};
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, Command, Subcommand};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = ".llmbundle.toml";
+/// Prefix of the environment variables setting flags
+pub const ENV_PREFIX: &str = "LLMBUNDLE_";
+
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// Directory the configuration of the project is searched from
+    #[arg(long, default_value = ".", help = "Root directory of the project")]
+    root: PathBuf,
+
+    #[command(subcommand)]
+    command: Option<ConfigCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Write a .llmbundle.toml by answering questions
+    Wizard(wizard::WizardArgs),
+}
+
+#[derive(Default)]
+pub struct Config {
+    /// Files in order of precedence
+    layers: Vec<Layer>,
+}
+
+struct Layer {
+    path: PathBuf,
+    table: Value,
+    lines: toml::Lines,
+}
+
+impl Layer {
+    fn load(path: &Path) -> Result<Layer> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let (table, lines) =
+            toml::parse(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Layer {
+            path: path.to_path_buf(),
+            table,
+            lines,
+        })
+    }
+
+    /// Where the key at `path` is defined, for error messages.
+    fn location(&self, path: &[&str]) -> String {
+        let line = self.lines.iter().find(|(keys, _)| keys.iter().eq(path));
+        match line {
+            Some((_, line)) => format!("line {line} of {}", self.path.display()),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    /// Problems with the tables and keys of the file, for the commands of `cli`.
+    fn validate(&self, cli: &Command) -> Vec<String> {
+        let mut problems = Vec::new();
+        self.validate_table(&mut problems, &[], cli, &self.table);
+        let Value::Object(members) = &self.table else {
+            return problems;
+        };
+        for (key, value) in members {
+            match (key.as_str(), value) {
+                ("alias", Value::Object(aliases)) => {
+                    for (name, alias) in aliases {
+                        let valid = match alias {
+                            Value::String(command) => split_words(command).is_ok(),
+                            Value::Array(args) => args.iter().all(|arg| arg.as_str().is_some()),
+                            _ => false,
+                        };
+                        if !valid {
+                            let location = self.location(&["alias", name]);
+                            problems.push(format!(
+                                "Invalid alias `{name}` on {location}, expected a command or an \
+                                 array of arguments"
+                            ));
+                        }
+                    }
+                }
+                ("profile", Value::Object(profiles)) => {
+                    for (name, profile) in profiles {
+                        match profile {
+                            Value::Object(_) => {
+                                self.validate_table(&mut problems, &["profile", name], cli, profile)
+                            }
+                            _ => problems.push(format!(
+                                "`profile.{name}` on {} is not a table",
+                                self.location(&["profile", name])
+                            )),
+                        }
+                    }
+                }
+                (name, Value::Object(_)) => match cli.find_subcommand(name) {
+                    Some(command) => self.validate_table(&mut problems, &[name], command, value),
+                    None => {
+                        let mut names: Vec<&str> = cli
+                            .get_subcommands()
+                            .map(|command| command.get_name())
+                            .collect();
+                        names.extend(["alias", "profile"]);
+                        problems.push(format!(
+                            "Unknown table [{name}] on {}{}",
+                            self.location(&[name]),
+                            suggestion(name, names)
+                        ));
+                    }
+                },
+                _ => {}
+            }
+        }
+        problems
+    }
+
+    /// Checks the keys of the table at `path`, applying to `command`, leaving out the subtables.
+    fn validate_table(
+        &self,
+        problems: &mut Vec<String>,
+        path: &[&str],
+        command: &Command,
+        table: &Value,
+    ) {
+        let Value::Object(members) = table else {
+            return;
+        };
+        let name = match path.is_empty() {
+            true => String::new(),
+            false => format!(" in [{}]", path.join(".")),
+        };
+        let mut keys = Vec::new();
+        for (key, value) in members {
+            let key_path: Vec<&str> = path.iter().copied().chain([key.as_str()]).collect();
+            let location = self.location(&key_path);
+            if matches!(value, Value::Object(_)) {
+                if !path.is_empty() {
+                    problems.push(format!("`{key}`{name} on {location} can't be a table"));
+                }
+                continue;
+            }
+
+            let values = match value {
+                Value::Array(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            if matches!(key.as_str(), "patterns" | "exclude") {
+                for value in values {
+                    let Some(pattern) = value.as_str() else {
+                        problems.push(format!("`{key}`{name} on {location} must be strings"));
+                        break;
+                    };
+                    if let Err(e) = bundle::resolve_pattern(pattern)
+                        .and_then(|glob| Ok(globset::Glob::new(&glob)?))
+                    {
+                        problems.push(format!(
+                            "Invalid pattern `{pattern}` in `{key}`{name} on {location}: {e}"
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            let Some(arg) = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == key.as_str())
+            else {
+                let mut ids: Vec<&str> = command
+                    .get_arguments()
+                    .map(|arg| arg.get_id().as_str())
+                    .collect();
+                ids.extend(["patterns", "exclude"]);
+                problems.push(format!(
+                    "Unknown key `{key}`{name} on {location}{}",
+                    suggestion(key, ids)
+                ));
+                continue;
+            };
+            // A disabled flag doesn't conflict with anything
+            if !matches!(value, Value::Bool(false)) {
+                keys.push(arg);
+            }
+            if !arg.get_action().takes_values() {
+                if !matches!(value, Value::Bool(_)) {
+                    problems.push(format!("`{key}`{name} on {location} must be true or false"));
+                }
+                continue;
+            }
+            let possible = arg.get_possible_values();
+            for value in values {
+                match value {
+                    Value::String(value) if !possible.is_empty() => {
+                        if !possible
+                            .iter()
+                            .any(|possible| possible.matches(value, false))
+                        {
+                            let names: Vec<&str> = possible
+                                .iter()
+                                .map(|possible| possible.get_name())
+                                .collect();
+                            problems.push(format!(
+                                "Invalid value `{value}` for `{key}`{name} on {location}, \
+                                 expected one of {}",
+                                names.join(", ")
+                            ));
+                        }
+                    }
+                    Value::String(_) | Value::Number(_) | Value::Bool(_) => {}
+                    _ => problems.push(format!("Invalid value for `{key}`{name} on {location}")),
+                }
+            }
+        }
+
+        for (i, arg) in keys.iter().enumerate() {
+            for other in command.get_arg_conflicts_with(arg) {
+                if keys[..i].iter().any(|key| key.get_id() == other.get_id()) {
+                    let key_path: Vec<&str> = path
+                        .iter()
+                        .copied()
+                        .chain([arg.get_id().as_str()])
+                        .collect();
+                    problems.push(format!(
+                        "`{}` can't be used with `{}`{name}, on {}",
+                        arg.get_id(),
+                        other.get_id(),
+                        self.location(&key_path)
+                    ));
+                }
+            }
+        }
+    }
+
+    /// The `LLMBUNDLE_*` variables naming an argument of `command`.
+    fn environment(command: &Command) -> Layer {
+        let mut members = Vec::new();
+        for (name, value) in std::env::vars() {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_lowercase();
+            let Some(arg) = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == key.as_str())
+            else {
+                continue;
+            };
+            let value = match arg.get_action().takes_values() {
+                true => Value::String(value),
+                false => match value.to_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => Value::Bool(true),
+                    "" | "0" | "false" | "no" | "off" => Value::Bool(false),
+                    _ => Value::String(value),
+                },
+            };
+            members.push((key, value));
+        }
+        Layer {
+            path: PathBuf::from("the environment"),
+            table: Value::Object(members),
+            lines: toml::Lines::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration of the project `root` is in and the user's, if any.
+    pub fn find(root: &Path) -> Result<Config> {
+        let root = crate::state::canonical(root);
+        let project = root
+            .ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|path| path.is_file());
+        Config::load(project)
+    }
+
+    /// Reads the user's configuration only, for the projects that aren't trusted.
+    pub fn user() -> Result<Config> {
+        Config::load(None)
+    }
+
+    fn load(project: Option<PathBuf>) -> Result<Config> {
+        let global = global_path().filter(|path| path.is_file());
+        Ok(Config {
+            layers: project
+                .into_iter()
+                .chain(global)
+                .map(|path| Layer::load(&path))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Fails listing the problems of the configuration files for the commands of `cli`, like
+    /// unknown keys, invalid patterns and conflicting options.
+    pub fn validate(&self, cli: &Command) -> Result<()> {
+        let problems: Vec<String> = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.validate(cli))
+            .collect();
+        match problems.as_slice() {
+            [] => Ok(()),
+            [problem] => bail!("{problem}"),
+            problems => bail!("Invalid configuration:\n- {}", problems.join("\n- ")),
+        }
+    }
+
+    /// Arguments the alias `name` expands to, if it's defined.
+    pub fn alias(&self, name: &str) -> Result<Option<Vec<String>>> {
+        for layer in &self.layers {
+            let Some(alias) = layer
+                .table
+                .get("alias")
+                .and_then(|aliases| aliases.get(name))
+            else {
+                continue;
+            };
+            let invalid = || format!("Invalid alias `{name}` in {}", layer.path.display());
+            let args = match alias {
+                Value::String(command) => split_words(command).with_context(invalid)?,
+                Value::Array(args) => args
+                    .iter()
+                    .map(|arg| arg.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .with_context(invalid)?,
+                _ => bail!(invalid()),
+            };
+            return Ok(Some(args));
+        }
+        Ok(None)
+    }
+
+    /// Names of the profiles defined in the configuration files.
+    pub fn profiles(&self) -> Vec<String> {
+        self.names("profile")
+    }
+
+    /// Names of the aliases defined in the configuration files.
+    pub fn aliases(&self) -> Vec<String> {
+        self.names("alias")
+    }
+
+    fn names(&self, table: &str) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for layer in &self.layers {
+            if let Some(Value::Object(members)) = layer.table.get(table) {
+                for (name, _) in members {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Arguments providing the values of the configuration for the command `matches` were parsed
+    /// with, leaving out the flags set on the command line.
+    pub fn args(&self, cli: &Command, matches: &ArgMatches) -> Result<Vec<String>> {
+        let profile = matches.get_one::<String>("profile");
+        let (command, matches) = match matches.subcommand() {
+            Some((name, sub_matches)) => (
+                cli.find_subcommand(name)
+                    .expect("matched subcommands exist"),
+                sub_matches,
+            ),
+            None => (cli, matches),
+        };
+
+        // Tables in order of precedence: the environment, the selected profile, then the table
+        // of the subcommand and the top-level keys of each file
+        let environment = Layer::environment(command);
+        let mut tables = vec![(&environment, String::new(), command, &environment.table)];
+        for layer in &self.layers {
+            if let Some(profile) = profile {
+                if let Some(table) = layer.table.get("profile").and_then(|t| t.get(profile)) {
+                    tables.push((layer, format!("[profile.{profile}]"), cli, table));
+                }
+            }
+        }
+        if let Some(profile) = profile {
+            if tables.len() == 1 {
+                bail!("No profile named {profile} in the configuration files");
+            }
+        }
+        for layer in &self.layers {
+            if !std::ptr::eq(command, cli) {
+                if let Some(table) = layer.table.get(command.get_name()) {
+                    tables.push((layer, format!("[{}]", command.get_name()), command, table));
+                }
+            }
+            tables.push((layer, String::new(), cli, &layer.table));
+        }
+
+        let mut args = Vec::new();
+        let mut set: Vec<&str> = Vec::new();
+        for (layer, name, scope, table) in tables {
+            let Value::Object(members) = table else {
+                bail!("{name} in {} is not a table", layer.path.display());
+            };
+            for (key, value) in members {
+                // Subtables and unknown keys were rejected by `validate`
+                if matches!(value, Value::Object(_))
+                    || (scope
+                        .get_arguments()
+                        .all(|arg| arg.get_id() != key.as_str())
+                        && !matches!(key.as_str(), "patterns" | "exclude"))
+                {
+                    continue;
+                }
+                // Excludes add up, everything else is overridden
+                if key != "exclude" && set.contains(&key.as_str()) {
+                    continue;
+                }
+                set.push(key);
+                layer.push_args(&mut args, command, matches, key, value)?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl Layer {
+    fn push_args(
+        &self,
+        args: &mut Vec<String>,
+        command: &Command,
+        matches: &ArgMatches,
+        key: &str,
+        value: &Value,
+    ) -> Result<()> {
+        let values = match value {
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        let values = values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => Ok(s.clone()),
+                Value::Number(n) => Ok(n.to_string()),
+                Value::Bool(b) => Ok(b.to_string()),
+                _ => bail!("Invalid value for `{key}` in {}", self.path.display()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let has_patterns = command
+            .get_arguments()
+            .any(|arg| arg.get_id() == "patterns");
+        match key {
+            _ if matches!(key, "patterns" | "exclude") && !has_patterns => {}
+            // The patterns given on the command line replace the default ones, but excludes
+            // always apply
+            "patterns" if !on_command_line(matches, "patterns") => args.extend(values),
+            "patterns" => {}
+            "exclude" => args.extend(values.iter().map(|value| format!("!{value}"))),
+            _ => {
+                // Keys of the default command that the subcommand doesn't have
+                let Some(arg) = command.get_arguments().find(|arg| arg.get_id() == key) else {
+                    return Ok(());
+                };
+                if on_command_line(matches, key) {
+                    return Ok(());
+                }
+                let flag = match arg.get_long() {
+                    Some(long) => format!("--{long}"),
+                    None => bail!("`{key}` in {} is not a flag", self.path.display()),
+                };
+                for value in values {
+                    match (value.as_str(), arg.get_action().takes_values()) {
+                        ("true", false) => args.push(flag.clone()),
+                        ("false", false) => {}
+                        (_, false) => {
+                            bail!("`{key}` in {} must be true or false", self.path.display())
+                        }
+                        // Paths are relative to the configuration, not the working directory
+                        (value, true) if key == "root" => {
+                            args.push(format!("{flag}={}", self.relative(value).display()))
+                        }
+                        (value, true) => args.push(format!("{flag}={value}")),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn relative(&self, path: &str) -> PathBuf {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        dir.join(shellexpand::tilde(path).as_ref())
+    }
+}
+
+/// Prints the configuration files of the project and the arguments they add to the default
+/// command of `cli`, with the given profile.
+pub fn run(args: &ConfigArgs, cli: &Command, profile: Option<&str>) -> Result<()> {
+    if let Some(ConfigCommand::Wizard(wizard_args)) = &args.command {
+        return wizard::run(wizard_args);
+    }
+    let config = Config::find(&args.root)?;
+    config.validate(cli)?;
+    if config.layers.is_empty() {
+        println!("{}", "No configuration files found.".red());
+    } else {
+        println!("{}", "Configuration files, by precedence".blue().bold());
+        for layer in &config.layers {
+            println!("{} {}", "+".red(), layer.path.display());
+        }
+    }
+
+    let profile = profile.map(|profile| format!("--profile={profile}"));
+    let matches = cli
+        .clone()
+        .try_get_matches_from(std::iter::once("llmbundle".to_string()).chain(profile))?;
+    let args = config.args(cli, &matches)?;
+    match args.is_empty() {
+        true => println!("\nNo arguments added to llmbundle."),
+        false => println!("\nArguments added to llmbundle: {}", args.join(" ").bold()),
+    }
+    Ok(())
+}
+
+/// Splits `command` into words like a POSIX shell, handling quotes and backslashes.
+fn split_words(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated quote"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => bail!("unterminated quote"),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("unterminated quote"),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// A hint naming the `candidates` closest to the mistyped `name`, if one is close enough.
+fn suggestion<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    let closest = candidates
+        .into_iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .min();
+    match closest {
+        Some((distance, candidate)) if distance <= (name.len() / 3).max(1) => {
+            format!(", did you mean `{candidate}`?")
+        }
+        _ => String::new(),
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, counting a swap of adjacent characters as one edit so
+/// `excldue` is next to `exclude`.
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// `config.toml` in the user's configuration directory, following the XDG base directory spec.
+fn global_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+    Some(dir.join("llmbundle").join("config.toml"))
+}
+
+fn on_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches
+            .try_contains_id(id)
+            .map(|_| matches.value_source(id)),
+        Ok(Some(ValueSource::CommandLine))
+    )
+}
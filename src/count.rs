@@ -0,0 +1,88 @@
+//! Prints the number of matched files and their estimated tokens with `--count-only`, streaming
+//! each file instead of holding the bundle in memory, for scripts checking the size of a
+//! selection.
+
+use crate::bundle::{self, ProcessedFile};
+use crate::failure::Failure;
+use crate::{errors, index, tokens, BundleArgs};
+use anyhow::Result;
+use llmbundle_core::tokens::Counter;
+use llmbundle_core::{links, mmap};
+use rayon::prelude::*;
+use std::io::Read;
+use std::path::Path;
+
+pub fn run(args: &BundleArgs) -> Result<()> {
+    let files = bundle::find_files(args)?;
+    // The content given by the transforms can only be counted once read, the files of a
+    // revision aren't on disk, and the last commits are only known to git
+    if bundle::transforms(args) || bundle::from_git(args) || args.with_git_log {
+        let bundle = bundle::bundle_files(args, &files)?;
+        let total = bundle.files.iter().map(|file| file.token_count).sum();
+        return report(files.len(), total);
+    }
+    if let Some(stats) = index::stats(args, &files)? {
+        let total: usize = stats.iter().map(|file| file.token_count).sum();
+        return report(files.len(), total);
+    }
+    let root = Path::new(&args.root);
+    let originals = links::originals(root, &files);
+    let total: usize = files
+        .par_iter()
+        .map(|path| {
+            if let Some(original) = originals.get(path) {
+                let stub = ProcessedFile::duplicate(path.clone(), original);
+                return tokens::estimate_chars(stub.char_count);
+            }
+            // The header of the file as it appears in the bundle, the checksum having the same
+            // length whatever the content
+            let header = match args.checksums {
+                true => ProcessedFile::with_checksum(path.clone(), String::new()),
+                false => ProcessedFile::new(path.clone(), String::new()),
+            };
+            tokens::estimate_chars(header.char_count + characters(root, path))
+        })
+        .sum();
+    report(files.len(), total)
+}
+
+fn report(files: usize, tokens: usize) -> Result<()> {
+    println!("{files} files, {tokens} tokens");
+    if files == 0 {
+        return Err(Failure::NoMatches.into());
+    }
+    Ok(())
+}
+
+/// Number of characters of the file at `path` under `root`, read in chunks, counting unreadable
+/// files as empty like the bundle does.
+fn characters(root: &Path, path: &Path) -> usize {
+    let full_path = root.join(path);
+    if let Some(count) = mmap::with_mapped(&full_path, |bytes| {
+        let mut counter = Counter::default();
+        counter.add(bytes);
+        counter.count()
+    }) {
+        return count;
+    }
+    let mut file = match std::fs::File::open(&full_path) {
+        Ok(file) => file,
+        Err(e) => {
+            errors::record(path, &format!("Failed to read the file: {e}"));
+            return 0;
+        }
+    };
+    let mut buffer = [0; 64 * 1024];
+    let mut counter = Counter::default();
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return counter.count(),
+            Ok(read) => counter.add(&buffer[..read]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                errors::record(path, &format!("Failed to read the file: {e}"));
+                return counter.count();
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Line-based diffs (Myers' algorithm, in linear space) grouped into unified-diff hunks.
+
+use colored::Colorize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A group of changes along with the lines of context around them.
+pub struct Hunk<'a> {
+    /// Index of the first line of the hunk in the old text, starting at 0
+    pub old_start: usize,
+    pub old_len: usize,
+    /// Index of the first line of the hunk in the new text, starting at 0
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<(Op, &'a str)>,
+}
+
+/// Splits `text` into lines, keeping their line endings so a diff can be applied exactly.
+pub fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Shortest edit script turning `old` into `new`.
+pub fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    conquer(old, new, &mut ops);
+    ops
+}
+
+/// Pushes the edits turning `a` into `b`, splitting them at a point of a shortest path found by
+/// [`middle_snake`] so the search only ever keeps one row of diagonals, rather than one per edit.
+fn conquer<'a>(a: &[&'a str], b: &[&'a str], ops: &mut Vec<(Op, &'a str)>) {
+    // Common prefix and suffix don't need the quadratic worst case
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    ops.extend(a[..prefix].iter().map(|line| (Op::Equal, *line)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, tail, b) = (
+        &a[..a.len() - suffix],
+        &a[a.len() - suffix..],
+        &b[..b.len() - suffix],
+    );
+
+    if a.is_empty() {
+        ops.extend(b.iter().map(|line| (Op::Insert, *line)));
+    } else if b.is_empty() {
+        ops.extend(a.iter().map(|line| (Op::Delete, *line)));
+    } else {
+        let (x, y) = middle_snake(a, b);
+        conquer(&a[..x], &b[..y], ops);
+        conquer(&a[x..], &b[y..], ops);
+    }
+    ops.extend(tail.iter().map(|line| (Op::Equal, *line)));
+}
+
+/// A point on a shortest path from the start of `a` and `b` to their ends, found by searching
+/// forward from the start and backward from the end until the two searches meet (Myers' linear
+/// space refinement, as in GNU diff). `a` and `b` must be non-empty and differ in their first and
+/// last lines, so the point is strictly between the start and the end.
+fn middle_snake(a: &[&str], b: &[&str]) -> (usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // Furthest `x` reached on each diagonal `k = x - y` from `-m - 1` to `n + 1`, forward and
+    // backward, with the diagonals just outside the searched ones holding sentinels
+    let index = |k: isize| (k + m + 1) as usize;
+    let mut forward = vec![0isize; (n + m + 3) as usize];
+    let mut backward = forward.clone();
+    let (min, max) = (-m, n);
+    let (forward_mid, backward_mid) = (0, n - m);
+    let odd = (forward_mid - backward_mid) % 2 != 0;
+    forward[index(forward_mid)] = 0;
+    backward[index(backward_mid)] = n;
+    let (mut forward_min, mut forward_max) = (forward_mid, forward_mid);
+    let (mut backward_min, mut backward_max) = (backward_mid, backward_mid);
+
+    loop {
+        if forward_min > min {
+            forward_min -= 1;
+            forward[index(forward_min - 1)] = -1;
+        } else {
+            forward_min += 1;
+        }
+        if forward_max < max {
+            forward_max += 1;
+            forward[index(forward_max + 1)] = -1;
+        } else {
+            forward_max -= 1;
+        }
+        for k in (forward_min..=forward_max).rev().step_by(2) {
+            let (low, high) = (forward[index(k - 1)], forward[index(k + 1)]);
+            let mut x = if low < high { high } else { low + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+            if odd && (backward_min..=backward_max).contains(&k) && backward[index(k)] <= x {
+                return (x as usize, y as usize);
+            }
+        }
+
+        if backward_min > min {
+            backward_min -= 1;
+            backward[index(backward_min - 1)] = isize::MAX;
+        } else {
+            backward_min += 1;
+        }
+        if backward_max < max {
+            backward_max += 1;
+            backward[index(backward_max + 1)] = isize::MAX;
+        } else {
+            backward_max -= 1;
+        }
+        for k in (backward_min..=backward_max).rev().step_by(2) {
+            let (low, high) = (backward[index(k - 1)], backward[index(k + 1)]);
+            let mut x = if low < high { low } else { high - 1 };
+            let mut y = x - k;
+            while x > 0 && y > 0 && a[x as usize - 1] == b[y as usize - 1] {
+                x -= 1;
+                y -= 1;
+            }
+            backward[index(k)] = x;
+            if !odd && (forward_min..=forward_max).contains(&k) && x <= forward[index(k)] {
+                return (x as usize, y as usize);
+            }
+        }
+    }
+}
+
+/// Groups the changes of `ops` into hunks with `context` lines of context.
+pub fn hunks<'a>(ops: &[(Op, &'a str)], context: usize) -> Vec<Hunk<'a>> {
+    // Position in the old and new text before each op
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut old, mut new) = (0, 0);
+    for (op, _) in ops {
+        positions.push((old, new));
+        match op {
+            Op::Equal => {
+                old += 1;
+                new += 1;
+            }
+            Op::Delete => old += 1,
+            Op::Insert => new += 1,
+        }
+    }
+    positions.push((old, new));
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != Op::Equal).collect();
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let first = changes[i];
+        let mut last = first;
+        while i + 1 < changes.len() && changes[i + 1] - last <= 2 * context + 1 {
+            i += 1;
+            last = changes[i];
+        }
+        i += 1;
+
+        let start = first.saturating_sub(context);
+        let end = (last + 1 + context).min(ops.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        hunks.push(Hunk {
+            old_start,
+            old_len: old_end - old_start,
+            new_start,
+            new_len: new_end - new_start,
+            lines: ops[start..end].to_vec(),
+        });
+    }
+    hunks
+}
+
+impl Hunk<'_> {
+    /// The `@@ -a,b +c,d @@` header of the hunk.
+    pub fn header(&self) -> String {
+        // Unified diffs number lines from 1, except for empty ranges which point before them
+        let start = |start: usize, len: usize| if len == 0 { start } else { start + 1 };
+        format!(
+            "@@ -{},{} +{},{} @@",
+            start(self.old_start, self.old_len),
+            self.old_len,
+            start(self.new_start, self.new_len),
+            self.new_len
+        )
+    }
+
+    /// Prints the hunk with the removed lines in red and the added ones in green.
+    pub fn print(&self) {
+        println!("{}", self.header().cyan());
+        for (op, line) in &self.lines {
+            let text = line.strip_suffix('\n').unwrap_or(line);
+            match op {
+                Op::Equal => println!(" {text}"),
+                Op::Delete => println!("{}", format!("-{text}").red()),
+                Op::Insert => println!("{}", format!("+{text}").green()),
+            }
+            if !line.ends_with('\n') {
+                println!("\\ No newline at end of file");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch;
+
+    /// `old` and `new` as a unified diff of hunks with `context` lines of context.
+    fn unified(old: &str, new: &str, context: usize) -> String {
+        let (old_lines, new_lines) = (lines(old), lines(new));
+        let mut text = String::from("--- a/f\n+++ b/f\n");
+        for hunk in hunks(&diff(&old_lines, &new_lines), context) {
+            text.push_str(&hunk.header());
+            text.push('\n');
+            for (op, line) in &hunk.lines {
+                let marker = match op {
+                    Op::Equal => ' ',
+                    Op::Delete => '-',
+                    Op::Insert => '+',
+                };
+                text.push(marker);
+                text.push_str(line);
+            }
+        }
+        text
+    }
+
+    /// Whether the diff from `old` to `new`, formatted then parsed back, turns `old` into `new`.
+    fn round_trips(old: &str, new: &str) {
+        for context in [0, 1, 3] {
+            let patches = patch::parse(&unified(old, new, context));
+            let patched = match patches.as_slice() {
+                [] => old.to_string(),
+                [patch] => patch.apply(old).unwrap(),
+                _ => panic!("the diff of a single file was parsed as several"),
+            };
+            assert_eq!(patched, new, "with {context} lines of context");
+        }
+    }
+
+    #[test]
+    fn diffs_are_the_shortest_edits() {
+        let (old, new) = (lines("a\nb\nc\n"), lines("a\nx\nc\nd\n"));
+        let ops: Vec<Op> = diff(&old, &new).into_iter().map(|(op, _)| op).collect();
+        assert_eq!(
+            ops,
+            [Op::Equal, Op::Delete, Op::Insert, Op::Equal, Op::Insert]
+        );
+    }
+
+    #[test]
+    fn diffs_are_as_short_as_the_longest_common_subsequence_allows() {
+        // A small linear congruential generator, so the cases are the same on every run
+        let mut seed = 7u32;
+        let mut random = |bound: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % bound
+        };
+        let alphabet = ["a\n", "b\n", "c\n"];
+        for _ in 0..500 {
+            let mut text = |len: u32| -> Vec<&str> {
+                (0..random(len))
+                    .map(|_| alphabet[random(3) as usize])
+                    .collect()
+            };
+            let (old, new) = (text(12), text(12));
+
+            let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for i in 0..old.len() {
+                for j in 0..new.len() {
+                    common[i + 1][j + 1] = if old[i] == new[j] {
+                        common[i][j] + 1
+                    } else {
+                        common[i][j + 1].max(common[i + 1][j])
+                    };
+                }
+            }
+
+            let ops = diff(&old, &new);
+            let side = |skipped: Op| -> Vec<&str> {
+                ops.iter()
+                    .filter(|(op, _)| *op != skipped)
+                    .map(|(_, line)| *line)
+                    .collect()
+            };
+            assert_eq!(
+                (side(Op::Insert), side(Op::Delete)),
+                (old.clone(), new.clone())
+            );
+            let edits = ops.iter().filter(|(op, _)| *op != Op::Equal).count();
+            assert_eq!(
+                edits,
+                old.len() + new.len() - 2 * common[old.len()][new.len()],
+                "{old:?} to {new:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn hunk_headers_number_lines_from_one() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = old.replace("5\n", "five\n");
+        let (old_lines, new_lines) = (lines(old), lines(&new));
+        let hunks = hunks(&diff(&old_lines, &new_lines), 3);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].header(), "@@ -2,7 +2,7 @@");
+    }
+
+    #[test]
+    fn far_apart_changes_get_separate_hunks() {
+        let old: String = (1..=20).map(|n| format!("{n}\n")).collect();
+        let new = old
+            .replace("\n2\n", "\ntwo\n")
+            .replace("\n19\n", "\nnineteen\n");
+        let (old_lines, new_lines) = (lines(&old), lines(&new));
+        assert_eq!(hunks(&diff(&old_lines, &new_lines), 3).len(), 2);
+        assert_eq!(hunks(&diff(&old_lines, &new_lines), 8).len(), 1);
+    }
+
+    #[test]
+    fn hunks_round_trip_through_patches() {
+        let old: String = (1..=30).map(|n| format!("line {n}\n")).collect();
+        round_trips(&old, &old.replace("line 1\n", "first\n"));
+        round_trips(&old, &old.replace("line 30\n", ""));
+        round_trips(&old, &old.replace("line 10\n", "ten\nand a half\n"));
+        round_trips(
+            &old,
+            &old.replace("line 5\n", "")
+                .replace("line 15\n", "fifteen\n")
+                .replace("line 25\n", "line 25\nline 25b\n"),
+        );
+        round_trips("", "new file\nwith two lines\n");
+        round_trips(&old, &old);
+    }
+}
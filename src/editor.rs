@@ -0,0 +1,38 @@
+use crate::{interrupt, pipe, temp};
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+
+/// Writes `text` to a temp file and opens it in the user's editor, waiting for it to close.
+pub fn open(text: &str) -> Result<()> {
+    edit(text).map(drop)
+}
+
+/// Lets the user edit `text` in their editor, returning the text saved.
+pub fn edit(text: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .context("Set VISUAL or EDITOR to open the bundle in an editor")?;
+
+    let (path, mut file) = temp::create("txt")?;
+    file.write_all(text.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    drop(file);
+
+    // The editor may contain arguments (e.g. `code --wait`), so run it through the shell
+    let quoted_path = format!("\"{}\"", path.display());
+    // Ctrl-C is meant for the editor, and the temp file must be removed even if it ends it
+    let guard = interrupt::catch();
+    let status = pipe::shell_command(&format!("{editor} {quoted_path}"))
+        .status()
+        .with_context(|| format!("Failed to run editor: {editor}"));
+    drop(guard);
+    let edited = std::fs::read_to_string(&path);
+
+    let _ = std::fs::remove_file(&path);
+
+    let status = status?;
+    if !status.success() {
+        bail!("Editor `{editor}` exited with {status}");
+    }
+    edited.with_context(|| format!("Failed to read {}", path.display()))
+}
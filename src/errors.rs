@@ -0,0 +1,61 @@
+//! The errors met on single files (read failures, invalid UTF-8, failed transforms), gathered as
+//! the files are read in parallel and reported together once done, instead of between the
+//! progress lines where they get lost.
+
+use crate::json::Value;
+use crate::paths;
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static ERRORS: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Remembers that the file at `path` failed with `error`.
+pub fn record(path: &Path, error: &str) {
+    log::debug!(path:% = path.display(); "{error}");
+    lock().push((path.to_owned(), error.to_string()));
+}
+
+/// The errors recorded so far, as `{"path", "error"}` objects for the JSON summary.
+pub fn json() -> Value {
+    Value::Array(
+        sorted()
+            .into_iter()
+            .map(|(path, error)| {
+                Value::object([
+                    ("path", paths::display(&path).into()),
+                    ("error", error.into()),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Prints the errors recorded so far to stderr, forgetting them.
+pub fn report() {
+    let errors = sorted();
+    lock().clear();
+    if errors.is_empty() {
+        return;
+    }
+    let files = match errors.len() {
+        1 => "1 file".to_string(),
+        count => format!("{count} files"),
+    };
+    eprintln!("{}", format!("Errors ({files})").red().bold());
+    for (path, error) in &errors {
+        eprintln!("  {}: {error}", paths::display(path));
+    }
+}
+
+/// The errors by path, a file being read once per bundle but possibly bundled again
+fn sorted() -> Vec<(PathBuf, String)> {
+    let mut errors = lock().clone();
+    errors.sort();
+    errors.dedup();
+    errors
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Vec<(PathBuf, String)>> {
+    ERRORS.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,173 @@
+//! Explains why a file is or isn't in the bundle: the ignore rule, the patterns matching it and
+//! whether it fit in the token budget.
+
+use crate::{bundle, paths, BundleArgs, Cli};
+use anyhow::{bail, Result};
+use clap::Args;
+use colored::Colorize;
+use ignore::gitignore::GitignoreBuilder;
+use ignore::Match;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Args)]
+pub struct ExplainArgs {
+    /// File to explain, relative to the root or to the working directory
+    path: PathBuf,
+
+    /// Patterns and flags of the bundle, as given to llmbundle, e.g. `'src/**' --max-tokens 50000`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+pub fn run(args: &ExplainArgs) -> Result<()> {
+    // Parsed like the default command, so the configuration applies the same way
+    let cli = Cli::parse_args(args.args.clone())?;
+    explain(&cli.run.bundle, &args.path)
+}
+
+fn explain(args: &BundleArgs, file: &Path) -> Result<()> {
+    let root = Path::new(&args.root);
+    let path = relative(root, file);
+    if !root.join(&path).is_file() {
+        bail!("{} is not a file under {}", file.display(), root.display());
+    }
+    let name = path.display().to_string().bold();
+
+    let depth = path.components().count();
+    if let Some(max_depth) = args.max_depth.filter(|max_depth| depth > *max_depth) {
+        println!(
+            "{name} is {}: it's {depth} levels deep, deeper than --max-depth {max_depth}.",
+            "excluded".red()
+        );
+        return Ok(());
+    }
+
+    if !bundle::walk(args)?.contains(&path) {
+        let reason = match ignore_rule(root, &path) {
+            Some((file, pattern)) => format!("it's ignored by `{pattern}` in {}", file.display()),
+            None if is_hidden(&path) => "it's hidden".to_string(),
+            None => "it's ignored by an ignore file, like the global gitignore".to_string(),
+        };
+        println!("{name} is {}: {reason}.", "excluded".red());
+        return Ok(());
+    }
+
+    let normalized = paths::normalized(&path);
+    let mut included = None;
+    let mut excluded = None;
+    for pattern in &args.patterns {
+        let (positive, negative) = bundle::build_glob_sets(std::slice::from_ref(pattern))?;
+        if pattern.trim().starts_with('!') {
+            if negative.is_match(&normalized) {
+                excluded.get_or_insert(pattern.trim());
+            }
+        } else if positive.is_match(&normalized) {
+            included.get_or_insert(pattern.trim());
+        }
+    }
+    let has_positive = args
+        .patterns
+        .iter()
+        .any(|pattern| !pattern.trim().starts_with('!'));
+    let matched = match (has_positive, included) {
+        (false, _) => "no patterns were given, so every file matches".to_string(),
+        (true, Some(pattern)) => format!("it matches `{pattern}`"),
+        (true, None) => {
+            println!(
+                "{name} is {}: it matches none of the patterns.",
+                "excluded".red()
+            );
+            return Ok(());
+        }
+    };
+    if let Some(pattern) = excluded {
+        println!(
+            "{name} is {}: {matched} but also `{pattern}`.",
+            "excluded".red()
+        );
+        return Ok(());
+    }
+
+    let bundle = bundle::bundle(args)?;
+    if bundle.dropped.contains(&path) {
+        println!(
+            "{name} is {}: {matched}, but doesn't fit in the budget of {} tokens left by the files \
+             before it.",
+            "excluded".red(),
+            args.max_tokens.unwrap_or_default()
+        );
+    } else if bundle.unreadable.contains(&path) {
+        println!(
+            "{name} is {} empty: {matched}, but couldn't be read.",
+            "included".yellow()
+        );
+    } else {
+        println!("{name} is {}: {matched}.", "included".green());
+    }
+    Ok(())
+}
+
+/// `path` relative to `root`, whether it was given relative to the root or to the working
+/// directory (always the case for paths going up with `..`).
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    let is_plain = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if is_plain && root.join(path).is_file() {
+        return normalize(path);
+    }
+    let canonical = |path: &Path| std::fs::canonicalize(path).ok();
+    match (canonical(root), canonical(path)) {
+        (Some(root), Some(path)) => path
+            .strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .unwrap_or(path),
+        _ => normalize(path),
+    }
+}
+
+/// `path` without its `./` components, as the walker gives them.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// The ignore file and pattern deciding that `path` is ignored, looking at the `.gitignore` and
+/// `.ignore` files from the root down to the directory of the file, the deepest one winning.
+fn ignore_rule(root: &Path, path: &Path) -> Option<(PathBuf, String)> {
+    let root = std::fs::canonicalize(root).ok()?;
+    let full = root.join(path);
+    let mut rule = None;
+    let dirs = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .collect::<Vec<_>>();
+    for dir in dirs.into_iter().rev() {
+        for name in [".gitignore", ".ignore"] {
+            let file = root.join(dir).join(name);
+            if !file.is_file() {
+                continue;
+            }
+            let mut builder = GitignoreBuilder::new(root.join(dir));
+            builder.add(&file);
+            let Ok(gitignore) = builder.build() else {
+                continue;
+            };
+            match gitignore.matched_path_or_any_parents(&full, false) {
+                Match::Ignore(glob) => {
+                    rule = Some((dir.join(name), glob.original().to_string()));
+                }
+                Match::Whitelist(_) => rule = None,
+                Match::None => {}
+            }
+        }
+    }
+    rule
+}
@@ -0,0 +1,55 @@
+//! Failures with an exit code of their own, so scripts wrapping llmbundle can branch on the
+//! outcome. They are attached to errors as context, other errors exit with 1 and invalid command
+//! lines with 2, like clap does.
+
+use std::fmt;
+use std::process::ExitCode;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Failure {
+    /// No file matched the patterns (3)
+    NoMatches,
+    /// A pattern is not a valid glob (4)
+    Pattern,
+    /// Some files couldn't be read, the bundle was still sent without their content (5)
+    Unreadable,
+    /// The files don't fit within `--max-tokens` with `--strict-budget` (6)
+    Budget,
+    /// The clipboard couldn't be read or written (7)
+    Clipboard,
+}
+
+impl Failure {
+    fn code(self) -> u8 {
+        match self {
+            Failure::NoMatches => 3,
+            Failure::Pattern => 4,
+            Failure::Unreadable => 5,
+            Failure::Budget => 6,
+            Failure::Clipboard => 7,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::NoMatches => "No files matched",
+            Failure::Pattern => "Invalid pattern",
+            Failure::Unreadable => "Some files couldn't be read",
+            Failure::Budget => "The files exceed the token budget",
+            Failure::Clipboard => "Failed to use the clipboard",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Exit code of a run that failed with `error`.
+pub fn exit_code(error: &anyhow::Error) -> ExitCode {
+    ExitCode::from(
+        error
+            .downcast_ref::<Failure>()
+            .map_or(1, |failure| failure.code()),
+    )
+}
@@ -0,0 +1,257 @@
+//! The files of a git revision for `--rev`, and the ones differing between two for `--between`,
+//! read straight from the repository through the system's `git` binary, without checking the
+//! revisions out or touching the working tree.
+
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// `--root` naming the root of the repository of the working directory
+pub const REPO_ROOT: &str = "git";
+
+/// The root of the repository `dir` is in: the closest of it and its parents with a `.git`,
+/// which is a file in the worktrees and submodules.
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Lists the files of `rev` under `root`, relative to the root, leaving out the hidden ones like
+/// the walk does. The submodules and symbolic links aren't files of the revision.
+pub fn files(root: &Path, rev: &str, max_depth: Option<usize>) -> Result<Vec<PathBuf>> {
+    let commit = resolve(root, rev)?;
+    let listing = output(root, &["ls-tree", "-r", "-z", &commit])
+        .with_context(|| format!("Failed to list the files of {rev}"))?;
+    let files = listing
+        .split(|&byte| byte == 0)
+        .filter_map(|entry| {
+            let entry = std::str::from_utf8(entry).ok()?;
+            let (info, path) = entry.split_once('\t')?;
+            let mut info = info.split(' ');
+            let (mode, kind) = (info.next()?, info.next()?);
+            (kind == "blob" && mode != "120000").then(|| PathBuf::from(path))
+        })
+        .filter(|path| !hidden(path))
+        .filter(|path| max_depth.is_none_or(|depth| path.components().count() <= depth))
+        .collect();
+    Ok(files)
+}
+
+/// The content of each of `paths` (relative to `root`) at `rev`, in order, or why it couldn't be
+/// read. The files are read through a single `git cat-file`.
+pub fn read(root: &Path, rev: &str, paths: &[PathBuf]) -> Result<Vec<Result<Vec<u8>, String>>> {
+    let commit = resolve(root, rev)?;
+    let mut child = git(root)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run git, is it installed?")?;
+
+    // Written meanwhile, so git isn't blocked on a full stdout while we're blocked on its stdin
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let objects: String = paths
+        .iter()
+        .map(|path| format!("{commit}:./{}\n", llmbundle_core::paths::display(path)))
+        .collect();
+    let writer = std::thread::spawn(move || stdin.write_all(objects.as_bytes()));
+
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut contents = Vec::with_capacity(paths.len());
+    let mut header = String::new();
+    for _ in paths {
+        header.clear();
+        if stdout.read_line(&mut header)? == 0 {
+            bail!("git exited before reading every file of {rev}");
+        }
+        // `<object> <type> <size>` followed by the object, or `<name> missing` for a path git
+        // can't resolve
+        let mut fields = header.trim_end().rsplitn(3, ' ');
+        let (size, kind) = (fields.next(), fields.next());
+        let Some(size) = size.and_then(|size| size.parse::<usize>().ok()) else {
+            contents.push(Err(format!("Not a file of {rev}")));
+            continue;
+        };
+        let mut content = vec![0; size + 1];
+        stdout
+            .read_exact(&mut content)
+            .with_context(|| format!("Failed to read the files of {rev} from git"))?;
+        // Without the newline ending each object
+        content.pop();
+        contents.push(match kind {
+            Some("blob") => Ok(content),
+            _ => Err(format!("Not a file of {rev}")),
+        });
+    }
+    drop(stdout);
+
+    writer
+        .join()
+        .expect("the writer doesn't panic")
+        .with_context(|| format!("Failed to ask git for the files of {rev}"))?;
+    let status = child.wait().context("Failed to wait for git")?;
+    if !status.success() {
+        bail!("Failed to read the files of {rev}, git exited with {status}");
+    }
+    Ok(contents)
+}
+
+/// Two revisions given as `A..B`, or `A...B` to start from their merge base, either defaulting to
+/// `HEAD`
+#[derive(Clone, Debug)]
+pub struct Range {
+    pub from: String,
+    pub to: String,
+    /// Whether it's from the merge base of both, like the changes of the branch `to` since it
+    /// forked from `from`
+    pub merge_base: bool,
+}
+
+impl Range {
+    /// Parses `A..B` or `A...B`.
+    pub fn parse(s: &str) -> Result<Range, String> {
+        let (from, to, merge_base) = match (s.split_once("..."), s.split_once("..")) {
+            (Some((from, to)), _) => (from, to, true),
+            (None, Some((from, to))) => (from, to, false),
+            (None, None) => return Err(format!("expected A..B or A...B, got {s}")),
+        };
+        let end = |rev: &str| match rev {
+            "" => "HEAD".to_string(),
+            rev => rev.to_string(),
+        };
+        Ok(Range {
+            from: end(from),
+            to: end(to),
+            merge_base,
+        })
+    }
+
+    /// The commits the range goes from and to, the merge base being the first one if asked for.
+    pub fn commits(&self, root: &Path) -> Result<(String, String)> {
+        let (from, to) = (resolve(root, &self.from)?, resolve(root, &self.to)?);
+        if !self.merge_base {
+            return Ok((from, to));
+        }
+        let base = output(root, &["merge-base", &from, &to])
+            .with_context(|| format!("{} and {} have no merge base", self.from, self.to))?;
+        Ok((String::from_utf8_lossy(&base).trim().to_string(), to))
+    }
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let dots = if self.merge_base { "..." } else { ".." };
+        write!(f, "{}{dots}{}", self.from, self.to)
+    }
+}
+
+/// Lists the files under `root` that differ between the ends of `range`, relative to the root,
+/// leaving out the hidden ones like [`files`], and the ones the range deletes unless `deleted`.
+pub fn changed(
+    root: &Path,
+    range: &Range,
+    deleted: bool,
+    max_depth: Option<usize>,
+) -> Result<Vec<PathBuf>> {
+    let (from, to) = range.commits(root)?;
+    // A renamed file is the deletion of its old path and the addition of the new one
+    let mut args = vec!["diff", "--name-only", "-z", "--no-renames", "--relative"];
+    if !deleted {
+        args.push("--diff-filter=d");
+    }
+    args.extend([from.as_str(), to.as_str(), "--"]);
+    let listing = output(root, &args)
+        .with_context(|| format!("Failed to list the files changed in {range}"))?;
+    let files = listing
+        .split(|&byte| byte == 0)
+        .filter_map(|path| std::str::from_utf8(path).ok())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| !hidden(path))
+        .filter(|path| max_depth.is_none_or(|depth| path.components().count() <= depth))
+        .collect();
+    Ok(files)
+}
+
+/// The unified diff of each of `paths` (relative to `root`) between the ends of `range`, in
+/// order, or why it couldn't be made.
+pub fn diffs(
+    root: &Path,
+    range: &Range,
+    paths: &[PathBuf],
+) -> Result<Vec<Result<Vec<u8>, String>>> {
+    let (from, to) = range.commits(root)?;
+    let diffs = paths
+        .par_iter()
+        .map(|path| {
+            let pathspec = format!(":(literal){}", llmbundle_core::paths::display(path));
+            let args = [
+                "diff",
+                "--no-color",
+                "--no-ext-diff",
+                "--no-renames",
+                "--relative",
+                &from,
+                &to,
+                "--",
+                &pathspec,
+            ];
+            output(root, &args).map_err(|e| format!("Failed to diff the file: {e:#}"))
+        })
+        .collect();
+    Ok(diffs)
+}
+
+/// Whether `path` has a hidden component, which the walk leaves out.
+pub fn hidden(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// The commit `rev` names, so the files are listed and read from the same one. Resolving it also
+/// checks `rev` isn't an option of git, before any file is listed (see [`with_revision`]).
+fn resolve(root: &Path, rev: &str) -> Result<String> {
+    let commit = output(
+        root,
+        &[
+            "rev-parse",
+            "--verify",
+            "--end-of-options",
+            &format!("{rev}^{{commit}}"),
+        ],
+    )
+    .with_context(|| format!("Unknown git revision {rev}"))?;
+    Ok(String::from_utf8_lossy(&commit).trim().to_string())
+}
+
+/// The stdout of git run with `args` in `root`, failing with its stderr.
+pub fn output(root: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = git(root)
+        .args(args)
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to run git, is it installed?")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+/// `args` followed by `rev` when there's one, for the commands taking a revision before their
+/// paths. It's passed as it is, since [`resolve`] checked it names a commit when the files were
+/// listed, and `git blame` doesn't take `--end-of-options`.
+pub fn with_revision<'a>(mut args: Vec<&'a str>, rev: Option<&'a str>) -> Vec<&'a str> {
+    args.extend(rev);
+    args
+}
+
+/// git run in `root`. The paths it's given and lists are relative to `root`, and only those under
+/// it are listed, with `--relative` for `log` and `diff`.
+pub fn git(root: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(root);
+    command
+}
@@ -0,0 +1,71 @@
+//! `--with-git-log`: the last commit of each file in its header, from `git log`, so the model
+//! can tell the files changed last week from the ones untouched for years.
+
+use crate::git;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// Starts the line of the last commit in the header of a file
+const HEADER: &str = "[file last commit]: ";
+
+/// The header line of the last commit of each of `paths` (relative to `root`) in the history of
+/// `rev`, or of `HEAD`, like `[file last commit]: abc1234 Jane Doe, 2024-05-01: Fix the parser`.
+/// The files that were never committed have none.
+pub fn headers(
+    root: &Path,
+    rev: Option<&str>,
+    paths: &[PathBuf],
+) -> Result<HashMap<PathBuf, String>> {
+    let mut args = git::with_revision(
+        vec![
+            "log",
+            "--format=%x01%h%x00%an%x00%as%x00%s",
+            "--name-only",
+            "-z",
+            "--relative",
+        ],
+        rev,
+    );
+    args.extend(["--", "."]);
+    let mut child = git::git(root)
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run git, is it installed?")?;
+
+    // The commits come from the newest, a file's first one being its last, so the history is
+    // only read until every file is found
+    let mut wanted: HashSet<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    let mut headers = HashMap::new();
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut record = Vec::new();
+    while !wanted.is_empty() {
+        record.clear();
+        if stdout.read_until(0x01, &mut record)? == 0 {
+            break;
+        }
+        // `<sha>\0<author>\0<date>\0<subject>\0\n<path>\0<path>\0...`
+        let record = String::from_utf8_lossy(record.strip_suffix(&[0x01]).unwrap_or(&record));
+        let mut fields = record.split('\0');
+        let (Some(sha), Some(author), Some(date), Some(subject)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        for path in fields.map(|path| path.trim_start_matches('\n')) {
+            if wanted.remove(Path::new(path)) {
+                let header = format!("{HEADER}{sha} {author}, {date}: {subject}");
+                headers.insert(PathBuf::from(path), header);
+            }
+        }
+    }
+    // Not waiting for the rest of the history
+    drop(stdout);
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(headers)
+}
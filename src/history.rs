@@ -0,0 +1,221 @@
+//! Local record of the bundles generated in a project, so a previous bundle can be sent again
+//! byte for byte (e.g. to keep hitting an LLM provider's prompt cache).
+//!
+//! Entries are stored one JSON object per line in `history/entries.jsonl` in the
+//! [state directory](crate::state), and each rendered bundle in a file named after its BLAKE3
+//! (or its SHA-256 for the bundles recorded by older versions, which are still read).
+
+use crate::bundle::Bundle;
+use crate::json::Value;
+use crate::{blake3, sha256, state, Cli};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+const DIR: &str = ".llmbundle/state/history";
+/// Older entries (and their bundles) are removed past this many
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Args)]
+pub struct RecopyArgs {
+    /// Id of the bundle, as shown by `history`
+    id: u64,
+
+    /// Extra flags appended to the recorded ones, e.g. `--output stdout`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct AgainArgs {
+    /// Extra flags appended to the recorded ones, e.g. `--output stdout`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+struct Entry {
+    id: u64,
+    /// Unix timestamp of the run
+    time: u64,
+    /// Command line of the run, without the program name
+    args: Vec<String>,
+    /// BLAKE3 of the bundle text, or its SHA-256 when recorded by an older version
+    hash: String,
+    files: usize,
+    tokens: usize,
+}
+
+impl Entry {
+    fn to_json(&self) -> Value {
+        Value::object([
+            ("id", self.id.into()),
+            ("time", self.time.into()),
+            ("args", self.args.clone().into()),
+            ("hash", self.hash.as_str().into()),
+            ("files", self.files.into()),
+            ("tokens", self.tokens.into()),
+        ])
+    }
+
+    fn from_json(json: &Value) -> Option<Entry> {
+        Some(Entry {
+            id: json.get("id")?.as_u64()?,
+            time: json.get("time")?.as_u64()?,
+            args: json
+                .get("args")?
+                .as_array()?
+                .iter()
+                .map(|arg| arg.as_str().map(str::to_string))
+                .collect::<Option<_>>()?,
+            hash: json.get("hash")?.as_str()?.to_string(),
+            files: json.get("files")?.as_u64()? as usize,
+            tokens: json.get("tokens")?.as_u64()? as usize,
+        })
+    }
+}
+
+/// Creates the history directory if needed.
+fn create_dir() -> Result<()> {
+    state::create_dir(Path::new("."))?;
+    std::fs::create_dir_all(DIR).with_context(|| format!("Failed to create {DIR}"))
+}
+
+/// Adds the bundle generated from `args` to the history.
+pub fn record(args: &Cli, bundle: &Bundle) -> Result<()> {
+    create_dir()?;
+
+    let hash = blake3::hex(bundle.text.as_bytes());
+    let output = output_path(&hash);
+    if !output.exists() {
+        std::fs::write(&output, &bundle.text)
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+    }
+
+    let mut entries = read()?;
+    entries.push(Entry {
+        id: entries.last().map_or(1, |entry| entry.id + 1),
+        time: state::now(),
+        args: args.argv.clone(),
+        hash,
+        files: bundle.files.len(),
+        tokens: bundle.files.iter().map(|f| f.token_count).sum(),
+    });
+
+    if entries.len() > MAX_ENTRIES {
+        let removed: Vec<_> = entries.drain(..entries.len() - MAX_ENTRIES).collect();
+        for entry in removed {
+            if !entries.iter().any(|kept| kept.hash == entry.hash) {
+                // A leftover bundle only wastes space, so failing to remove it isn't an error
+                let _ = std::fs::remove_file(output_path(&entry.hash));
+            }
+        }
+    }
+
+    let mut contents = String::new();
+    for entry in &entries {
+        contents.push_str(&entry.to_json().to_string());
+        contents.push('\n');
+    }
+    let path = entries_path();
+    std::fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Prints the recorded bundles, most recent last.
+pub fn list() -> Result<()> {
+    let entries = read()?;
+    if entries.is_empty() {
+        println!("{}", "No bundles recorded yet.".red());
+        return Ok(());
+    }
+
+    let now = state::now();
+    for entry in entries {
+        let info = format!(
+            "({}, {} files, {} tokens, {})",
+            age(now.saturating_sub(entry.time)),
+            entry.files,
+            entry.tokens,
+            &entry.hash[..12]
+        )
+        .black();
+        println!(
+            "{} llmbundle {} {info}",
+            format!("{:>4}", entry.id).bold(),
+            entry.args.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Sends a recorded bundle to the outputs of its run (or the ones given), exactly as it was.
+pub fn recopy(args: &RecopyArgs) -> Result<()> {
+    let entries = read()?;
+    let Some(entry) = entries.iter().find(|entry| entry.id == args.id) else {
+        bail!("No bundle with id {} in the history", args.id);
+    };
+
+    let output = output_path(&entry.hash);
+    let text = std::fs::read_to_string(&output)
+        .with_context(|| format!("Failed to read {}", output.display()))?;
+    // The bundles recorded by older versions have their SHA-256
+    if ![blake3::hex(text.as_bytes()), sha256::hex(text.as_bytes())].contains(&entry.hash) {
+        bail!(
+            "{} no longer matches the recorded hash, the bundle can't be reproduced exactly",
+            output.display()
+        );
+    }
+
+    let cli = Cli::parse_args(entry.args.iter().chain(&args.args).cloned().collect())?;
+    crate::emit(&cli.run, Bundle::from_text(text))
+}
+
+/// Bundles again with the command line of the last recorded run.
+pub fn again(args: &AgainArgs) -> Result<()> {
+    let entries = read()?;
+    let Some(entry) = entries.last() else {
+        bail!("No bundles recorded yet");
+    };
+    crate::run(Cli::parse_args(
+        entry.args.iter().chain(&args.args).cloned().collect(),
+    )?)
+}
+
+/// Reads the recorded entries, oldest first.
+fn read() -> Result<Vec<Entry>> {
+    let path = entries_path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            Value::parse(line)
+                .ok()
+                .as_ref()
+                .and_then(Entry::from_json)
+                .with_context(|| format!("Invalid history entry in {}: {line}", path.display()))
+        })
+        .collect()
+}
+
+fn entries_path() -> PathBuf {
+    Path::new(DIR).join("entries.jsonl")
+}
+
+fn output_path(hash: &str) -> PathBuf {
+    Path::new(DIR).join(format!("{hash}.txt"))
+}
+
+fn age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
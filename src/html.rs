@@ -0,0 +1,297 @@
+//! Renders bundled files as syntax-highlighted HTML for the rich-text clipboard flavor.
+//!
+//! The highlighter is deliberately simple: it recognizes comments, strings, numbers and the
+//! keywords of common languages, which is enough for pasted code to read well in documents.
+
+use crate::bundle::ProcessedFile;
+
+const STYLE_COMMENT: &str = "color:#6a737d;font-style:italic";
+const STYLE_STRING: &str = "color:#032f62";
+const STYLE_NUMBER: &str = "color:#005cc5";
+const STYLE_KEYWORD: &str = "color:#d73a49;font-weight:bold";
+
+struct Language {
+    extensions: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    keywords: &'static [&'static str],
+    /// Whether `'` can start a lifetime (`'a`) rather than a char literal
+    lifetimes: bool,
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        extensions: &["rs"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        lifetimes: true,
+    },
+    Language {
+        extensions: &["py", "pyi"],
+        line_comments: &["#"],
+        block_comment: None,
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+            "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return",
+            "True", "try", "while", "with", "yield",
+        ],
+        lifetimes: false,
+    },
+    Language {
+        extensions: &["js", "jsx", "ts", "tsx", "mjs", "cjs"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "return",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        lifetimes: false,
+    },
+    Language {
+        extensions: &["go"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "false",
+            "fallthrough",
+            "for",
+            "func",
+            "go",
+            "goto",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        lifetimes: false,
+    },
+    Language {
+        extensions: &[
+            "c", "h", "cc", "cpp", "cxx", "hpp", "java", "kt", "cs", "swift", "scala",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        keywords: &[
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "do",
+            "else",
+            "enum",
+            "extends",
+            "false",
+            "final",
+            "for",
+            "if",
+            "implements",
+            "import",
+            "include",
+            "new",
+            "null",
+            "nullptr",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "static",
+            "struct",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "typedef",
+            "void",
+            "while",
+        ],
+        lifetimes: false,
+    },
+    Language {
+        extensions: &["sh", "bash", "zsh", "toml", "yaml", "yml", "rb", "pl", "r"],
+        line_comments: &["#"],
+        block_comment: None,
+        keywords: &[
+            "case", "def", "do", "done", "elif", "else", "end", "esac", "export", "false", "fi",
+            "for", "function", "if", "in", "local", "return", "then", "true", "while",
+        ],
+        lifetimes: false,
+    },
+];
+
+/// Renders the files as an HTML document with one highlighted code block per file.
+pub fn render(files: &[ProcessedFile]) -> String {
+    let mut html = String::from("<html><body>\n");
+    for file in files {
+        let path = file.path.display().to_string();
+        html.push_str(&format!("<p><b>{}</b></p>\n", escape(&path)));
+        html.push_str("<pre style=\"font-family:monospace;background:#f6f8fa;padding:8px\"><code>");
+        let language = file
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| LANGUAGES.iter().find(|l| l.extensions.contains(&e)));
+        match language {
+            Some(language) => highlight(&file.content, language, &mut html),
+            None => html.push_str(&escape(&file.content)),
+        }
+        html.push_str("</code></pre>\n");
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn push_span(html: &mut String, style: &str, text: &str) {
+    html.push_str(&format!("<span style=\"{style}\">{}</span>", escape(text)));
+}
+
+fn highlight(code: &str, language: &Language, html: &mut String) {
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        if let Some((start, end)) = language.block_comment {
+            if rest.starts_with(start) {
+                let len = rest[start.len()..]
+                    .find(end)
+                    .map_or(rest.len(), |i| start.len() + i + end.len());
+                push_span(html, STYLE_COMMENT, &rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+        }
+        if language.line_comments.iter().any(|lc| rest.starts_with(lc)) {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            push_span(html, STYLE_COMMENT, &rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        if c == '\'' && language.lifetimes && is_lifetime(rest) {
+            html.push('\'');
+            rest = &rest[1..];
+            continue;
+        }
+        if c == '"' || c == '\'' || c == '`' {
+            let len = string_len(rest, c);
+            push_span(html, STYLE_STRING, &rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.')
+                .unwrap_or(rest.len());
+            push_span(html, STYLE_NUMBER, &rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if language.keywords.contains(&word) {
+                push_span(html, STYLE_KEYWORD, word);
+            } else {
+                html.push_str(word);
+            }
+            rest = &rest[len..];
+            continue;
+        }
+        html.push_str(&escape(&rest[..c.len_utf8()]));
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+fn is_lifetime(text: &str) -> bool {
+    let mut chars = text.chars().skip(1);
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_') && chars.next() != Some('\'')
+}
+
+/// Length of the string literal at the start of `text`, stopping at the closing quote or at the
+/// end of the line so an unmatched quote (e.g. a Rust lifetime) doesn't swallow the file.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
@@ -0,0 +1,254 @@
+//! Minimal HTTP client built on top of the `curl` binary.
+//!
+//! The request (including headers, which may carry API keys) is handed to curl as a config file
+//! on stdin so nothing sensitive shows up in the process list. The body goes through a temp file
+//! instead, as curl rejects the config lines of 100 KiB or more, and stdin already holds the
+//! config.
+
+use crate::temp;
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub struct Request {
+    method: &'static str,
+    url: String,
+    headers: Vec<String>,
+    body: Option<String>,
+}
+
+impl Request {
+    pub fn get(url: impl Into<String>) -> Self {
+        Request {
+            method: "GET",
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Request {
+            method: "POST",
+            ..Request::get(url)
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push(format!("{name}: {value}"));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Sends the request and returns the whole response body.
+    pub fn send(self) -> Result<String> {
+        let mut body = String::new();
+        self.send_with(|reader| {
+            reader
+                .read_to_string(&mut body)
+                .context("Failed to read HTTP response")?;
+            Ok(())
+        })?;
+        Ok(body)
+    }
+
+    /// Sends the request and returns the whole response body as bytes, for binary downloads.
+    pub fn send_bytes(self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.send_with(|reader| {
+            reader
+                .read_to_end(&mut body)
+                .context("Failed to read HTTP response")?;
+            Ok(())
+        })?;
+        Ok(body)
+    }
+
+    /// Sends the request and calls `on_line` for every line of the response as it arrives.
+    pub fn stream_lines(self, mut on_line: impl FnMut(&str) -> Result<()>) -> Result<()> {
+        self.send_with(|reader| {
+            for line in BufReader::new(reader).lines() {
+                on_line(&line.context("Failed to read HTTP response")?)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The config of the request for curl, sending the body from the file at `body` if any.
+    fn config(&self, body: Option<&Path>) -> String {
+        let mut config = format!(
+            "url = {}\nrequest = {}\n",
+            quote(&self.url),
+            quote(self.method)
+        );
+        for header in &self.headers {
+            config.push_str(&format!("header = {}\n", quote(header)));
+        }
+        if let Some(body) = body {
+            // `@` reads the file as is, without stripping its newlines like `--data` does
+            let file = format!("@{}", body.display());
+            config.push_str(&format!("data-binary = {}\n", quote(&file)));
+        }
+        config
+    }
+
+    fn send_with(self, read: impl FnOnce(&mut dyn Read) -> Result<()>) -> Result<()> {
+        let body = match &self.body {
+            Some(body) => Some(BodyFile::create(body)?),
+            None => None,
+        };
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--no-buffer", "--location"])
+            .args(["--fail-with-body", "--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run curl, make sure it is installed and in PATH")?;
+
+        let config = self.config(body.as_ref().map(|body| body.path.as_path()));
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        std::thread::spawn(move || stdin.write_all(config.as_bytes()));
+
+        let mut stdout = TailReader {
+            inner: child.stdout.take().expect("stdout is piped"),
+            tail: Vec::new(),
+        };
+        let result = read(&mut stdout);
+
+        // Close our end of the pipe so curl can't block on a response we stopped reading
+        let TailReader { inner, tail } = stdout;
+        drop(inner);
+
+        let output = child
+            .wait_with_output()
+            .context("Failed to wait for curl")?;
+        if !output.status.success() {
+            bail!(
+                "HTTP {} {} failed: {}\n{}",
+                self.method,
+                self.url,
+                String::from_utf8_lossy(&output.stderr).trim(),
+                String::from_utf8_lossy(&tail).trim()
+            );
+        }
+
+        result
+    }
+}
+
+/// The body of a request written to a temp file only the user can read, removed once dropped
+struct BodyFile {
+    path: PathBuf,
+}
+
+impl BodyFile {
+    fn create(body: &str) -> Result<BodyFile> {
+        let (path, mut file) = temp::create("body")?;
+        let body_file = BodyFile { path };
+        file.write_all(body.as_bytes())
+            .and_then(|()| file.flush())
+            .context("Failed to write the request body")?;
+        Ok(body_file)
+    }
+}
+
+impl Drop for BodyFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Keeps the last bytes read so error responses can be reported even after being consumed.
+struct TailReader<R> {
+    inner: R,
+    tail: Vec<u8>,
+}
+
+const TAIL_SIZE: usize = 4096;
+
+impl<R: Read> Read for TailReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.tail.extend_from_slice(&buf[..n]);
+        if self.tail.len() > TAIL_SIZE {
+            self.tail.drain(..self.tail.len() - TAIL_SIZE);
+        }
+        Ok(n)
+    }
+}
+
+/// Quotes a value for a curl config file.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Serves a single request on a local port, answering with `answer` and handing the body of
+    /// the request over through the returned thread.
+    pub(crate) fn serve_once(answer: &str) -> (String, std::thread::JoinHandle<Vec<u8>>) {
+        let answer = answer.to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{answer}",
+                answer.len()
+            )
+            .unwrap();
+            body
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn sends_bodies_over_a_megabyte() {
+        let (url, server) = serve_once("received");
+        // Quotes, backslashes and newlines, which the config file would have to escape
+        let body = "line with \"quotes\" and \\ backslashes\n".repeat(40_000);
+        assert!(body.len() > 1024 * 1024);
+        let response = Request::post(url).body(body.clone()).send().unwrap();
+        assert_eq!(response, "received");
+        assert_eq!(server.join().unwrap(), body.as_bytes());
+    }
+}
@@ -0,0 +1,380 @@
+//! Small HTTP server so browser extensions and internal tools can fetch fresh bundles.
+//!
+//! Endpoints:
+//! - `GET /bundle?pattern=<glob>&max_tokens=<n>&format=text|json`
+//! - `GET /files?pattern=<glob>&format=text|json`
+//!
+//! Only requests addressed to the server itself (a `Host` of localhost or the address it's bound
+//! to) from no other web page are answered, so a site opened in the browser can't read the
+//! project through DNS rebinding.
+
+use crate::bundle;
+use crate::json::Value;
+use crate::serve::{self, ServeArgs};
+use anyhow::{Context, Result};
+use llmbundle_core::paths;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Connections handled at once at most, the next ones being answered with a 503
+const MAX_CONNECTIONS: usize = 16;
+/// How long the client may take to send its request, and to read each part of the response
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest request line and headers read, past which the request is cut off
+const MAX_HEAD: u64 = 64 * 1024;
+
+/// Origins of the browser extensions, which are allowed to fetch bundles
+const EXTENSION_SCHEMES: [&str; 3] = [
+    "chrome-extension://",
+    "moz-extension://",
+    "safari-web-extension://",
+];
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+
+    fn json(body: Value) -> Self {
+        Response {
+            status: 200,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+}
+
+pub fn run(args: &ServeArgs, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr).with_context(|| format!("Failed to bind {addr}"))?;
+    eprintln!(
+        "Serving bundles of {} on http://{}",
+        args.root,
+        listener.local_addr()?
+    );
+
+    let connections = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {e}");
+                    continue;
+                }
+            };
+            let timeouts = stream
+                .set_read_timeout(Some(READ_TIMEOUT))
+                .and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT)));
+            if let Err(e) = timeouts {
+                eprintln!("Failed to set the timeouts of the connection: {e}");
+                continue;
+            }
+            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                let busy = Response::text(503, "Too many connections, try again later\n");
+                let _ = respond(&mut stream, &busy);
+                continue;
+            }
+            let connections = &connections;
+            scope.spawn(move || {
+                if let Err(e) = handle_connection(args, stream) {
+                    eprintln!("Failed to handle request: {e:#}");
+                }
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_connection(args: &ServeArgs, mut stream: TcpStream) -> Result<()> {
+    let local = stream.local_addr()?;
+    let mut reader = BufReader::new((&stream).take(MAX_HEAD));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut accepts_json = false;
+    let (mut host, mut origin) = (None, None);
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("accept") && value.contains("application/json") {
+                accepts_json = true;
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    log::info!("{method} {target}");
+
+    let response = if !host.is_some_and(|host| is_local(&host, local)) {
+        Response::text(
+            403,
+            "The Host header must be localhost or the server's address\n",
+        )
+    } else if !origin.is_none_or(|origin| is_allowed_origin(&origin, local)) {
+        Response::text(403, "Requests from web pages aren't allowed\n")
+    } else if method != "GET" {
+        Response::text(405, "Only GET is supported\n")
+    } else {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        match route(args, path, &parse_query(query), accepts_json) {
+            Ok(response) => response,
+            Err(e) => Response::text(500, format!("{e:#}\n")),
+        }
+    };
+    respond(&mut stream, &response)
+}
+
+fn respond(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn route(
+    args: &ServeArgs,
+    path: &str,
+    query: &[(String, String)],
+    accepts_json: bool,
+) -> Result<Response> {
+    let param = |name: &str| query.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+
+    let json = match param("format").map(String::as_str) {
+        Some("json") => true,
+        Some("text") => false,
+        Some(format) => {
+            return Ok(Response::text(
+                400,
+                format!("Unknown format {format}, expected text or json\n"),
+            ))
+        }
+        None => accepts_json,
+    };
+    let max_tokens = match param("max_tokens").map(|v| v.parse::<usize>()) {
+        None => None,
+        Some(Ok(max_tokens)) => Some(max_tokens),
+        Some(Err(_)) => return Ok(Response::text(400, "max_tokens must be a number\n")),
+    };
+
+    let patterns = query
+        .iter()
+        .filter(|(k, _)| k == "pattern")
+        .map(|(_, v)| v.clone())
+        .collect();
+    let bundle_args = args.bundle_args(patterns, max_tokens);
+
+    match path {
+        "/bundle" => {
+            let bundle = serve::bundle(&bundle_args, None)?;
+            if !json {
+                return Ok(Response::text(200, bundle.text));
+            }
+            let files = bundle
+                .files
+                .iter()
+                .map(|file| {
+                    Value::object([
+                        ("path", paths::display(&file.path).into()),
+                        ("lines", file.line_count.into()),
+                        ("tokens", file.token_count.into()),
+                    ])
+                })
+                .collect();
+            let dropped = bundle
+                .dropped
+                .iter()
+                .map(|path| paths::display(path))
+                .collect::<Vec<_>>();
+            Ok(Response::json(Value::object([
+                ("files", Value::Array(files)),
+                ("dropped", dropped.into()),
+                (
+                    "tokens",
+                    bundle
+                        .files
+                        .iter()
+                        .map(|f| f.token_count)
+                        .sum::<usize>()
+                        .into(),
+                ),
+                ("text", bundle.text.into()),
+            ])))
+        }
+        "/files" => {
+            let files: Vec<_> = bundle::find_files(&bundle_args)?
+                .iter()
+                .map(|path| paths::display(path))
+                .collect();
+            if json {
+                Ok(Response::json(Value::object([("files", files.into())])))
+            } else {
+                let mut body = files.join("\n");
+                body.push('\n');
+                Ok(Response::text(200, body))
+            }
+        }
+        _ => Ok(Response::text(
+            404,
+            "Not found, available endpoints: /bundle, /files\n",
+        )),
+    }
+}
+
+/// Whether `host`, the `Host` header of a request, names the server at `local`: localhost or the
+/// address the request was sent to, with its port if any.
+fn is_local(host: &str, local: SocketAddr) -> bool {
+    let (name, port) = match host.rsplit_once(':') {
+        // Not the last group of an IPv6 address
+        Some((name, port)) if !port.contains(']') => (name, Some(port)),
+        _ => (host, None),
+    };
+    if port.is_some_and(|port| port.parse() != Ok(local.port())) {
+        return false;
+    }
+    let name = name
+        .strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .unwrap_or(name);
+    match name.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback() || ip == local.ip(),
+        Err(_) => name.eq_ignore_ascii_case("localhost"),
+    }
+}
+
+/// Whether a request from `origin`, its `Origin` header, may be answered: from a browser extension
+/// or a page served by the server itself.
+fn is_allowed_origin(origin: &str, local: SocketAddr) -> bool {
+    if EXTENSION_SCHEMES
+        .iter()
+        .any(|scheme| origin.starts_with(scheme))
+    {
+        return true;
+    }
+    origin
+        .strip_prefix("http://")
+        .is_some_and(|host| is_local(host, local))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'+', _) => out.push(b' '),
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 2;
+            }
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local() -> SocketAddr {
+        "192.168.1.20:7878".parse().unwrap()
+    }
+
+    #[test]
+    fn accepts_the_hosts_of_the_server() {
+        for host in [
+            "localhost",
+            "LOCALHOST:7878",
+            "127.0.0.1:7878",
+            "[::1]:7878",
+            "[::1]",
+            "192.168.1.20",
+        ] {
+            assert!(is_local(host, local()), "{host}");
+        }
+    }
+
+    #[test]
+    fn rejects_the_other_hosts() {
+        for host in [
+            "evil.example",
+            "evil.example:7878",
+            "localhost:8080",
+            "192.168.1.21:7878",
+            "localhost.evil.example",
+            "",
+        ] {
+            assert!(!is_local(host, local()), "{host}");
+        }
+    }
+
+    #[test]
+    fn allows_extensions_and_its_own_pages_only() {
+        assert!(is_allowed_origin("chrome-extension://abcdef", local()));
+        assert!(is_allowed_origin("moz-extension://1234-5678", local()));
+        assert!(is_allowed_origin("http://localhost:7878", local()));
+        assert!(!is_allowed_origin("https://evil.example", local()));
+        assert!(!is_allowed_origin("http://evil.example:7878", local()));
+        assert!(!is_allowed_origin("null", local()));
+    }
+}
@@ -0,0 +1,322 @@
+//! Container images given as the root, like `--root docker://nginx:1.27/etc/nginx`: the
+//! filesystem of the image is exported through the `docker` (or `podman`) CLI into the user's
+//! cache directory, once per image, then bundled like a directory, to see what actually shipped
+//! in the image.
+
+use crate::remote;
+use anyhow::{bail, Context, Result};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of the images given as the root
+pub const SCHEME: &str = "docker://";
+
+/// The directory of the exported filesystem of the image `reference` names (the root without
+/// its `docker://`), at the path after the tag if any, exporting it the first time.
+pub fn checkout(reference: &str) -> Result<PathBuf> {
+    let (image, path) = split(reference);
+    // Not taken for an option of the CLI
+    if image.is_empty() || image.starts_with('-') {
+        bail!("Invalid image {image:?}");
+    }
+    let engine = engine();
+    let cache = remote::cache_dir()
+        .context("Failed to find the cache directory, set XDG_CACHE_HOME or HOME")?
+        .join("images");
+    std::fs::create_dir_all(&cache)
+        .with_context(|| format!("Failed to create {}", cache.display()))?;
+    let dir = match image_id(engine, image) {
+        Some(id) if cache.join(&id).is_dir() => cache.join(id),
+        _ => export(engine, image, &cache)?,
+    };
+    let root = dir.join(path);
+    if !root.is_dir() {
+        bail!("/{path} isn't a directory of {image}");
+    }
+    Ok(root)
+}
+
+/// Splits `reference` into the image and the path within it, which starts at the first `/`
+/// after the tag or digest: `ghcr.io/org/app:1.0/srv/app` is `srv/app` in `ghcr.io/org/app:1.0`.
+/// Without a tag, it's all the image.
+fn split(reference: &str) -> (&str, &str) {
+    let mut start = 0;
+    for (i, segment) in reference.split('/').enumerate() {
+        let end = start + segment.len();
+        // The registry host comes first, possibly with a port, like `localhost:5000`
+        let host = segment.split(':').next().unwrap_or_default();
+        let registry =
+            i == 0 && end < reference.len() && (host.contains('.') || host == "localhost");
+        if !registry && segment.contains([':', '@']) {
+            let path = reference.get(end + 1..).unwrap_or_default();
+            return (&reference[..end], path.trim_matches('/'));
+        }
+        start = end + 1;
+    }
+    (reference, "")
+}
+
+/// `docker`, or `podman` where docker isn't installed.
+fn engine() -> &'static str {
+    let installed = |engine| {
+        Command::new(engine)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    };
+    match installed("docker") || !installed("podman") {
+        true => "docker",
+        false => "podman",
+    }
+}
+
+/// Hex of the ID of `image`, if it's been pulled.
+fn image_id(engine: &str, image: &str) -> Option<String> {
+    let id = output(
+        engine,
+        &["image", "inspect", "--format", "{{.Id}}", "--", image],
+    )
+    .ok()?;
+    let id = String::from_utf8_lossy(&id);
+    let hex = id.trim().rsplit(':').next()?;
+    Some(hex.chars().take(12).collect()).filter(|hex: &String| !hex.is_empty())
+}
+
+/// Exports the filesystem of `image` into a directory of `cache` named after its ID, through a
+/// container created from it, pulling the image if needed.
+fn export(engine: &str, image: &str, cache: &Path) -> Result<PathBuf> {
+    eprintln!("Exporting the filesystem of {image}...");
+    // The pull shows its progress on stderr, and the container is never started
+    let created = Command::new(engine)
+        .args(["create", image, "true"])
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run {engine}, is it installed?"))?;
+    if !created.status.success() {
+        bail!(
+            "Failed to create a container from {image}, {engine} exited with {}",
+            created.status
+        );
+    }
+    let container = String::from_utf8_lossy(&created.stdout).trim().to_string();
+
+    // Extracted aside, then moved in place, so an interrupted export isn't taken for a whole one
+    let partial = cache.join(format!("partial-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&partial);
+    let exported = extract_container(engine, &container, &partial);
+    if let Err(e) = output(engine, &["rm", "--", &container]) {
+        eprintln!("Failed to remove the container {container}: {e:#}");
+    }
+    if let Err(e) = exported {
+        let _ = std::fs::remove_dir_all(&partial);
+        return Err(e.context(format!("Failed to export the filesystem of {image}")));
+    }
+
+    let id =
+        image_id(engine, image).with_context(|| format!("Failed to find the ID of {image}"))?;
+    let dir = cache.join(id);
+    if let Err(e) = std::fs::rename(&partial, &dir) {
+        let _ = std::fs::remove_dir_all(&partial);
+        // Exported meanwhile by another run
+        if !dir.is_dir() {
+            return Err(e)
+                .with_context(|| format!("Failed to move the export to {}", dir.display()));
+        }
+    }
+    Ok(dir)
+}
+
+/// Extracts the filesystem of `container` into `dir`.
+fn extract_container(engine: &str, container: &str, dir: &Path) -> Result<()> {
+    let mut child = Command::new(engine)
+        .args(["export", "--", container])
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {engine}, is it installed?"))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let extracted = extract(BufReader::new(stdout), dir);
+    let status = child.wait()?;
+    extracted?;
+    if !status.success() {
+        bail!("{engine} exited with {status}");
+    }
+    Ok(())
+}
+
+/// Extracts the directories and regular files of the tar archive read from `reader` into `dir`,
+/// and the hard links between them. The symbolic links, which could point out of `dir`, and the special
+/// files are left out.
+fn extract(mut reader: impl Read, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Set by the GNU and PAX entries coming before the one they describe
+    let (mut long_name, mut long_link) = (None, None);
+    let mut header = [0u8; 512];
+    loop {
+        reader
+            .read_exact(&mut header)
+            .context("Truncated archive")?;
+        // The archive ends with empty blocks
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+        let size = header_number(&header[124..136]);
+        let kind = header[156];
+        let data = |reader: &mut dyn Read| -> Result<Vec<u8>> {
+            let mut data = Vec::new();
+            reader.take(size).read_to_end(&mut data)?;
+            Ok(data)
+        };
+        let mut written = 0;
+        match kind {
+            b'L' => long_name = Some(c_string(&data(&mut reader)?)),
+            b'K' => long_link = Some(c_string(&data(&mut reader)?)),
+            // Global PAX headers hold nothing worth extracting
+            b'g' => {}
+            b'x' => {
+                for (key, value) in pax_records(&data(&mut reader)?) {
+                    match key.as_str() {
+                        "path" => long_name = Some(value),
+                        "linkpath" => long_link = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {
+                let name = long_name.take().unwrap_or_else(|| ustar_name(&header));
+                let link = long_link
+                    .take()
+                    .unwrap_or_else(|| c_string(&header[157..257]));
+                let path = relative(&name).map(|path| dir.join(path));
+                match (kind, path) {
+                    (b'5', Some(path)) => std::fs::create_dir_all(&path)
+                        .with_context(|| format!("Failed to create {}", path.display()))?,
+                    (b'0' | b'\0' | b'7', Some(path)) => {
+                        let mut file = create_file(&path)?;
+                        written = std::io::copy(&mut (&mut reader).take(size), &mut file)
+                            .with_context(|| format!("Failed to write {}", path.display()))?;
+                    }
+                    (b'1', Some(path)) => {
+                        // The target comes first in the archive, unless left out
+                        let target = relative(&link).map(|target| dir.join(target));
+                        if let Some(target) = target.filter(|target| target.is_file()) {
+                            drop(create_file(&path)?);
+                            // Linked again when possible, so the bundle stubs the duplicate
+                            let _ = std::fs::remove_file(&path);
+                            if std::fs::hard_link(&target, &path).is_err() {
+                                std::fs::copy(&target, &path).with_context(|| {
+                                    format!(
+                                        "Failed to copy {} to {}",
+                                        target.display(),
+                                        path.display()
+                                    )
+                                })?;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // The rest of the data (all of it when not written) and the padding to the next block
+        let skipped = match kind {
+            b'L' | b'K' | b'x' => 0,
+            _ => size - written,
+        };
+        let padding = (512 - size % 512) % 512;
+        std::io::copy(
+            &mut (&mut reader).take(skipped + padding),
+            &mut std::io::sink(),
+        )?;
+    }
+}
+
+/// Creates the file at `path` and its directories, which may not have their own entries.
+fn create_file(path: &Path) -> Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))
+}
+
+/// `name` of an entry as a path relative to the extraction directory, `None` if it would escape
+/// it.
+fn relative(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path).filter(|path| !path.as_os_str().is_empty())
+}
+
+/// The name of the entry of `header`, joined to its ustar prefix.
+fn ustar_name(header: &[u8; 512]) -> String {
+    let name = c_string(&header[..100]);
+    match &header[257..262] == b"ustar" {
+        true => match c_string(&header[345..500]) {
+            prefix if prefix.is_empty() => name,
+            prefix => format!("{prefix}/{name}"),
+        },
+        false => name,
+    }
+}
+
+/// A number of a header: in octal, or in base 256 when its high bit is set.
+fn header_number(field: &[u8]) -> u64 {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |n, &byte| {
+                (n << 8) | u64::from(byte)
+            });
+    }
+    let digits = c_string(field);
+    u64::from_str_radix(digits.trim_matches([' ', '\0']), 8).unwrap_or_default()
+}
+
+/// The text of `bytes` up to the first NUL.
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// The `<length> <key>=<value>\n` records of a PAX extended header.
+fn pax_records(data: &[u8]) -> Vec<(String, String)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|&b| b == b' ') {
+        let Some(length) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|length| length.parse::<usize>().ok())
+            .filter(|&length| length > space && length <= rest.len())
+        else {
+            break;
+        };
+        let record = String::from_utf8_lossy(&rest[space + 1..length]);
+        if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+            records.push((key.to_string(), value.to_string()));
+        }
+        rest = &rest[length..];
+    }
+    records
+}
+
+/// The stdout of `engine` run with `args`, failing with its stderr.
+fn output(engine: &str, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new(engine)
+        .args(args)
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to run {engine}, is it installed?"))?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,303 @@
+//! The index of a project, opted into with `llmbundle index`: the size, modification time,
+//! BLAKE3 and counts of every file, kept in the `.llmbundle/state/index.json` of the root so
+//! `stats` and `--count-only` only read the files that changed since instead of all of them.
+//! They refresh the entries of the files they read.
+
+use crate::bundle::{self, Conflicts, Format, ProcessedFile, Sort};
+use crate::json::Value;
+use crate::{lfs, paths, state, tokens, BundleArgs};
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use llmbundle_core::{links, FileStats};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Args)]
+pub struct IndexArgs {
+    /// Root directory of the project
+    #[arg(long, default_value = ".", help = "Root directory for file search")]
+    root: String,
+
+    /// Delete the index, `stats` and `--count-only` reading every file again
+    #[arg(long, help = "Delete the index")]
+    delete: bool,
+}
+
+/// Algorithm of the hashes of the index
+const HASH: &str = "blake3";
+
+/// The size and modification time of a file, which change when it's written to
+type Stamp = (u64, SystemTime);
+
+/// What the index knows of a file, as last read
+struct Entry {
+    stamp: Stamp,
+    /// BLAKE3 of the content
+    hash: String,
+    /// Counts of the content, which the header of the file adds to in the bundle
+    chars: usize,
+    words: usize,
+    newlines: usize,
+}
+
+struct Index {
+    /// Canonical directory the paths are relative to
+    root: PathBuf,
+    files: HashMap<PathBuf, Entry>,
+}
+
+pub fn run(args: &IndexArgs) -> Result<()> {
+    if args.delete {
+        let path = index_path(Path::new(&args.root));
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => {
+                println!("Deleted the index.");
+                Ok(())
+            }
+        };
+    }
+
+    let bundle_args = BundleArgs {
+        patterns: Vec::new(),
+        max_depth: None,
+        root: args.root.clone(),
+        repo_root: false,
+        rev: None,
+        between: None,
+        diff: false,
+        commits: None,
+        max_tokens: None,
+        format: Format::Default,
+        checksums: false,
+        blame: None,
+        with_git_log: false,
+        conflicts: Conflicts::Flag,
+        lfs: lfs::Lfs::Note,
+        lfs_fetch: false,
+        strict: false,
+        sort: Sort::Path,
+        reverse: false,
+        group_by_dir: None,
+        // Only the changed files are read, and the command is run on purpose
+        max_files: 0,
+    };
+    let root = Path::new(&args.root);
+    let files = bundle::find_files(&bundle_args)?;
+    let mut index = load(root)?.unwrap_or_else(|| Index {
+        root: state::canonical(root),
+        files: HashMap::new(),
+    });
+    let read = index.refresh(&bundle_args, &files);
+    let present: HashSet<&PathBuf> = files.iter().collect();
+    index.files.retain(|path, _| present.contains(path));
+    index.save()?;
+    println!(
+        "{}",
+        format!("Indexed {} files, {read} read.", files.len()).green()
+    );
+    Ok(())
+}
+
+/// The counts of `files` as bundled with `args`, from the index of the project after reading the
+/// files that changed since it was refreshed, or `None` if `args.root` has no index.
+pub fn stats(args: &BundleArgs, files: &[PathBuf]) -> Result<Option<Vec<FileStats>>> {
+    // The index counts the files as read, not as the transforms change them, the ones of the
+    // working tree rather than of a revision, and without the last commits in their headers
+    if bundle::transforms(args) || bundle::from_git(args) || args.with_git_log {
+        return Ok(None);
+    }
+    let root = Path::new(&args.root);
+    let Some(mut index) = load(root)? else {
+        return Ok(None);
+    };
+    if index.refresh(args, files) > 0 {
+        if let Err(e) = index.save() {
+            log::warn!("Failed to save the index: {e:#}");
+        }
+    }
+    let originals = links::originals(root, files);
+    Ok(Some(
+        files
+            .iter()
+            .map(|path| index.file_stats(args, path, originals.get(path)))
+            .collect(),
+    ))
+}
+
+impl Index {
+    /// Reads the `files` that aren't in the index or changed since, returning their number.
+    fn refresh(&mut self, args: &BundleArgs, files: &[PathBuf]) -> usize {
+        let root = Path::new(&args.root);
+        let originals = links::originals(root, files);
+        // Stamped before reading, as the files of a `ReadCache` are
+        let stale: HashMap<&PathBuf, Stamp> = files
+            .iter()
+            .filter(|path| !originals.contains_key(*path))
+            .filter_map(|path| Some((path, stamp(&root.join(path))?)))
+            .filter(|(path, stamp)| self.files.get(*path).map(|entry| entry.stamp) != Some(*stamp))
+            .collect();
+        if stale.is_empty() {
+            return 0;
+        }
+        log::info!("Reading {} files changed since indexed", stale.len());
+
+        let paths: Vec<PathBuf> = stale.keys().map(|path| (*path).clone()).collect();
+        let bundle = bundle::bundler(args).max_tokens(None).bundle_files(&paths);
+        let unreadable: HashSet<&PathBuf> = bundle.unreadable.iter().collect();
+        for file in &bundle.files {
+            // Counted as empty until they can be read
+            if unreadable.contains(&file.path) {
+                self.files.remove(&file.path);
+                continue;
+            }
+            let counts = tokens::count(&file.content);
+            let entry = Entry {
+                stamp: stale[&file.path],
+                hash: file.hash.clone(),
+                chars: counts.chars,
+                words: counts.words,
+                newlines: file.content.matches('\n').count(),
+            };
+            self.files.insert(file.path.clone(), entry);
+        }
+        stale.len()
+    }
+
+    /// The counts of the file at `path` in the bundle, or of its stub if it's the same file as
+    /// the `original` one.
+    fn file_stats(&self, args: &BundleArgs, path: &Path, original: Option<&PathBuf>) -> FileStats {
+        let (header, entry) = match original {
+            Some(original) => (ProcessedFile::duplicate(path.to_owned(), original), None),
+            // The checksum has the same length whatever the content
+            None if args.checksums => (
+                ProcessedFile::with_checksum(path.to_owned(), String::new()),
+                self.files.get(path),
+            ),
+            None => (
+                ProcessedFile::new(path.to_owned(), String::new()),
+                self.files.get(path),
+            ),
+        };
+        let (chars, words, newlines) = entry.map_or((0, 0, 0), |entry| {
+            (entry.chars, entry.words, entry.newlines)
+        });
+        let char_count = header.char_count + chars;
+        FileStats {
+            path: path.to_owned(),
+            // Each newline of the content starts a line of the bundle
+            line_count: header.line_count + newlines,
+            word_count: header.word_count + words,
+            char_count,
+            token_count: tokens::estimate_chars(char_count),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        state::create_dir(&self.root)?;
+        let path = index_path(&self.root);
+        std::fs::write(&path, self.to_json().to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn to_json(&self) -> Value {
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        Value::object([
+            ("root", self.root.display().to_string().into()),
+            ("algorithm", HASH.into()),
+            (
+                "files",
+                Value::object(files.into_iter().map(|(path, entry)| {
+                    let mtime = entry.stamp.1.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    (
+                        paths::display(path),
+                        Value::object([
+                            ("size", entry.stamp.0.into()),
+                            // Nanoseconds since the epoch don't fit in a JSON number exactly
+                            ("mtime", mtime.as_secs().into()),
+                            ("mtime_nanos", u64::from(mtime.subsec_nanos()).into()),
+                            ("hash", entry.hash.as_str().into()),
+                            ("tokens", tokens::estimate_chars(entry.chars).into()),
+                            ("characters", entry.chars.into()),
+                            ("words", entry.words.into()),
+                            ("newlines", entry.newlines.into()),
+                        ]),
+                    )
+                })),
+            ),
+        ])
+    }
+
+    fn from_json(json: &Value) -> Option<Index> {
+        let Value::Object(files) = json.get("files")? else {
+            return None;
+        };
+        let root = PathBuf::from(json.get("root")?.as_str()?);
+        // Indexed with SHA-256 by older versions, the files are read again
+        if json.get("algorithm").and_then(Value::as_str) != Some(HASH) {
+            log::debug!("The index has the hashes of an older version, reading the files again");
+            return Some(Index {
+                root,
+                files: HashMap::new(),
+            });
+        }
+        Some(Index {
+            root,
+            files: files
+                .iter()
+                .map(|(path, entry)| {
+                    let count = |key| Some(entry.get(key)?.as_u64()? as usize);
+                    let mtime = Duration::new(
+                        entry.get("mtime")?.as_u64()?,
+                        u32::try_from(entry.get("mtime_nanos")?.as_u64()?).ok()?,
+                    );
+                    let entry = Entry {
+                        stamp: (entry.get("size")?.as_u64()?, UNIX_EPOCH + mtime),
+                        hash: entry.get("hash")?.as_str()?.to_string(),
+                        chars: count("characters")?,
+                        words: count("words")?,
+                        newlines: count("newlines")?,
+                    };
+                    Some((PathBuf::from(path), entry))
+                })
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+/// The index of the files under `root`, if the project has one.
+fn load(root: &Path) -> Result<Option<Index>> {
+    let path = index_path(root);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let index = Value::parse(&contents)
+        .ok()
+        .as_ref()
+        .and_then(Index::from_json)
+        .with_context(|| format!("Invalid index in {}", path.display()))?;
+    if index.root != state::canonical(root) {
+        log::debug!(root:% = index.root.display(); "The index is of another root");
+        return Ok(None);
+    }
+    Ok(Some(index))
+}
+
+/// The stamp of the file at `path`, `None` if it can't be read or is older than the epoch.
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?;
+    mtime.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), mtime))
+}
+
+fn index_path(root: &Path) -> PathBuf {
+    state::dir(root).join("index.json")
+}
@@ -0,0 +1,182 @@
+//! Writes a starter `.llmbundle.toml` with the usual sources of the ecosystems detected in the
+//! project.
+
+use crate::config;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Args)]
+pub struct InitArgs {
+    /// Directory of the project
+    #[arg(long, default_value = ".", help = "Root directory of the project")]
+    root: PathBuf,
+
+    /// Replace an existing configuration
+    #[arg(long, help = "Overwrite an existing .llmbundle.toml")]
+    force: bool,
+}
+
+struct Ecosystem {
+    name: &'static str,
+    /// Files whose presence in the root identifies the ecosystem
+    markers: &'static [&'static str],
+    patterns: &'static [&'static str],
+    exclude: &'static [&'static str],
+}
+
+const ECOSYSTEMS: &[Ecosystem] = &[
+    Ecosystem {
+        name: "Rust",
+        markers: &["Cargo.toml"],
+        patterns: &["**/*.rs", "**/Cargo.toml"],
+        exclude: &["target/**"],
+    },
+    Ecosystem {
+        name: "JavaScript/TypeScript",
+        markers: &["package.json"],
+        patterns: &[
+            "src/**/*.js",
+            "src/**/*.jsx",
+            "src/**/*.ts",
+            "src/**/*.tsx",
+            "package.json",
+            "tsconfig.json",
+        ],
+        exclude: &["node_modules/**", "dist/**", "build/**", "**/*.min.js"],
+    },
+    Ecosystem {
+        name: "Python",
+        markers: &["pyproject.toml", "setup.py", "requirements.txt"],
+        patterns: &["**/*.py", "pyproject.toml", "setup.py", "requirements.txt"],
+        exclude: &[".venv/**", "venv/**", "**/__pycache__/**", "build/**"],
+    },
+    Ecosystem {
+        name: "Go",
+        markers: &["go.mod"],
+        patterns: &["**/*.go", "go.mod"],
+        exclude: &["vendor/**"],
+    },
+    Ecosystem {
+        name: "Java/Kotlin",
+        markers: &["pom.xml", "build.gradle", "build.gradle.kts"],
+        patterns: &[
+            "src/main/**/*.java",
+            "src/main/**/*.kt",
+            "pom.xml",
+            "build.gradle",
+            "build.gradle.kts",
+        ],
+        exclude: &["target/**", "build/**"],
+    },
+    Ecosystem {
+        name: "Ruby",
+        markers: &["Gemfile"],
+        patterns: &["**/*.rb", "Gemfile"],
+        exclude: &["vendor/**"],
+    },
+];
+
+/// Lock files and other generated files that waste tokens whatever the ecosystem
+const ALWAYS_EXCLUDED: &[&str] = &["*.lock", "package-lock.json", "pnpm-lock.yaml", "go.sum"];
+
+/// Ecosystems found in a project, with the patterns and excludes they call for
+pub struct Detected {
+    pub names: Vec<&'static str>,
+    pub patterns: Vec<&'static str>,
+    pub exclude: Vec<&'static str>,
+}
+
+pub fn run(args: &InitArgs) -> Result<()> {
+    let path = args.root.join(config::FILE_NAME);
+    if path.exists() && !args.force {
+        bail!(
+            "{} already exists, pass --force to replace it",
+            path.display()
+        );
+    }
+
+    let Detected {
+        names,
+        patterns,
+        exclude,
+    } = detect(&args.root);
+
+    let mut text = String::from(
+        "# llmbundle configuration, see https://github.com/chicoferreira/llmbundle#configuration\n",
+    );
+    if names.is_empty() {
+        text.push_str(
+            "# No known project type detected, everything not ignored by git is bundled\n",
+        );
+    } else {
+        text.push_str(&format!("# Detected: {}\n", names.join(", ")));
+    }
+
+    text.push('\n');
+    if !patterns.is_empty() {
+        text.push_str(&array("patterns", &patterns));
+    }
+    text.push_str(&array("exclude", &exclude));
+    text.push_str("\n# format = \"patchable\"\n# max_tokens = 100000\n");
+
+    std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+    let path = path.display().to_string().bold();
+    match names.is_empty() {
+        true => println!("Wrote {path}."),
+        false => println!("Wrote {path} for a {} project.", names.join(" and ")),
+    }
+    Ok(())
+}
+
+/// Detects the ecosystems of the project in `root` by their marker files.
+pub fn detect(root: &Path) -> Detected {
+    let detected: Vec<&Ecosystem> = ECOSYSTEMS
+        .iter()
+        .filter(|ecosystem| {
+            ecosystem
+                .markers
+                .iter()
+                .any(|marker| root.join(marker).is_file())
+        })
+        .collect();
+
+    let mut patterns = Vec::new();
+    let mut exclude = Vec::new();
+    for ecosystem in &detected {
+        add(&mut patterns, ecosystem.patterns);
+        add(&mut exclude, ecosystem.exclude);
+    }
+    add(&mut exclude, ALWAYS_EXCLUDED);
+    Detected {
+        names: detected.iter().map(|ecosystem| ecosystem.name).collect(),
+        patterns,
+        exclude,
+    }
+}
+
+/// Adds the `values` missing from `list`, as ecosystems can share patterns (like `target/**` for
+/// Rust and Java).
+fn add(list: &mut Vec<&'static str>, values: &[&'static str]) {
+    for value in values {
+        if !list.contains(value) {
+            list.push(value);
+        }
+    }
+}
+
+/// A TOML array of strings, one per line.
+pub fn array(key: &str, values: &[&str]) -> String {
+    let mut text = format!("{key} = [\n");
+    for value in values {
+        text.push_str(&format!("    {},\n", string(value)));
+    }
+    text.push_str("]\n");
+    text
+}
+
+/// A TOML basic string.
+pub fn string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,52 @@
+//! Ctrl-C handling for the steps worth finishing cleanly: while a [`Guard`] is held, Ctrl-C only
+//! sets a flag the step checks, so it can stop early, clean up after itself (remove its temp
+//! file, clear the progress line) and decide what to do with what it has done so far. A second
+//! Ctrl-C exits right away.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of a process interrupted by Ctrl-C, as set by shells (128 + SIGINT)
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Restores the default Ctrl-C behavior, ending the process, when dropped.
+pub struct Guard(());
+
+/// Catches Ctrl-C until the returned guard is dropped.
+pub fn catch() -> Guard {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    set_handler(true);
+    Guard(())
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        set_handler(false);
+    }
+}
+
+/// Whether Ctrl-C was pressed since the last [`catch`].
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+fn set_handler(catch: bool) {
+    extern "C" fn handle(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: `_exit` is async-signal-safe
+            unsafe { libc::_exit(EXIT_CODE) };
+        }
+    }
+
+    let handler = match catch {
+        true => handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        false => libc::SIG_DFL,
+    };
+    // SAFETY: the handler only touches an atomic and calls `_exit`, both async-signal-safe
+    unsafe { libc::signal(libc::SIGINT, handler) };
+}
+
+#[cfg(not(unix))]
+fn set_handler(_catch: bool) {}
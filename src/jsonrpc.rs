@@ -0,0 +1,102 @@
+//! JSON-RPC 2.0 over newline-delimited stdin/stdout, shared by the long-running server modes.
+
+use crate::json::Value;
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Error::new(METHOD_NOT_FOUND, format!("Method not found: {method}"))
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Error::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Error::new(INTERNAL_ERROR, format!("{e:#}"))
+    }
+}
+
+/// Reads requests from stdin until it is closed, answering each one with the result of `handle`
+/// (called with the method name and its params). Notifications get no response.
+pub fn serve_stdio(mut handle: impl FnMut(&str, &Value) -> Result<Value, Error>) -> Result<()> {
+    let stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+
+    for line in stdin.lines() {
+        let line = line.context("Failed to read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match Value::parse(&line) {
+            Err(e) => Some(error_response(
+                Value::Null,
+                Error::new(PARSE_ERROR, format!("{e:#}")),
+            )),
+            Ok(request) => {
+                let id = request.get("id").cloned();
+                match request.get("method").and_then(Value::as_str) {
+                    None => Some(error_response(
+                        id.unwrap_or(Value::Null),
+                        Error::new(INVALID_REQUEST, "Missing method"),
+                    )),
+                    Some(method) => {
+                        let params = request.get("params").cloned().unwrap_or(Value::Null);
+                        let result = handle(method, &params);
+                        id.map(|id| match result {
+                            Ok(result) => Value::object([
+                                ("jsonrpc", "2.0".into()),
+                                ("id", id),
+                                ("result", result),
+                            ]),
+                            Err(e) => error_response(id, e),
+                        })
+                    }
+                }
+            }
+        };
+
+        if let Some(response) = response {
+            writeln!(stdout, "{response}").context("Failed to write to stdout")?;
+            stdout.flush().context("Failed to write to stdout")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn error_response(id: Value, error: Error) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Value::object([
+                ("code", Value::Number(error.code as f64)),
+                ("message", error.message.into()),
+            ]),
+        ),
+    ])
+}
@@ -0,0 +1,133 @@
+//! Git LFS pointers: the few lines git keeps in place of a file stored with LFS until it's
+//! fetched, which bundled as they are pass for the asset itself. They're noted in their header,
+//! left out with `--lfs skip`, or replaced with the file with `--lfs-fetch`.
+
+use crate::{git, progress, remote};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// A pointer is a small text file, and no other starts like one
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// The first line of a pointer, the second one naming the spec before it was called LFS
+const VERSIONS: [&str; 2] = [
+    "version https://git-lfs.github.com/spec/v1\n",
+    "version https://hawser.github.com/spec/v1\n",
+];
+
+/// Starts the line noting a pointer in the header of the file
+const HEADER: &str = "[file LFS object]: ";
+
+/// What becomes of the LFS pointers that aren't fetched
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Lfs {
+    /// Bundled with a `[file LFS object]: 1.5 MB, not fetched` line in their header
+    #[default]
+    Note,
+    /// Left out of the bundle
+    Skip,
+}
+
+/// Size in bytes of the file `content` points to, if it's an LFS pointer.
+fn pointed_size(content: &str) -> Option<u64> {
+    if content.len() as u64 > MAX_POINTER_SIZE {
+        return None;
+    }
+    let rest = VERSIONS
+        .iter()
+        .find_map(|version| content.strip_prefix(version))?;
+    // `<key> <value>` lines sorted by key, of which `oid` and `size` are required
+    let (mut oid, mut size) = (false, None);
+    for line in rest.lines() {
+        let (key, value) = line.split_once(' ')?;
+        match key {
+            "oid" => oid = value.starts_with("sha256:"),
+            "size" => size = Some(value.parse().ok()?),
+            _ => {}
+        }
+    }
+    size.filter(|_| oid)
+}
+
+/// The line noting `content` is an LFS pointer in the header of its file, if it is one.
+pub fn note(content: &str) -> Option<String> {
+    let size = pointed_size(content)?;
+    Some(format!(
+        "{HEADER}{}, not fetched",
+        progress::size(size as f64)
+    ))
+}
+
+/// Whether `content` is an LFS pointer.
+pub fn is_pointer(content: &str) -> bool {
+    pointed_size(content).is_some()
+}
+
+/// `files` (relative to `root`) without the LFS pointers, told by their first bytes.
+pub fn without_pointers(root: &Path, files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let skipped_before = files.len();
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|path| !is_pointer_file(&root.join(path)))
+        .collect();
+    let skipped = skipped_before - files.len();
+    if skipped > 0 {
+        log::info!("Left out {skipped} LFS pointers");
+    }
+    files
+}
+
+fn is_pointer_file(path: &Path) -> bool {
+    let small = std::fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.len() <= MAX_POINTER_SIZE);
+    if !small {
+        return false;
+    }
+    let mut content = String::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(MAX_POINTER_SIZE).read_to_string(&mut content))
+        .is_ok_and(|_| is_pointer(&content))
+}
+
+/// The file `content` points to if it's an LFS pointer, fetched through `git lfs smudge` run in
+/// `root` like the other [downloads](remote::fetch), or `content` as it is when it isn't a
+/// pointer or can't be fetched.
+pub fn fetch(root: &Path, path: &Path, content: String, strict: bool) -> String {
+    if !is_pointer(&content) {
+        return content;
+    }
+    let fetched = remote::fetch(|| smudge(root, path, &content))
+        .and_then(|bytes| llmbundle_core::decode_file(bytes, strict).map_err(anyhow::Error::msg));
+    match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            log::warn!(path:% = path.display(); "Failed to fetch the LFS object, bundling the pointer: {e:#}");
+            content
+        }
+    }
+}
+
+/// The object the `pointer` of the file at `path` points to, downloaded unless it's in the
+/// local LFS storage already.
+fn smudge(root: &Path, path: &Path, pointer: &str) -> Result<Vec<u8>> {
+    let mut child = git::git(root)
+        .args(["lfs", "smudge", "--"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git, is it installed?")?;
+    // A pointer is small enough for the pipe, so git isn't blocked on its stdout meanwhile
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(pointer.as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
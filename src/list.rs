@@ -0,0 +1,94 @@
+//! Prints the files a bundle would contain, for scripts wanting to reuse the selection.
+
+use crate::bundle::{self, Conflicts, Format, Sort};
+use crate::json::Value;
+use crate::{lfs, paths, BundleArgs};
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use std::io::Write;
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Glob patterns to match files (supports wildcards and tilde expansion)
+    patterns: Vec<String>,
+
+    /// Maximum directory depth to traverse (optional)
+    #[arg(long, help = "Set the maximum depth for directory traversal")]
+    max_depth: Option<usize>,
+
+    /// Root directory to start the search from
+    #[arg(long, default_value = ".", help = "Root directory for file search")]
+    root: String,
+
+    /// How the paths are printed (`list_format` in the configuration, as `format` is the format of
+    /// the bundle)
+    #[arg(
+        id = "list_format",
+        long = "format",
+        value_enum,
+        default_value_t = ListFormat::Text,
+        help = "Choose the output format"
+    )]
+    format: ListFormat,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum ListFormat {
+    /// One path per line
+    Text,
+    /// A JSON array of paths
+    Json,
+    /// Paths terminated by NUL characters, for `xargs -0`
+    Null,
+}
+
+impl ListArgs {
+    fn bundle_args(&self) -> BundleArgs {
+        BundleArgs {
+            patterns: self.patterns.clone(),
+            max_depth: self.max_depth,
+            root: self.root.clone(),
+            repo_root: false,
+            rev: None,
+            between: None,
+            diff: false,
+            commits: None,
+            max_tokens: None,
+            format: Format::Default,
+            checksums: false,
+            blame: None,
+            with_git_log: false,
+            conflicts: Conflicts::Flag,
+            lfs: lfs::Lfs::Note,
+            lfs_fetch: false,
+            strict: false,
+            sort: Sort::Path,
+            reverse: false,
+            group_by_dir: None,
+            // Listing reads no file
+            max_files: 0,
+        }
+    }
+}
+
+pub fn run(args: &ListArgs) -> Result<()> {
+    let paths: Vec<String> = bundle::find_files(&args.bundle_args())?
+        .iter()
+        .map(|path| paths::display(path))
+        .collect();
+    let mut stdout = std::io::stdout().lock();
+    match args.format {
+        ListFormat::Text => {
+            for path in paths {
+                writeln!(stdout, "{path}")?;
+            }
+        }
+        ListFormat::Json => writeln!(stdout, "{}", Value::from(paths))?,
+        ListFormat::Null => {
+            for path in paths {
+                write!(stdout, "{path}\0")?;
+            }
+        }
+    }
+    Ok(())
+}
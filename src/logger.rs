@@ -0,0 +1,83 @@
+//! Leveled logging to stderr behind the `log` macros, with the level chosen by repeating `-v` or
+//! with `--log-level`. Key-value pairs of the records (like the `path` of per-file events) are
+//! printed after the message.
+
+use clap::ValueEnum;
+use colored::Colorize;
+use log::kv::{self, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fmt::Write;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // The records of the dependencies (like the directory walker) only at the trace level
+        metadata.level() <= log::max_level()
+            && (metadata.target().starts_with(env!("CARGO_PKG_NAME"))
+                || log::max_level() == LevelFilter::Trace)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = match record.level() {
+            Level::Error => "error".red(),
+            Level::Warn => "warn".yellow(),
+            Level::Info => "info".blue(),
+            Level::Debug => "debug".cyan(),
+            Level::Trace => "trace".black(),
+        };
+        let mut line = format!("{level} {}", record.args());
+        let _ = record.key_values().visit(&mut Fields(&mut line));
+        eprintln!("{line}");
+    }
+
+    fn flush(&self) {}
+}
+
+/// Appends the key-value pairs of a record as ` key=value`.
+struct Fields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let _ = write!(self.0, " {}", format!("{key}={value}").black());
+        Ok(())
+    }
+}
+
+/// Installs the logger, at `level` if given and otherwise at warnings plus one level per `-v`.
+pub fn init(level: Option<LogLevel>, verbosity: u8) {
+    let filter = match (level, verbosity) {
+        (Some(level), _) => level.filter(),
+        (None, 0) => LevelFilter::Warn,
+        (None, 1) => LevelFilter::Info,
+        (None, 2) => LevelFilter::Debug,
+        (None, _) => LevelFilter::Trace,
+    };
+    if log::set_logger(&Logger).is_ok() {
+        log::set_max_level(filter);
+    }
+}
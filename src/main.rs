@@ -1,220 +1,864 @@
+mod apply;
+mod archive;
+mod ask;
+mod blame;
+mod bundle;
+mod bundle_diff;
+mod clipboard;
+mod commits;
+mod completions;
+mod compress;
+mod config;
+mod count;
+mod diff;
+mod editor;
+mod errors;
+mod explain;
+mod failure;
+mod git;
+mod git_log;
+mod history;
+mod html;
+mod http;
+mod http_server;
+mod image;
+mod index;
+mod init;
+mod interrupt;
+mod json;
+mod jsonrpc;
+mod lfs;
+mod list;
+mod logger;
+mod man;
+mod manifest;
+mod mcp;
+mod merge;
+mod output;
+mod parse;
+mod patch;
+mod pipe;
+mod plugin;
+mod preview;
+mod progress;
+mod remote;
+mod rpc;
+mod saved;
+mod self_update;
+mod serve;
+mod skipped;
+mod ssh;
+mod state;
+mod stats;
+mod stream;
+mod temp;
+mod timings;
+mod toml;
+mod unbundle;
+mod upload;
+mod watch;
+mod watch_apply;
+mod wizard;
+
 use anyhow::{Context, Result};
-use arboard::Clipboard;
-use clap::{Parser, ValueEnum};
-use colored::Colorize;
-use globset::{Glob, GlobSet, GlobSetBuilder};
-use ignore::WalkBuilder;
-use rayon::prelude::*;
+use bundle::Bundle;
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clipboard::ClipboardSelection;
+use config::Config;
+use failure::Failure;
+use llmbundle_core::{blake3, paths, sha256, tokens};
+use output::{Output, SummaryFormat};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
 
 #[derive(Debug, Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Glob patterns to match files (supports wildcards and tilde expansion)
-    patterns: Vec<String>,
-
-    /// Maximum directory depth to traverse (optional)
-    #[arg(long, help = "Set the maximum depth for directory traversal")]
-    max_depth: Option<usize>,
-
-    /// Root directory to start the search from
-    #[arg(long, default_value = ".", help = "Root directory for file search")]
-    root: String,
-
-    /// Output destination: stdout or clipboard
-    #[arg(long, value_enum, default_value_t = Output::Clipboard, help = "Choose the output destination"
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Arguments of the default command, also given to `bundle`
+    #[command(flatten)]
+    run: RunArgs,
+
+    /// Use the patterns and flags of the `[profile.<NAME>]` table of the configuration
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Select a profile of the configuration"
+    )]
+    profile: Option<String>,
+
+    /// Ignore the `.llmbundle.toml` of the project and the user's configuration
+    #[arg(long, global = true, help = "Don't read the configuration files")]
+    no_config: bool,
+
+    /// When to color the output; `auto` colors it when stdout is a terminal and `NO_COLOR` isn't
+    /// set
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = ColorChoice::Auto,
+        help = "Choose when to color the output"
+    )]
+    color: ColorChoice,
+
+    /// Log what is being done to stderr, more with each repetition: `-v` for the steps, `-vv` for
+    /// every file and `-vvv` for the internals of the dependencies
+    #[arg(short, long, global = true, action = ArgAction::Count, help = "Enable verbose output")]
+    verbose: u8,
+
+    /// Level of the messages logged to stderr, instead of counting `-v`
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Choose the level of the logged messages"
+    )]
+    log_level: Option<logger::LogLevel>,
+
+    /// Threads walking the directories and reading the files, by default one per core (up to 12
+    /// for the walk); fewer to go easy on shared machines and network filesystems
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "N",
+        help = "Number of threads reading the files"
     )]
-    output: Output,
+    jobs: Option<NonZeroUsize>,
+
+    /// Longest a file or directory may take to be looked up or read, like `10s` or `500ms`: the
+    /// ones of a hung network mount or FUSE filesystem are skipped and reported instead of
+    /// freezing the run
+    #[arg(
+        long,
+        global = true,
+        value_name = "DURATION",
+        value_parser = bundle::parse_timeout,
+        help = "Skip the paths taking longer to look up or read"
+    )]
+    timeout: Option<Duration>,
+
+    /// Commands contributing file filters, content transforms or an output format, speaking the
+    /// JSON protocol of the `plugin` module on their stdin and stdout. Repeat the flag for several
+    #[arg(
+        long,
+        global = true,
+        value_name = "COMMAND",
+        help = "Run a plugin filtering, transforming or formatting the files"
+    )]
+    plugin: Vec<String>,
 
-    /// Enable verbose logging for debugging purposes
-    #[arg(short, long, help = "Enable verbose output")]
-    verbose: bool,
+    /// Command line the arguments were parsed from, without the program name, for the history
+    #[arg(skip)]
+    argv: Vec<String>,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
-enum Output {
-    Stdout,
-    Clipboard,
+// Arguments of the default command, bundling the files and sending the bundle to the outputs
+#[derive(Debug, Args)]
+struct RunArgs {
+    #[command(flatten)]
+    bundle: BundleArgs,
+
+    /// Output destinations: stdout, clipboard, gist, paste, editor, tempfile, file:<path>,
+    /// archive:<path> or ssh:[user@]host:<path>.
+    /// Repeat the flag or separate with commas to send the bundle to several destinations.
+    /// Defaults to clipboard when run interactively and to stdout when stdout is piped
+    #[arg(long, value_delimiter = ',', help = "Choose the output destinations")]
+    output: Vec<Output>,
+
+    /// Clipboard selection to copy the output to (primary is only available on Linux)
+    #[arg(long, value_enum, default_value_t = ClipboardSelection::Clipboard, help = "Choose the clipboard selection to copy to"
+    )]
+    clipboard_selection: ClipboardSelection,
+
+    /// Shell command to pipe the output to instead of using the system clipboard (e.g. `wl-copy`
+    /// or `xclip -selection clipboard`), run through `sh -c` (`cmd /C` on Windows)
+    #[arg(long, help = "Use a custom command to copy to the clipboard")]
+    clipboard_cmd: Option<String>,
+
+    /// Paste service used by `--output paste`: 0x0, paste.rs or the URL of a custom endpoint that
+    /// accepts the bundle as the POST body and answers with the paste URL
+    #[arg(long, default_value = "0x0", help = "Paste service to upload to")]
+    paste_service: upload::PasteService,
+
+    /// How long the paste should be kept, e.g. `12h` or `1d` (only supported by 0x0)
+    #[arg(long, value_parser = upload::parse_duration, help = "Expiration of the uploaded paste")]
+    expire: Option<std::time::Duration>,
+
+    /// Also place a syntax-highlighted HTML flavor on the clipboard, so pasting into rich-text
+    /// editors (Google Docs, Slack, Notion...) keeps the code formatting
+    #[arg(
+        long,
+        help = "Copy a syntax-highlighted HTML version along with the text"
+    )]
+    clipboard_html: bool,
+
+    /// Append the bundle to the text already on the clipboard instead of replacing it, to
+    /// accumulate the results of several runs before pasting
+    #[arg(long, help = "Append to the current clipboard contents")]
+    append: bool,
+
+    /// Copy to the clipboard even if the bundle is identical to the last one copied from this
+    /// project (which is skipped by default)
+    #[arg(long, help = "Copy even if the bundle is unchanged")]
+    force: bool,
+
+    /// Compress the bundle and encode it as text, with a header explaining how to decode it, for
+    /// chat messages or issue comments with tight size limits
+    #[arg(long, value_enum, help = "Compress the output")]
+    compress: Option<compress::Compression>,
+
+    /// Shell command to stream the output to, relaying its stdout (e.g. `llm -s "Review this"`)
+    #[arg(long, help = "Pipe the output into a command instead")]
+    pipe: Option<String>,
+
+    /// Print the summary (files with their size, totals and files left out) as JSON on stderr, for
+    /// wrapper scripts and editor plugins
+    #[arg(long, value_enum, default_value_t, help = "Choose the summary format")]
+    summary_format: SummaryFormat,
+
+    /// File descriptor to write the JSON summary to instead of stderr, e.g. `3` with `3>summary.json`
+    #[arg(
+        long,
+        value_name = "FD",
+        requires = "summary_format",
+        help = "Write the JSON summary to a file descriptor"
+    )]
+    summary_fd: Option<u32>,
+
+    /// Don't print the list of files and the totals, only warnings and errors
+    #[arg(short, long, help = "Suppress the summary")]
+    quiet: bool,
+
+    /// Template of the totals line of the summary, with the placeholders `{action}`,
+    /// `{destination}`, `{files}`, `{lines}`, `{words}`, `{characters}` and `{tokens}`; empty to
+    /// leave the totals out
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = output::parse_totals_template,
+        allow_hyphen_values = true,
+        help = "Template of the summary totals"
+    )]
+    summary_template: Option<String>,
+
+    /// Template of the line listing each file in the summary, with the placeholders `{path}`,
+    /// `{lines}`, `{words}`, `{characters}` and `{tokens}`; empty to leave the list out
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = output::parse_file_template,
+        allow_hyphen_values = true,
+        help = "Template of the summary line of each file"
+    )]
+    summary_file_template: Option<String>,
+
+    /// Fail with exit code 6 instead of leaving out the files that don't fit within
+    /// `--max-tokens`
+    #[arg(
+        long,
+        requires = "max_tokens",
+        help = "Fail when files don't fit in the token budget"
+    )]
+    strict_budget: bool,
+
+    /// After bundling, list the files that were left out with the reason: ignored, excluded by
+    /// a negative pattern, unreadable or over the token budget
+    #[arg(long, help = "Report the files left out and why")]
+    report_skipped: bool,
+
+    /// Print how many files each pattern matches and stop, without reading the files
+    #[arg(long, help = "Count the files matched by each pattern")]
+    preview: bool,
+
+    /// Print only the number of matched files and their estimated tokens and stop, streaming the
+    /// files instead of building the bundle
+    #[arg(
+        long,
+        conflicts_with = "preview",
+        help = "Print the number of files and tokens"
+    )]
+    count_only: bool,
+
+    /// Write a JSON record of the run to a file: the patterns as matched, the files with their
+    /// hash, size and tokens, the options transforming the bundle and the totals
+    #[arg(long, value_name = "PATH", help = "Write a JSON manifest of the run")]
+    manifest: Option<PathBuf>,
+
+    /// Keep running and re-generate the bundle whenever the matched files change, sending it to
+    /// the outputs again each time. Changes are polled for every 500 ms, each poll walking the
+    /// root and reading the metadata of the matched files, less often on trees taking longer
+    #[arg(
+        long,
+        conflicts_with_all = ["rev", "between", "commits"],
+        help = "Watch the matched files and re-bundle on changes"
+    )]
+    watch: bool,
+
+    /// Replace the files that didn't change since the last bundle of this project with a one-line
+    /// `[unchanged since last bundle]` stub, to save tokens in long conversations
+    #[arg(long, help = "Stub out files unchanged since the last bundle")]
+    incremental: bool,
+
+    /// Keep a copy of the bundled files in the project state, so `apply` can three-way merge the
+    /// response with the edits made to them meanwhile instead of overwriting them
+    #[arg(
+        long,
+        help = "Keep the bundled files to merge responses with local edits"
+    )]
+    merge_base: bool,
+
+    /// Write the bundle to stdout or the `file:` outputs as the files are read instead of
+    /// building it in memory first, for huge selections. The bundle isn't recorded in the history
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "compress",
+            "pipe",
+            "incremental",
+            "merge_base",
+            "manifest",
+            "watch",
+            "report_skipped",
+            "strict_budget",
+            "strict",
+            "summary_format",
+            "rev",
+            "between",
+            "commits",
+        ],
+        help = "Stream the bundle to its outputs"
+    )]
+    stream: bool,
+
+    /// Memory the streamed files are held in at most, like `512MB`: fewer files are read at once,
+    /// and with `--sort tokens` the files waiting to be written are spilled to a temp file
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = stream::parse_size,
+        requires = "stream",
+        help = "Bound the memory used by --stream"
+    )]
+    memory_limit: Option<u64>,
+
+    /// Print the time taken by each phase (walk, filter, read, transform, tokenize, format and
+    /// output) and by the 10 slowest files to stderr, to spot regressions and pathological files
+    #[arg(
+        long,
+        conflicts_with_all = ["preview", "count_only", "watch"],
+        help = "Report the time taken by each phase"
+    )]
+    timings: bool,
 }
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
-
-    let (pos_globs, neg_globs) = build_glob_sets(&args.patterns)?;
-
-    if args.verbose {
-        eprintln!("Searching in root: {}", args.root);
-    }
-
-    let walker = WalkBuilder::new(&args.root)
-        .max_depth(args.max_depth)
-        .build();
+impl Cli {
+    /// Parses `args` (without the program name) as a command line, after expanding an alias and
+    /// adding the defaults of the environment and the configuration files, exiting with clap's
+    /// error message if they are invalid.
+    fn parse_args(args: Vec<String>) -> Result<Cli> {
+        let program = || std::iter::once("llmbundle".to_string());
+        let command = Cli::command();
+
+        let mut expanded = args.clone();
+        let alias = args
+            .first()
+            .filter(|name| command.find_subcommand(name).is_none());
+        if let Some(name) = alias.filter(|_| !args.iter().any(|arg| arg == "--no-config")) {
+            if let Some(alias) = Config::find(Path::new(&default_root()))?.alias(name)? {
+                expanded.splice(..1, alias);
+            }
+        }
 
-    let root = Path::new(&args.root);
+        let matches = command
+            .clone()
+            .try_get_matches_from(program().chain(expanded.iter().cloned()))
+            .unwrap_or_else(|e| e.exit());
 
-    let mut matching_files = Vec::new();
-    for entry in walker {
-        let entry = entry?;
-        let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
-        if !path.is_file() {
-            continue;
+        let command_matches = matches.subcommand().map_or(&matches, |(_, m)| m);
+        let remote = match command_matches.try_get_one::<String>("root") {
+            Ok(Some(root)) => remote::local_copy(root)?.map(|copy| (root.clone(), copy)),
+            _ => None,
+        };
+        // A repository or an image is bundled from its local copy, given as the root instead
+        if let Some((url, copy)) = &remote {
+            replace_root(&mut expanded, url, &copy.display().to_string());
         }
-        if !pos_globs.is_match(path) || neg_globs.is_match(path) {
-            continue;
+        // So is the repository of the working directory, from its root
+        let repo_root = matches!(
+            command_matches.try_get_one::<bool>("repo_root"),
+            Ok(Some(true))
+        );
+        let named = matches!(
+            command_matches.try_get_one::<String>("root"),
+            Ok(Some(root)) if root == git::REPO_ROOT
+        );
+        if repo_root || named {
+            let cwd = std::env::current_dir().context("Failed to find the working directory")?;
+            let root = git::repo_root(&cwd)
+                .with_context(|| format!("{} isn't in a git repository", cwd.display()))?
+                .display()
+                .to_string();
+            match repo_root {
+                true => {
+                    for arg in expanded.iter_mut().filter(|arg| *arg == "--repo-root") {
+                        *arg = format!("--root={root}");
+                    }
+                }
+                false => replace_root(&mut expanded, git::REPO_ROOT, &root),
+            }
         }
-        if args.verbose {
-            eprintln!("Matched file: {}", path.display());
+        let config = if matches.get_flag("no_config") {
+            Config::default()
+        } else {
+            let root = match command_matches.try_get_one::<String>("root") {
+                Ok(Some(root)) => root.clone(),
+                _ => default_root(),
+            };
+            // The configuration of a cloned repository or an image could run its plugins
+            let config = match remote {
+                Some(_) => Config::user()?,
+                None => Config::find(Path::new(&root))?,
+            };
+            config.validate(&command)?;
+            config
+        };
+        let mut argv = expanded;
+        // Subcommands come first, their arguments after them
+        let at = usize::from(matches.subcommand().is_some());
+        argv.splice(at..at, config.args(&command, &matches)?);
+
+        let mut cli = Cli::try_parse_from(program().chain(argv)).unwrap_or_else(|e| e.exit());
+        // `bundle` is the explicit name of the default command
+        if let Some(Command::Bundle(run)) = cli.command.take_if(|c| matches!(c, Command::Bundle(_)))
+        {
+            cli.run = run;
         }
-        matching_files.push(path.to_owned());
-    }
-
-    if args.verbose {
-        eprintln!("Total matching files: {}", matching_files.len());
+        cli.argv = args;
+        Ok(cli)
     }
+}
 
-    let file_outputs: (Vec<String>, Vec<ProcessedFile>) = matching_files
-        .par_iter()
-        .map(|path| process_file(path, args.verbose))
-        .collect();
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
 
-    let output_buffer = file_outputs.0.join("\n");
-
-    match args.output {
-        Output::Stdout => println!("{}", output_buffer),
-        Output::Clipboard => {
-            print_summary(file_outputs.1, &output_buffer);
-            Clipboard::new()
-                .context("Failed to initialize clipboard")?
-                .set_text(output_buffer)
-                .context("Failed to set clipboard text")?;
-            if args.verbose {
-                eprintln!("Output copied to clipboard.");
-            }
+impl ColorChoice {
+    fn apply(self) {
+        match self {
+            // Left to `colored`, which follows NO_COLOR and CLICOLOR
+            ColorChoice::Auto => {}
+            ColorChoice::Always => colored::control::set_override(true),
+            ColorChoice::Never => colored::control::set_override(false),
         }
     }
-
-    Ok(())
 }
 
-const FORMAT: &str = r#"[file name]: {file_name}
-[file content begin]
-{file_content}
-[file content end]
-"#;
-
-struct ProcessedFile {
-    path: PathBuf,
-    line_count: usize,
-    word_count: usize,
-    char_count: usize,
+/// Replaces the `--root` given as `from` in `args` with `to`.
+fn replace_root(args: &mut [String], from: &str, to: &str) {
+    let root_arg = format!("--root={from}");
+    for i in 0..args.len() {
+        if args[i] == root_arg {
+            args[i] = format!("--root={to}");
+        } else if args[i] == from && i > 0 && args[i - 1] == "--root" {
+            args[i] = to.to_string();
+        }
+    }
 }
 
-fn process_file(path: &Path, verbose: bool) -> (String, ProcessedFile) {
-    if verbose {
-        eprintln!("Reading file: {}", path.display());
-    }
+/// Root the configuration is searched from when the command line doesn't set one.
+fn default_root() -> String {
+    std::env::var(format!("{}ROOT", config::ENV_PREFIX)).unwrap_or_else(|_| ".".to_string())
+}
 
-    let content = std::fs::read(path)
-        .map(|b| String::from_utf8_lossy(&b).into_owned())
-        .unwrap_or_else(|e| {
-            eprintln!("Error reading {}: {}", path.display(), e);
-            String::new()
-        });
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Bundle the files and send them to the outputs, the default command
+    Bundle(RunArgs),
+    /// List the files that would be bundled
+    List(list::ListArgs),
+    /// Show the size of the files that would be bundled, largest first
+    Stats(stats::StatsArgs),
+    /// Index the size and counts of the files, so `stats` and `--count-only` only read the
+    /// changed ones
+    Index(index::IndexArgs),
+    /// Explain why a file is or isn't in the bundle
+    Explain(explain::ExplainArgs),
+    /// Send the bundle along with a prompt to an LLM API and stream the response
+    Ask(ask::AskArgs),
+    /// Run a long-lived server that bundles files on request
+    Serve(serve::ServeArgs),
+    /// Save the patterns and flags of a bundle under a name
+    Save(saved::SaveArgs),
+    /// Bundle again from a definition saved with `save`
+    Load(saved::LoadArgs),
+    /// List the previous bundles
+    History,
+    /// Send a previous bundle again, byte for byte
+    Recopy(history::RecopyArgs),
+    /// Bundle again with the arguments of the last run
+    Again(history::AgainArgs),
+    /// Write the files of a bundle back to disk
+    Unbundle(unbundle::UnbundleArgs),
+    /// Apply the files of an LLM response to the working tree, showing a diff of each first
+    Apply(apply::ApplyArgs),
+    /// List the files added, removed and changed between two bundles
+    Diff(bundle_diff::DiffArgs),
+    /// Watch the clipboard for LLM responses and offer to apply each one
+    WatchApply(watch_apply::WatchApplyArgs),
+    /// Write a starter .llmbundle.toml for the type of the project
+    Init(init::InitArgs),
+    /// Show the configuration files and the arguments they add
+    Config(config::ConfigArgs),
+    /// Print the completion script of a shell
+    Completions(completions::CompletionsArgs),
+    /// Print the man page
+    Man,
+    /// Update llmbundle to the latest GitHub release
+    ///
+    /// The download is checked against the SHA256SUMS file of the release only, with no
+    /// signature: that detects a corrupted download, not a tampered release.
+    SelfUpdate(self_update::SelfUpdateArgs),
+    /// List names for the completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete(completions::CompleteArgs),
+}
 
-    let content = FORMAT
-        .replace("{file_name}", path.display().to_string().as_str())
-        .replace("{file_content}", &content);
+// Arguments that select and read the files making up a bundle, shared with subcommands
+#[derive(Debug, Args)]
+struct BundleArgs {
+    /// Glob patterns to match files (supports wildcards and tilde expansion)
+    patterns: Vec<String>,
 
-    let line_count = content.lines().count();
-    let word_count = content.split_whitespace().count();
-    let char_count = content.chars().count();
+    /// Maximum directory depth to traverse (optional)
+    #[arg(long, help = "Set the maximum depth for directory traversal")]
+    max_depth: Option<usize>,
 
-    let processed_file = ProcessedFile {
-        path: path.to_path_buf(),
-        line_count,
-        word_count,
-        char_count,
-    };
+    /// Root directory to start the search from; `git` is the root of the git repository of the
+    /// working directory
+    #[arg(long, default_value = ".", help = "Root directory for file search")]
+    root: String,
 
-    (content, processed_file)
+    /// Search from the root of the git repository of the working directory, like `--root git`,
+    /// whichever of its subdirectories the command is run from
+    #[arg(
+        long,
+        conflicts_with = "root",
+        help = "Search from the root of the git repository"
+    )]
+    repo_root: bool,
+
+    /// Bundle the files as they are at a git revision (a commit, branch or tag), read from the
+    /// repository of the root without checking it out
+    #[arg(long, value_name = "REV", help = "Bundle the files of a git revision")]
+    rev: Option<String>,
+
+    /// Bundle the files differing between two git revisions, as they are in the second one:
+    /// `main..feature/x` compares both ends, `main...feature/x` the branch with where it forked
+    /// from `main`, either end defaulting to `HEAD`. The files deleted are left out
+    #[arg(
+        long,
+        value_name = "A..B",
+        value_parser = git::Range::parse,
+        conflicts_with = "rev",
+        help = "Bundle the files differing between two git revisions"
+    )]
+    between: Option<git::Range>,
+
+    /// With `--between`, bundle the diff of each file between the revisions instead of its
+    /// content, the deleted files included
+    #[arg(long, requires = "between", help = "Bundle the diffs of --between")]
+    diff: bool,
+
+    /// Bundle the commits of a git range instead of the files, oldest first: the message and the
+    /// diff of each one, limited to the files under the root matching the patterns, named like
+    /// the patches of `git format-patch`. Merges are left out
+    #[arg(
+        long,
+        value_name = "A..B",
+        value_parser = git::Range::parse,
+        conflicts_with_all = ["rev", "between"],
+        help = "Bundle the messages and diffs of a range of commits"
+    )]
+    commits: Option<git::Range>,
+
+    /// Token budget for the bundle; files that would exceed it are left out (estimated at four
+    /// characters per token)
+    #[arg(long, help = "Maximum number of tokens in the bundle")]
+    max_tokens: Option<usize>,
+
+    /// How the files are laid out in the bundle
+    #[arg(long, value_enum, default_value_t, help = "Choose the bundle format")]
+    format: bundle::Format,
+
+    /// Add the SHA-256 of each file to its header, so `unbundle` and `apply` can detect pastes
+    /// truncated or mangled on the way
+    #[arg(long, help = "Add per-file checksums")]
+    checksums: bool,
+
+    /// Annotate the lines of each file with the short SHA and the author of the commit that last
+    /// changed them, from `git blame`: a line before each run of lines from the same commit
+    /// (`hunks`, the default) or a prefix on every line (`lines`)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "hunks",
+        help = "Annotate the lines with git blame"
+    )]
+    blame: Option<blame::Blame>,
+
+    /// Add the short SHA, author, date and subject of the last commit of each file to its
+    /// header, from `git log`, telling the files changed recently from the ones untouched for
+    /// long
+    #[arg(long, help = "Add the last commit of each file to its header")]
+    with_git_log: bool,
+
+    /// What becomes of the files with unresolved merge conflicts (`<<<<<<<` markers): flagged in
+    /// their header, left out, or bundled as they are. A warning is shown unless ignored
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        help = "Flag or exclude the files with merge conflicts"
+    )]
+    conflicts: bundle::Conflicts,
+
+    /// What becomes of the Git LFS pointers, the few lines standing for the files stored with
+    /// LFS until they're fetched: bundled with a note of the size of the file in their header,
+    /// or left out
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        help = "Note or skip the Git LFS pointers"
+    )]
+    lfs: lfs::Lfs,
+
+    /// Bundle the files the Git LFS pointers stand for, fetched through `git lfs`, instead of the
+    /// pointers; the ones that can't be fetched are noted like without it
+    #[arg(
+        long,
+        conflicts_with = "lfs",
+        help = "Fetch the files of the Git LFS pointers"
+    )]
+    lfs_fetch: bool,
+
+    /// Fail when a file can't be read or isn't valid UTF-8, instead of bundling it empty or with
+    /// the invalid bytes replaced
+    #[arg(long, help = "Fail on files that can't be read")]
+    strict: bool,
+
+    /// Order of the files in the bundle; with `--max-tokens`, the files are kept in this order
+    /// while they fit
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Choose the order of the files"
+    )]
+    sort: bundle::Sort,
+
+    /// Reverse the order given by `--sort`
+    #[arg(long, help = "Reverse the order of the files")]
+    reverse: bool,
+
+    /// Group the files by their first directories (one level, or the given number), with a
+    /// `== src ==` header before each group
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        num_args = 0..=1,
+        default_missing_value = "1",
+        help = "Group the files by directory under headers"
+    )]
+    group_by_dir: Option<usize>,
+
+    /// Number of matched files past which the bundle asks whether to go on before reading them,
+    /// or warns when not run interactively, so a stray `**` doesn't read a whole home directory;
+    /// 0 for no limit
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = bundle::MAX_FILES,
+        help = "Ask before bundling more files than this"
+    )]
+    max_files: usize,
 }
 
-fn normalize_pattern(pattern: &str) -> String {
-    if pattern.contains(std::path::MAIN_SEPARATOR) {
-        pattern.to_string()
-    } else {
-        format!("**/{}", pattern)
+fn main() -> ExitCode {
+    let args = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let result = Cli::parse_args(args).and_then(|cli| {
+        cli.color.apply();
+        logger::init(cli.log_level, cli.verbose);
+        if let Some(jobs) = cli.jobs {
+            bundle::set_jobs(jobs.get())?;
+        }
+        if let Some(timeout) = cli.timeout {
+            bundle::set_timeout(timeout);
+        }
+        plugin::start(&cli.plugin)?;
+        let result = run(cli);
+        errors::report();
+        timings::report();
+        result
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            failure::exit_code(&e)
+        }
     }
 }
 
-fn build_glob_sets(patterns: &[String]) -> Result<(GlobSet, GlobSet)> {
-    let (mut pos, mut neg) = (GlobSetBuilder::new(), GlobSetBuilder::new());
-
-    let mut pos_builder_is_empty = true;
-
-    for pattern in patterns {
-        let pattern = pattern.trim();
-
-        let (pattern, builder) = {
-            if pattern.starts_with('!') {
-                let pattern = pattern.trim_start_matches('!');
-                if pattern.is_empty() {
-                    continue;
-                }
-                // If the pattern starts with a '!', add to negative patterns
-                (pattern, &mut neg)
-            } else {
-                // Otherwise add to positive patterns
-                pos_builder_is_empty = false;
-                (pattern, &mut pos)
-            }
-        };
-
-        let pattern = normalize_pattern(pattern);
-
-        let expanded = shellexpand::full(&pattern)
-            .with_context(|| format!("Failed to expand pattern: {}", pattern))?
-            .into_owned();
-        let glob = Glob::new(&expanded)
-            .with_context(|| format!("Invalid glob pattern after expansion: {}", expanded))?;
-
-        builder.add(glob);
+fn run(args: Cli) -> Result<()> {
+    match &args.command {
+        Some(Command::Bundle(_)) => unreachable!("parse_args makes bundle the default command"),
+        Some(Command::List(list_args)) => return list::run(list_args),
+        Some(Command::Stats(stats_args)) => return stats::run(stats_args),
+        Some(Command::Index(index_args)) => return index::run(index_args),
+        Some(Command::Explain(explain_args)) => return explain::run(explain_args),
+        Some(Command::Ask(ask_args)) => {
+            let bundle = bundle::bundle(&ask_args.bundle)?;
+            bundle::warn_dropped(&bundle);
+            return ask::run(ask_args, &bundle.text);
+        }
+        Some(Command::Serve(serve_args)) => return serve::run(serve_args),
+        Some(Command::Save(save_args)) => return saved::save(save_args),
+        Some(Command::Load(load_args)) => return saved::load(load_args),
+        Some(Command::History) => return history::list(),
+        Some(Command::Recopy(recopy_args)) => return history::recopy(recopy_args),
+        Some(Command::Again(again_args)) => return history::again(again_args),
+        Some(Command::Unbundle(unbundle_args)) => return unbundle::run(unbundle_args),
+        Some(Command::Apply(apply_args)) => return apply::run(apply_args),
+        Some(Command::Diff(diff_args)) => return bundle_diff::run(diff_args),
+        Some(Command::WatchApply(watch_apply_args)) => return watch_apply::run(watch_apply_args),
+        Some(Command::Init(init_args)) => return init::run(init_args),
+        Some(Command::Config(config_args)) => {
+            return config::run(config_args, &Cli::command(), args.profile.as_deref())
+        }
+        Some(Command::Completions(completions_args)) => return completions::run(completions_args),
+        Some(Command::Man) => return man::run(),
+        Some(Command::SelfUpdate(self_update_args)) => return self_update::run(self_update_args),
+        Some(Command::Complete(complete_args)) => return completions::complete(complete_args),
+        None => {}
     }
 
-    if pos_builder_is_empty {
-        pos.add(Glob::new("**").expect("** is valid pattern"));
+    if args.run.timings {
+        timings::enable();
+    }
+    if args.run.preview {
+        return preview::run(&args.run.bundle);
+    }
+    if args.run.count_only {
+        return count::run(&args.run.bundle);
+    }
+    if args.run.watch {
+        return watch::run(&args.run);
+    }
+    if args.run.stream {
+        return stream::run(&args.run);
     }
 
-    let pos_set = pos.build().context("Failed to build positive glob set")?;
-    let neg_set = neg.build().context("Failed to build negative glob set")?;
-
-    Ok((pos_set, neg_set))
+    let guard = interrupt::catch();
+    let mut bundle = bundle::bundle(&args.run.bundle)?;
+    drop(guard);
+    if interrupt::interrupted() && !send_partial(&bundle) {
+        std::process::exit(interrupt::EXIT_CODE);
+    }
+    if args.run.report_skipped {
+        skipped::report(&args.run.bundle, &bundle)?;
+    }
+    if bundle.files.is_empty() && bundle.dropped.is_empty() {
+        return Err(Failure::NoMatches.into());
+    }
+    if args.run.strict_budget && !bundle.dropped.is_empty() {
+        if !args.run.report_skipped {
+            bundle::warn_dropped(&bundle);
+        }
+        return Err(Failure::Budget.into());
+    }
+    let root = Path::new(&args.run.bundle.root);
+    // A manifest saved while bundling another directory says nothing about these files
+    let previous = match args.run.incremental {
+        true => state::manifest(root)?.filter(|manifest| manifest.is_of(root)),
+        false => None,
+    };
+    // The manifest describes the files themselves, not the stubs sent in their place
+    if let Err(e) = state::save_manifest(root, &bundle, args.run.merge_base) {
+        eprintln!("Failed to save the bundle manifest: {e:#}");
+    }
+    let stubbed = previous.map(|previous| {
+        bundle.stub_unchanged(|file| previous.hash(&file.path) == Some(file.hash.as_str()))
+    });
+    if let Some(stubbed) = stubbed {
+        log::info!("Replaced {stubbed} unchanged files with stubs.");
+    }
+    if let Err(e) = history::record(&args, &bundle) {
+        eprintln!("Failed to record the bundle in the history: {e:#}");
+    }
+    if let Some(path) = &args.run.manifest {
+        manifest::write(path, &args.run, &bundle, stubbed)?;
+    }
+    let unreadable = std::mem::take(&mut bundle.unreadable);
+    timings::time(timings::Phase::Output, || emit(&args.run, bundle))?;
+    if !unreadable.is_empty() {
+        return Err(bundle::unreadable_error(&unreadable));
+    }
+    Ok(())
 }
 
-fn print_summary(mut matching_files: Vec<ProcessedFile>, buffer: &str) {
-    println!("{}", "Files matched".blue().bold());
-    if matching_files.is_empty() {
-        println!("{}", "No files matched.".red());
-        return;
+/// Asks whether to send the files read before Ctrl-C was pressed, when there is someone to ask.
+fn send_partial(bundle: &Bundle) -> bool {
+    use std::io::IsTerminal;
+    eprintln!("Interrupted after reading {} files.", bundle.files.len());
+    if bundle.files.is_empty()
+        || !std::io::stdin().is_terminal()
+        || !std::io::stderr().is_terminal()
+    {
+        return false;
     }
+    eprint!("Send the partial bundle? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
 
-    matching_files.sort_by_key(|v| v.line_count);
-
-    for file in &matching_files {
-        let file_info = format!(
-            "({} lines, {} words, {} characters)",
-            file.line_count, file.word_count, file.char_count
-        )
-        .black();
-
-        println!("{} {} {file_info}", "+".red(), file.path.display());
+/// Sends the bundle to the command given with `--pipe`, or to the selected outputs.
+fn emit(args: &RunArgs, mut bundle: Bundle) -> Result<()> {
+    if let Some(text) = plugin::format(&bundle)? {
+        bundle.text = text;
+    }
+    if let Some(compression) = args.compress {
+        bundle.text = compress::compress(&bundle.text, compression)?;
     }
 
-    println!(
-        "\nCopied {} to clipboard totalling {}, {} and {}.",
-        format!("{} files", matching_files.len()).bold(),
-        format!("{} lines", buffer.lines().count()).bold(),
-        format!("{} words", buffer.split_whitespace().count()).bold(),
-        format!("{} characters", buffer.chars().count()).bold()
-    );
+    let summary = (args.summary_format == SummaryFormat::Json).then(|| bundle::summary(&bundle));
+    if let Some(command) = &args.pipe {
+        if summary.is_none() && !args.report_skipped {
+            bundle::warn_dropped(&bundle);
+        }
+        pipe::pipe_to(command, &bundle.text)?;
+    } else {
+        output::write_all(args, bundle)?;
+    }
+    match summary {
+        Some(summary) => output::write_summary(args, &summary),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,185 @@
+//! Man page generated from the definition of the command line, for packagers:
+//! `llmbundle man > llmbundle.1`.
+
+use crate::{config, Cli};
+use anyhow::Result;
+use clap::{Arg, CommandFactory};
+use std::fmt::Write;
+
+pub fn run() -> Result<()> {
+    print!("{}", page());
+    Ok(())
+}
+
+fn page() -> String {
+    let mut cli = Cli::command();
+    cli.build();
+    let version = cli.get_version().unwrap_or_default().to_string();
+
+    let mut page = String::new();
+    let _ = writeln!(
+        page,
+        ".TH LLMBUNDLE 1 \"\" \"llmbundle {version}\" \"User Commands\""
+    );
+    page.push_str(".SH NAME\nllmbundle \\- bundle project files into a single text for LLMs\n");
+
+    page.push_str(".SH SYNOPSIS\n");
+    page.push_str(".B llmbundle\n[\\fIOPTIONS\\fR] [\\fIPATTERNS\\fR]...\n.br\n");
+    page.push_str(".B llmbundle\n\\fICOMMAND\\fR [\\fIARGS\\fR]...\n");
+
+    page.push_str(".SH DESCRIPTION\n");
+    page.push_str(
+        "Matches the files under a root directory with glob patterns, skipping the ones ignored by \
+         git, and concatenates them with headers giving their paths, to paste a project into a \
+         conversation with a language model. The bundle is copied to the clipboard when run \
+         interactively and written to stdout otherwise.\n",
+    );
+
+    page.push_str(".SH OPTIONS\n");
+    arguments(&mut page, &cli);
+
+    page.push_str(".SH COMMANDS\n");
+    for command in cli.get_subcommands() {
+        if command.is_hide_set() || command.get_name() == "help" {
+            continue;
+        }
+        let _ = writeln!(page, ".SS {}", escape(&usage(command)));
+        if let Some(about) = command.get_long_about().or(command.get_about()) {
+            let _ = writeln!(page, "{}", escape(&about.to_string()));
+        }
+        arguments(&mut page, command);
+    }
+
+    page.push_str(".SH ENVIRONMENT\n");
+    let _ = writeln!(
+        page,
+        ".TP\n.B {}*\nSet the flag of the same name in upper case, e.g. \\fB{0}MAX_TOKENS=50000\\fR \
+         for \\fB\\-\\-max\\-tokens 50000\\fR. Switches take \\fB1\\fR or \\fB0\\fR. They take \
+         precedence over the configuration files.",
+        config::ENV_PREFIX
+    );
+    page.push_str(
+        ".TP\n.B EDITOR\nEditor used by \\fB\\-\\-output editor\\fR and to edit hunks when \
+         applying interactively.\n",
+    );
+
+    page.push_str(".SH FILES\n");
+    let _ = writeln!(
+        page,
+        ".TP\n.I {}\nProject configuration, found by walking up from the root. Each key is named \
+         after a flag.",
+        escape(config::FILE_NAME)
+    );
+    page.push_str(
+        ".TP\n.I ~/.config/llmbundle/config.toml\nUser configuration, under the project \
+         configuration.\n",
+    );
+    page.push_str(".TP\n.I .llmbundle/bundles\nBundle definitions stored with \\fBsave\\fR.\n");
+    page.push_str(
+        ".TP\n.I .llmbundle/state\nManifest of the last bundle of the root, or history of the current directory.\n",
+    );
+    page
+}
+
+/// Usage line of a subcommand, like `llmbundle load [OPTIONS] <NAME> [ARGS]...`.
+fn usage(command: &clap::Command) -> String {
+    let mut usage = format!("llmbundle {}", command.get_name());
+    if command.get_arguments().any(|arg| !arg.is_positional()) {
+        usage.push_str(" [OPTIONS]");
+    }
+    for arg in command.get_arguments().filter(|arg| arg.is_positional()) {
+        let name = value_name(arg);
+        match (
+            arg.is_required_set(),
+            arg.get_action().takes_values() && is_multiple(arg),
+        ) {
+            (true, false) => usage.push_str(&format!(" <{name}>")),
+            (true, true) => usage.push_str(&format!(" <{name}>...")),
+            (false, false) => usage.push_str(&format!(" [{name}]")),
+            (false, true) => usage.push_str(&format!(" [{name}]...")),
+        }
+    }
+    usage
+}
+
+fn is_multiple(arg: &Arg) -> bool {
+    arg.get_num_args()
+        .is_some_and(|range| range.max_values() > 1)
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| arg.get_id().to_string().to_uppercase())
+}
+
+/// Adds a paragraph per argument of `command`, positional ones first.
+fn arguments(page: &mut String, command: &clap::Command) {
+    let (positional, options): (Vec<&Arg>, Vec<&Arg>) = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .partition(|arg| arg.is_positional());
+    for arg in positional.into_iter().chain(options) {
+        let mut flags: Vec<String> = Vec::new();
+        if let Some(short) = arg.get_short() {
+            flags.push(format!("\\fB\\-{short}\\fR"));
+        }
+        if let Some(long) = arg.get_long() {
+            flags.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+        }
+        let mut term = flags.join(", ");
+        if arg.is_positional() {
+            term = format!("\\fI{}\\fR", escape(&value_name(arg)));
+        } else if arg.get_action().takes_values() {
+            let _ = write!(term, " \\fI{}\\fR", escape(&value_name(arg)));
+        }
+        let _ = writeln!(page, ".TP\n{term}");
+
+        let help = arg.get_long_help().or(arg.get_help());
+        if let Some(help) = help {
+            let _ = writeln!(page, "{}", escape(&help.to_string()));
+        }
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+        if !defaults.is_empty() && arg.get_action().takes_values() {
+            let _ = writeln!(
+                page,
+                ".br\nDefaults to \\fB{}\\fR.",
+                escape(&defaults.join(","))
+            );
+        }
+        let values: Vec<_> = arg
+            .get_possible_values()
+            .into_iter()
+            .filter(|value| !value.is_hide_set())
+            .collect();
+        if arg.get_action().takes_values() && !values.is_empty() {
+            page.push_str(".RS\n");
+            for value in values {
+                let _ = writeln!(page, ".TP\n.B {}", escape(value.get_name()));
+                if let Some(help) = value.get_help() {
+                    let _ = writeln!(page, "{}", escape(&help.to_string()));
+                }
+            }
+            page.push_str(".RE\n");
+        }
+    }
+}
+
+/// Escapes `text` so roff prints it as is.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    // Lines starting with a dot or a quote would be taken as requests
+    escaped
+        .lines()
+        .map(|line| match line.starts_with(['.', '\'']) {
+            true => format!("\\&{line}"),
+            false => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
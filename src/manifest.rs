@@ -0,0 +1,97 @@
+//! Machine-readable record of a run written with `--manifest`: the patterns as matched, the files
+//! with their hash, size and tokens, the options transforming the bundle and the totals. Unlike
+//! the manifest kept in the [state](crate::state), it's written where asked and describes this
+//! run only, for audits and scripts.
+
+use crate::bundle::Bundle;
+use crate::json::Value;
+use crate::{blake3, bundle, paths, state, RunArgs};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+/// Writes the manifest of `bundle` to `path`. `stubbed` is the number of files replaced by a stub
+/// with `--incremental`.
+pub fn write(path: &Path, args: &RunArgs, bundle: &Bundle, stubbed: Option<usize>) -> Result<()> {
+    let patterns = args
+        .bundle
+        .patterns
+        .iter()
+        .map(|pattern| {
+            let pattern = pattern.trim();
+            Ok(match pattern.strip_prefix('!') {
+                Some(negative) => format!("!{}", bundle::resolve_pattern(negative)?),
+                None => bundle::resolve_pattern(pattern)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let files = bundle.files.iter().map(|file| {
+        Value::object([
+            ("path", paths::display(&file.path).into()),
+            ("blake3", file.hash.as_str().into()),
+            ("bytes", file.content.len().into()),
+            ("tokens", file.token_count.into()),
+        ])
+    });
+    let paths = |paths: &[PathBuf]| -> Value {
+        let paths: Vec<String> = paths.iter().map(|path| paths::display(path)).collect();
+        paths.into()
+    };
+
+    let manifest = Value::object([
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        ("time", state::now().into()),
+        (
+            "root",
+            state::canonical(Path::new(&args.bundle.root))
+                .display()
+                .to_string()
+                .into(),
+        ),
+        ("patterns", patterns.into()),
+        ("files", Value::Array(files.collect())),
+        ("dropped", paths(&bundle.dropped)),
+        ("unreadable", paths(&bundle.unreadable)),
+        (
+            "transforms",
+            Value::object([
+                ("format", name(args.bundle.format)),
+                ("checksums", args.bundle.checksums.into()),
+                ("sort", name(args.bundle.sort)),
+                ("reverse", args.bundle.reverse.into()),
+                ("group_by_dir", args.bundle.group_by_dir.into()),
+                ("max_tokens", args.bundle.max_tokens.into()),
+                ("incremental_stubs", stubbed.into()),
+                ("compress", args.compress.map(name).into()),
+            ]),
+        ),
+        (
+            "totals",
+            Value::object([
+                ("files", bundle.files.len().into()),
+                ("bytes", bundle.text.len().into()),
+                (
+                    "tokens",
+                    bundle
+                        .files
+                        .iter()
+                        .map(|file| file.token_count)
+                        .sum::<usize>()
+                        .into(),
+                ),
+                ("blake3", blake3::hex(bundle.text.as_bytes()).into()),
+            ]),
+        ),
+    ]);
+    std::fs::write(path, format!("{manifest}\n"))
+        .with_context(|| format!("Failed to write the manifest to {}", path.display()))
+}
+
+/// Name of an option value as given on the command line.
+fn name(value: impl ValueEnum) -> Value {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .into()
+}
@@ -0,0 +1,184 @@
+//! Model Context Protocol server exposing the bundler as tools, so agents can pull project
+//! context on demand.
+
+use crate::bundle;
+use crate::json::Value;
+use crate::jsonrpc::{self, Error};
+use crate::serve::{self, ServeArgs};
+use anyhow::Result;
+use llmbundle_core::paths;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+pub fn run(args: &ServeArgs) -> Result<()> {
+    log::info!("Serving MCP on stdin/stdout for root: {}", args.root);
+
+    jsonrpc::serve_stdio(|method, params| {
+        log::info!("MCP request: {method}");
+        match method {
+            "initialize" => Ok(Value::object([
+                ("protocolVersion", PROTOCOL_VERSION.into()),
+                (
+                    "capabilities",
+                    Value::object([("tools", Value::object::<&str>([]))]),
+                ),
+                (
+                    "serverInfo",
+                    Value::object([
+                        ("name", env!("CARGO_PKG_NAME").into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "ping" => Ok(Value::object::<&str>([])),
+            "tools/list" => Ok(Value::object([("tools", tools())])),
+            "tools/call" => call_tool(args, params),
+            method if method.starts_with("notifications/") => Ok(Value::Null),
+            method => Err(Error::method_not_found(method)),
+        }
+    })
+}
+
+fn tools() -> Value {
+    let patterns_schema = Value::object([
+        ("type", "array".into()),
+        ("items", Value::object([("type", "string".into())])),
+        (
+            "description",
+            "Glob patterns to match files, relative to the project root. Patterns without a \
+             '/' match files with that name anywhere, patterns starting with '!' exclude files. \
+             Leave empty to match every file not ignored by .gitignore."
+                .into(),
+        ),
+    ]);
+
+    Value::Array(vec![
+        Value::object([
+            ("name", "bundle".into()),
+            (
+                "description",
+                "Bundle the contents of the project files matching the patterns into a single \
+                 text, each file prefixed with its path."
+                    .into(),
+            ),
+            (
+                "inputSchema",
+                Value::object([
+                    ("type", "object".into()),
+                    (
+                        "properties",
+                        Value::object([
+                            ("patterns", patterns_schema.clone()),
+                            (
+                                "budget",
+                                Value::object([
+                                    ("type", "integer".into()),
+                                    (
+                                        "description",
+                                        "Maximum number of tokens of the bundle, files that \
+                                         don't fit are left out."
+                                            .into(),
+                                    ),
+                                ]),
+                            ),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]),
+        Value::object([
+            ("name", "list_files".into()),
+            (
+                "description",
+                "List the paths of the project files matching the patterns.".into(),
+            ),
+            (
+                "inputSchema",
+                Value::object([
+                    ("type", "object".into()),
+                    ("properties", Value::object([("patterns", patterns_schema)])),
+                ]),
+            ),
+        ]),
+    ])
+}
+
+fn call_tool(args: &ServeArgs, params: &Value) -> Result<Value, Error> {
+    let name = params
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::invalid_params("Missing tool name"))?;
+    let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+
+    let patterns = match arguments.get("patterns") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(patterns)) => patterns
+            .iter()
+            .map(|p| p.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::invalid_params("patterns must be an array of strings"))?,
+        Some(_) => {
+            return Err(Error::invalid_params(
+                "patterns must be an array of strings",
+            ))
+        }
+    };
+    let budget = match arguments.get("budget") {
+        None | Some(Value::Null) => None,
+        Some(budget) => Some(
+            budget
+                .as_u64()
+                .ok_or_else(|| Error::invalid_params("budget must be a positive integer"))?
+                as usize,
+        ),
+    };
+
+    let bundle_args = args.bundle_args(patterns, budget);
+
+    // Tool failures are reported in the result so the model can see and react to them
+    let text = match name {
+        "bundle" => serve::bundle(&bundle_args, None).map(|bundle| {
+            let mut text = bundle.text;
+            if !bundle.dropped.is_empty() {
+                text.push_str(&format!(
+                    "\n[{} files left out to stay within the budget: {}]\n",
+                    bundle.dropped.len(),
+                    bundle
+                        .dropped
+                        .iter()
+                        .map(|p| paths::display(p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            text
+        }),
+        "list_files" => bundle::find_files(&bundle_args).map(|files| {
+            if files.is_empty() {
+                "No files matched.".to_string()
+            } else {
+                files
+                    .iter()
+                    .map(|p| paths::display(p))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        }),
+        name => return Err(Error::invalid_params(format!("Unknown tool: {name}"))),
+    };
+
+    let (text, is_error) = match text {
+        Ok(text) => (text, false),
+        Err(e) => (format!("Error: {e:#}"), true),
+    };
+    Ok(Value::object([
+        (
+            "content",
+            Value::Array(vec![Value::object([
+                ("type", "text".into()),
+                ("text", text.into()),
+            ])]),
+        ),
+        ("isError", is_error.into()),
+    ]))
+}
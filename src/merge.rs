@@ -0,0 +1,151 @@
+//! Line-based three-way merge, for changes proposed against a version of a file that was edited
+//! locally since.
+
+use crate::diff::{self, Op};
+
+pub struct Merge {
+    pub text: String,
+    /// Number of regions changed on both sides, marked with conflict markers in the text
+    pub conflicts: usize,
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`, like `diff3 -m`.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Merge {
+    let (base, ours, theirs) = (diff::lines(base), diff::lines(ours), diff::lines(theirs));
+    let in_ours = matches(&base, &ours);
+    let in_theirs = matches(&base, &theirs);
+
+    let mut merge = Merge {
+        text: String::new(),
+        conflicts: 0,
+    };
+    let (mut o, mut a, mut b) = (0, 0, 0);
+    loop {
+        // Next base line left untouched by both sides
+        let stable = (o..base.len()).find(|&i| in_ours[i].is_some() && in_theirs[i].is_some());
+        let (end, a_end, b_end) = match stable {
+            Some(i) if i == o && in_ours[i] == Some(a) && in_theirs[i] == Some(b) => {
+                merge.text.push_str(base[o]);
+                (o, a, b) = (o + 1, a + 1, b + 1);
+                continue;
+            }
+            Some(i) => (i, in_ours[i].unwrap(), in_theirs[i].unwrap()),
+            None => (base.len(), ours.len(), theirs.len()),
+        };
+        merge.chunk(&base[o..end], &ours[a..a_end], &theirs[b..b_end]);
+        if stable.is_none() {
+            return merge;
+        }
+        (o, a, b) = (end, a_end, b_end);
+    }
+}
+
+impl Merge {
+    fn chunk(&mut self, base: &[&str], ours: &[&str], theirs: &[&str]) {
+        let resolved = if ours == theirs || theirs == base {
+            ours
+        } else if ours == base {
+            theirs
+        } else {
+            self.conflicts += 1;
+            self.text.push_str("<<<<<<< local\n");
+            self.side(ours);
+            self.text.push_str("=======\n");
+            self.side(theirs);
+            self.text.push_str(">>>>>>> response\n");
+            return;
+        };
+        for line in resolved {
+            self.text.push_str(line);
+        }
+    }
+
+    /// Pushes one side of a conflict, making sure the marker after it starts on its own line.
+    fn side(&mut self, lines: &[&str]) {
+        for line in lines {
+            self.text.push_str(line);
+        }
+        if !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+/// For each line of `base`, its index in `other` if the diff between them keeps it.
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    let (mut i, mut j) = (0, 0);
+    for (op, _) in diff::diff(base, other) {
+        match op {
+            Op::Equal => {
+                matches[i] = Some(j);
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    #[test]
+    fn merges_changes_to_different_lines() {
+        let ours = "ONE\ntwo\nthree\nfour\nfive\n";
+        let theirs = "one\ntwo\nthree\nfour\nFIVE\n";
+        let merged = merge(BASE, ours, theirs);
+        assert_eq!(merged.text, "ONE\ntwo\nthree\nfour\nFIVE\n");
+        assert_eq!(merged.conflicts, 0);
+    }
+
+    #[test]
+    fn takes_the_same_change_once() {
+        let both = "one\n2\nthree\nfour\nfive\n";
+        let merged = merge(BASE, both, both);
+        assert_eq!(merged.text, both);
+        assert_eq!(merged.conflicts, 0);
+    }
+
+    #[test]
+    fn keeps_lines_added_and_removed_on_each_side() {
+        let ours = "zero\none\ntwo\nthree\nfour\nfive\n";
+        let theirs = "one\ntwo\nfour\nfive\n";
+        let merged = merge(BASE, ours, theirs);
+        assert_eq!(merged.text, "zero\none\ntwo\nfour\nfive\n");
+        assert_eq!(merged.conflicts, 0);
+    }
+
+    #[test]
+    fn marks_lines_changed_on_both_sides() {
+        let ours = "one\nlocal\nthree\nfour\nFIVE\n";
+        let theirs = "one\nresponse\nthree\nfour\nfive\n";
+        let merged = merge(BASE, ours, theirs);
+        assert_eq!(
+            merged.text,
+            "one\n<<<<<<< local\nlocal\n=======\nresponse\n>>>>>>> response\nthree\nfour\nFIVE\n"
+        );
+        assert_eq!(merged.conflicts, 1);
+    }
+
+    #[test]
+    fn counts_each_conflict() {
+        let ours = "1\ntwo\nthree\nfour\n5\n";
+        let theirs = "I\ntwo\nthree\nfour\nV\n";
+        assert_eq!(merge(BASE, ours, theirs).conflicts, 2);
+    }
+
+    #[test]
+    fn puts_the_markers_on_their_own_lines() {
+        let merged = merge("a\nb", "a\nours", "a\ntheirs");
+        assert_eq!(
+            merged.text,
+            "a\n<<<<<<< local\nours\n=======\ntheirs\n>>>>>>> response\n"
+        );
+    }
+}
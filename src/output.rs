@@ -0,0 +1,446 @@
+use crate::bundle::{self, Bundle, ProcessedFile};
+use crate::failure::Failure;
+use crate::json::Value;
+use crate::paths;
+use crate::{archive, blake3, clipboard, editor, html, ssh, state, temp, tokens, upload, RunArgs};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Output {
+    Stdout,
+    Clipboard,
+    /// Upload to a secret GitHub gist (token read from `GITHUB_TOKEN` or `GH_TOKEN`)
+    Gist,
+    /// Upload to the paste service chosen with `--paste-service`
+    Paste,
+    /// Open in `$VISUAL`/`$EDITOR` for pruning and annotating before copying
+    Editor,
+    /// Write to a new temp file and print only its path
+    Tempfile,
+    /// Write to the given file (`file:<path>`)
+    File(PathBuf),
+    /// Pack the matched files into a zip or tar(.gz) archive (`archive:<path>`)
+    Archive(PathBuf),
+    /// Write to a file on a remote host over SSH (`ssh:[user@]host:<path>`)
+    Ssh {
+        host: String,
+        path: String,
+    },
+}
+
+/// How the summary of what was bundled is printed.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Default)]
+pub enum SummaryFormat {
+    /// The colored list of files and the totals
+    #[default]
+    Text,
+    /// A JSON object on stderr (or the descriptor given with `--summary-fd`) after the outputs
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(Output::Stdout),
+            "clipboard" => Ok(Output::Clipboard),
+            "gist" => Ok(Output::Gist),
+            "paste" => Ok(Output::Paste),
+            "editor" => Ok(Output::Editor),
+            "tempfile" => Ok(Output::Tempfile),
+            _ => match s.split_once(':') {
+                Some(("file", path)) if !path.is_empty() => Ok(Output::File(path.into())),
+                Some(("archive", path)) if !path.is_empty() => Ok(Output::Archive(path.into())),
+                Some(("ssh", remote)) => match remote.split_once(':') {
+                    Some((host, path)) if !host.is_empty() && !path.is_empty() => Ok(Output::Ssh {
+                        host: host.to_string(),
+                        path: path.to_string(),
+                    }),
+                    _ => Err("expected ssh:[user@]host:<path>".to_string()),
+                },
+                _ => Err(
+                    "expected stdout, clipboard, gist, paste, editor, tempfile, \
+                          file:<path>, archive:<path> or ssh:[user@]host:<path>"
+                        .to_string(),
+                ),
+            },
+        }
+    }
+}
+
+impl Output {
+    /// Whether this output writes the bundle (or something meant for scripts) to stdout, in which
+    /// case the summary must stay off stdout.
+    fn uses_stdout(&self) -> bool {
+        matches!(self, Output::Stdout | Output::Tempfile)
+    }
+}
+
+/// Sends the bundle to every output selected in `args`, or to the default output (clipboard when
+/// stdout is a terminal, stdout otherwise) if none was selected.
+pub fn write_all(args: &RunArgs, bundle: Bundle) -> Result<()> {
+    let Bundle {
+        text: buffer,
+        files,
+        ..
+    } = &bundle;
+
+    let outputs = if args.output.is_empty() {
+        if std::io::stdout().is_terminal() {
+            vec![Output::Clipboard]
+        } else {
+            vec![Output::Stdout]
+        }
+    } else {
+        args.output.clone()
+    };
+
+    let show_summary = !args.quiet
+        && args.summary_format == SummaryFormat::Text
+        && !outputs.iter().any(Output::uses_stdout);
+    if show_summary {
+        print_file_list(args, files);
+    }
+    // The JSON summary and the skipped files report list the dropped files
+    if args.summary_format == SummaryFormat::Text && !args.report_skipped {
+        bundle::warn_dropped(&bundle);
+    }
+
+    for output in &outputs {
+        match output {
+            Output::Stdout => println!("{}", buffer),
+            Output::Clipboard => copy_to_clipboard(args, files, buffer, show_summary)?,
+            Output::Gist => {
+                let url = upload::gist(buffer)?;
+                if show_summary {
+                    print_totals(args, files, buffer, "Uploaded", "to a secret gist");
+                }
+                println!("{url}");
+                copy_url(args, url);
+            }
+            Output::Paste => {
+                let url = upload::paste(buffer, &args.paste_service, args.expire)?;
+                if show_summary {
+                    print_totals(args, files, buffer, "Uploaded", "to a paste");
+                }
+                println!("{url}");
+                copy_url(args, url);
+            }
+            Output::Editor => editor::open(buffer)?,
+            Output::Tempfile => {
+                let (path, mut file) = temp::create("txt")?;
+                file.write_all(buffer.as_bytes())
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                println!("{}", path.display());
+            }
+            Output::File(path) => {
+                temp::write_atomic(path, buffer.as_bytes())?;
+                if show_summary {
+                    print_totals(
+                        args,
+                        files,
+                        buffer,
+                        "Wrote",
+                        &format!("to {}", path.display()),
+                    );
+                }
+            }
+            Output::Archive(path) => {
+                archive::write(path, files)?;
+                if show_summary {
+                    print_totals(
+                        args,
+                        files,
+                        buffer,
+                        "Archived",
+                        &format!("to {}", path.display()),
+                    );
+                }
+            }
+            Output::Ssh { host, path } => {
+                ssh::write(host, path, buffer)?;
+                if show_summary {
+                    print_totals(
+                        args,
+                        files,
+                        buffer,
+                        "Wrote",
+                        &format!("to {path} on {host}"),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the JSON summary of a bundle to stderr, or to the file descriptor given with
+/// `--summary-fd`.
+pub fn write_summary(args: &RunArgs, summary: &Value) -> Result<()> {
+    let summary = format!("{summary}\n");
+    match args.summary_fd {
+        Some(fd) => {
+            let path = format!("/dev/fd/{fd}");
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(summary.as_bytes()))
+                .with_context(|| format!("Failed to write the summary to file descriptor {fd}"))
+        }
+        None => std::io::stderr()
+            .write_all(summary.as_bytes())
+            .context("Failed to write the summary"),
+    }
+}
+
+/// Copies the bundle to the clipboard, unless it's identical to the last one copied from this
+/// project to the same selection.
+fn copy_to_clipboard(
+    args: &RunArgs,
+    files: &[ProcessedFile],
+    buffer: &str,
+    show_summary: bool,
+) -> Result<()> {
+    let root = Path::new(&args.bundle.root);
+    let hash = blake3::hex(buffer.as_bytes());
+    let unchanged = state::last_copy(root) == Some((hash.clone(), args.clipboard_selection));
+    if !args.force && !args.append && unchanged {
+        let message = "Skipped copying to clipboard, the bundle is unchanged since the last copy \
+                       (use --force to copy anyway).";
+        if show_summary {
+            println!("{message}");
+        } else {
+            eprintln!("{message}");
+        }
+        return Ok(());
+    }
+
+    let mut text = buffer.to_string();
+    // The HTML flavor would paste the files uncompressed
+    let mut html = (args.clipboard_html && args.compress.is_none()).then(|| html::render(files));
+    if args.append {
+        let existing = clipboard::read(args.clipboard_selection, args.clipboard_cmd.as_deref())
+            .context(Failure::Clipboard)?;
+        if !existing.is_empty() {
+            // Separate with a blank line, like the files within a bundle
+            text = format!("{}\n\n{buffer}", existing.trim_end_matches('\n'));
+        }
+        if html.take().is_some() {
+            eprintln!(
+                "The HTML clipboard flavor is not supported with --append, copying plain text only."
+            );
+        }
+    }
+    if show_summary {
+        let action = if args.append { "Appended" } else { "Copied" };
+        print_totals(args, files, buffer, action, "to clipboard");
+    }
+    clipboard::copy(
+        text.clone(),
+        html,
+        args.clipboard_selection,
+        args.clipboard_cmd.as_deref(),
+    )
+    .context(Failure::Clipboard)?;
+    log::info!("Output copied to clipboard.");
+    if args.clipboard_cmd.is_none() {
+        verify_clipboard(args, &text);
+    }
+
+    // After appending the clipboard holds more than this bundle, so nothing can be skipped next time
+    let last_copy = (!args.append).then_some((hash.as_str(), args.clipboard_selection));
+    if let Err(e) = state::set_last_copy(root, last_copy) {
+        eprintln!("Failed to remember the copied bundle: {e:#}");
+    }
+    Ok(())
+}
+
+/// Reads the clipboard back and warns if it doesn't hold `expected`, since some clipboard
+/// managers silently cap the size of their entries.
+fn verify_clipboard(args: &RunArgs, expected: &str) {
+    let actual = match clipboard::read(args.clipboard_selection, None) {
+        Ok(actual) => actual,
+        Err(e) => {
+            eprintln!("Failed to read the clipboard back to verify it: {e:#}");
+            return;
+        }
+    };
+    // Some tools drop trailing newlines, which doesn't matter when pasting
+    if actual.trim_end() == expected.trim_end() {
+        log::info!("Verified the clipboard contents.");
+        return;
+    }
+
+    let (actual_chars, expected_chars) = (actual.chars().count(), expected.chars().count());
+    let message = if actual_chars < expected_chars && expected.starts_with(actual.trim_end()) {
+        format!(
+            "The clipboard was truncated to {actual_chars} of {expected_chars} characters, \
+             pasting it will leave out part of the bundle!"
+        )
+    } else {
+        format!(
+            "The clipboard holds different contents than the bundle ({actual_chars} characters \
+             instead of {expected_chars}), another program may have replaced or altered it!"
+        )
+    };
+    eprintln!("{}", message.red().bold());
+}
+
+/// Copies the URL of an uploaded bundle to the clipboard, only warning on failure since the
+/// upload itself succeeded and the URL has already been printed.
+fn copy_url(args: &RunArgs, url: String) {
+    match clipboard::copy(
+        url,
+        None,
+        args.clipboard_selection,
+        args.clipboard_cmd.as_deref(),
+    ) {
+        Ok(()) => log::info!("URL copied to clipboard."),
+        Err(e) => eprintln!("Failed to copy the URL to the clipboard: {e:#}"),
+    }
+}
+
+fn print_file_list(args: &RunArgs, matching_files: &[ProcessedFile]) {
+    if args.summary_file_template.as_deref() == Some("") {
+        return;
+    }
+    println!("{}", "Files matched".blue().bold());
+    if matching_files.is_empty() {
+        println!("{}", "No files matched.".red());
+        return;
+    }
+
+    let mut matching_files: Vec<_> = matching_files.iter().collect();
+    matching_files.sort_by_key(|v| v.line_count);
+
+    for file in matching_files {
+        if let Some(template) = &args.summary_file_template {
+            let values = [
+                ("path", paths::display(&file.path)),
+                ("lines", file.line_count.to_string()),
+                ("words", file.word_count.to_string()),
+                ("characters", file.char_count.to_string()),
+                ("tokens", file.token_count.to_string()),
+            ];
+            println!(
+                "{}",
+                fill(template, &values).expect("validated when parsed")
+            );
+            continue;
+        }
+        let file_info = format!(
+            "({} lines, {} words, {} characters)",
+            file.line_count, file.word_count, file.char_count
+        )
+        .black();
+
+        println!("{} {} {file_info}", "+".red(), paths::display(&file.path));
+    }
+    println!();
+}
+
+fn print_totals(
+    args: &RunArgs,
+    matching_files: &[ProcessedFile],
+    buffer: &str,
+    action: &str,
+    destination: &str,
+) {
+    if matching_files.is_empty() {
+        return;
+    }
+
+    let counts = tokens::count(buffer);
+    let (lines, words, characters) = (counts.lines, counts.words, counts.chars);
+    if let Some(template) = &args.summary_template {
+        if template.is_empty() {
+            return;
+        }
+        let values = [
+            ("action", action.to_string()),
+            ("destination", destination.to_string()),
+            ("files", matching_files.len().to_string()),
+            ("lines", lines.to_string()),
+            ("words", words.to_string()),
+            ("characters", characters.to_string()),
+            ("tokens", counts.tokens().to_string()),
+        ];
+        println!(
+            "{}",
+            fill(template, &values).expect("validated when parsed")
+        );
+        return;
+    }
+
+    println!(
+        "{action} {} {destination} totalling {}, {} and {}.",
+        format!("{} files", matching_files.len()).bold(),
+        format!("{lines} lines").bold(),
+        format!("{words} words").bold(),
+        format!("{characters} characters").bold()
+    );
+}
+
+/// Checks the placeholders of `--summary-template`.
+pub fn parse_totals_template(template: &str) -> Result<String, String> {
+    let names = [
+        "action",
+        "destination",
+        "files",
+        "lines",
+        "words",
+        "characters",
+        "tokens",
+    ];
+    let values = names.map(|name| (name, String::new()));
+    fill(template, &values).map(|_| template.to_string())
+}
+
+/// Checks the placeholders of `--summary-file-template`.
+pub fn parse_file_template(template: &str) -> Result<String, String> {
+    let names = ["path", "lines", "words", "characters", "tokens"];
+    let values = names.map(|name| (name, String::new()));
+    fill(template, &values).map(|_| template.to_string())
+}
+
+/// Replaces the `{name}` placeholders of `template` with their value, `{{` and `}}` standing for
+/// literal braces.
+fn fill(template: &str, values: &[(&str, String)]) -> Result<String, String> {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        text.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            text.push_str(brace);
+            rest = after;
+            continue;
+        }
+        if brace == "}" {
+            return Err("unmatched `}`, write `}}` for a literal brace".to_string());
+        }
+        let end = rest
+            .find('}')
+            .ok_or("unmatched `{`, write `{{` for a literal brace")?;
+        let name = &rest[..end];
+        let Some((_, value)) = values.iter().find(|(key, _)| *key == name) else {
+            let names: Vec<String> = values.iter().map(|(key, _)| format!("{{{key}}}")).collect();
+            return Err(format!(
+                "unknown placeholder `{{{name}}}`, expected one of {}",
+                names.join(", ")
+            ));
+        };
+        text.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
@@ -0,0 +1,152 @@
+//! Extracts files from bundles and LLM responses, in the `[file name]:` format or in the common
+//! markdown and XML conventions models tend to answer with.
+
+use crate::bundle;
+use std::path::PathBuf;
+
+/// Finds the files in `text`, as `(path, content)` pairs in the order they appear.
+///
+/// Recognized, in order of preference (the first one yielding files wins):
+/// - `[file name]:` blocks, as produced by llmbundle
+/// - `<file path="...">...</file>` elements (`name` is accepted instead of `path`)
+/// - Fenced code blocks with the path in the info string (```` ```rust src/main.rs ````) or on
+///   the line just before the fence (`src/main.rs`, `` `src/main.rs` ``, `### src/main.rs`...)
+pub fn files(text: &str) -> Vec<(PathBuf, String)> {
+    let files = bundle::parse(text);
+    if !files.is_empty() {
+        return files;
+    }
+    let files = xml(text);
+    if !files.is_empty() {
+        return files;
+    }
+    markdown(text)
+}
+
+fn xml(text: &str) -> Vec<(PathBuf, String)> {
+    let mut files = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<file ") {
+        rest = &rest[start + "<file ".len()..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let attributes = &rest[..tag_end];
+        let Some(path) = attribute(attributes, "path").or_else(|| attribute(attributes, "name"))
+        else {
+            continue;
+        };
+        let body = &rest[tag_end + 1..];
+        let Some(body_end) = body.find("</file>") else {
+            break;
+        };
+        rest = &body[body_end + "</file>".len()..];
+
+        let content = body[..body_end]
+            .strip_prefix('\n')
+            .unwrap_or(&body[..body_end]);
+        let content = match content.trim().strip_prefix("<![CDATA[") {
+            Some(cdata) => cdata
+                .strip_suffix("]]>")
+                .unwrap_or(cdata)
+                .trim_start_matches('\n'),
+            None => content,
+        };
+        files.push((PathBuf::from(path), content.to_string()));
+    }
+    files
+}
+
+/// Value of the `name="value"` (or single-quoted) attribute in `attributes`.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    attributes.match_indices(name).find_map(|(start, _)| {
+        if !attributes[..start].is_empty() && !attributes[..start].ends_with(char::is_whitespace) {
+            return None;
+        }
+        let value = attributes[start + name.len()..]
+            .trim_start()
+            .strip_prefix('=')?
+            .trim_start();
+        let quote = value.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+        let value = &value[1..];
+        value.find(quote).map(|end| &value[..end])
+    })
+}
+
+fn markdown(text: &str) -> Vec<(PathBuf, String)> {
+    let mut files = Vec::new();
+    let mut previous_line = "";
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let fence_char = match trimmed.chars().next() {
+            Some(c @ ('`' | '~')) => c,
+            _ => {
+                if !line.trim().is_empty() {
+                    previous_line = line;
+                }
+                continue;
+            }
+        };
+        let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+        if fence_len < 3 {
+            previous_line = line;
+            continue;
+        }
+
+        let info = &trimmed[fence_len..];
+        let path = info
+            .split_whitespace()
+            .last()
+            .and_then(path_in_info)
+            .or_else(|| path_in_heading(previous_line));
+
+        let mut content = String::new();
+        for line in lines.by_ref() {
+            let trimmed = line.trim_start();
+            if trimmed.chars().take_while(|c| *c == fence_char).count() >= fence_len
+                && trimmed.trim_start_matches(fence_char).trim().is_empty()
+            {
+                break;
+            }
+            content.push_str(line);
+            content.push('\n');
+        }
+
+        if let Some(path) = path {
+            files.push((PathBuf::from(path), content));
+        }
+        previous_line = "";
+    }
+    files
+}
+
+/// Path in the info string of a fence, like `src/main.rs` or `title="src/main.rs"`.
+fn path_in_info(token: &str) -> Option<&str> {
+    let token = token
+        .split_once('=')
+        .map_or(token, |(_, value)| value)
+        .trim_matches(['"', '\'']);
+    looks_like_path(token).then_some(token)
+}
+
+/// Path on the line before a fence, stripped of the usual markdown decorations.
+fn path_in_heading(line: &str) -> Option<&str> {
+    let mut line = line.trim().trim_start_matches('#').trim();
+    for prefix in ["File:", "file:", "Path:", "path:"] {
+        line = line.strip_prefix(prefix).unwrap_or(line).trim();
+    }
+    let line = line
+        .trim_end_matches(':')
+        .trim_matches(['*', '`', '_', '"']);
+    looks_like_path(line).then_some(line)
+}
+
+fn looks_like_path(s: &str) -> bool {
+    !s.is_empty()
+        && !s.contains(char::is_whitespace)
+        && (s.contains('/') || s.contains('.'))
+        && !s.starts_with("http://")
+        && !s.starts_with("https://")
+}
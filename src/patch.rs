@@ -0,0 +1,352 @@
+//! Parses unified diffs out of LLM responses and applies them leniently, since models often get
+//! line numbers, counts and surrounding whitespace slightly wrong.
+
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// How many context lines may be dropped from each end of a hunk that doesn't apply as is, like
+/// the fuzz factor of GNU patch
+const MAX_FUZZ: usize = 2;
+
+/// Path of the missing side of the diff of a created or deleted file
+const DEV_NULL: &str = "/dev/null";
+
+pub struct FilePatch {
+    pub path: PathBuf,
+    /// Whether the new side is `/dev/null`, the path then being the one of the old side
+    deletes: bool,
+    hunks: Vec<Hunk>,
+}
+
+struct Hunk {
+    /// Line the hunk claims to start at in the original file, starting at 1
+    old_start: usize,
+    lines: Vec<Line>,
+}
+
+#[derive(Clone)]
+enum Line {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+impl Hunk {
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Removed(text) => Some(text.as_str()),
+                Line::Added(_) => None,
+            })
+            .collect()
+    }
+
+    fn new_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Added(text) => Some(text.as_str()),
+                Line::Removed(_) => None,
+            })
+            .collect()
+    }
+
+    /// The hunk without up to `fuzz` context lines at its start and end.
+    fn fuzzed(&self, fuzz: usize) -> Hunk {
+        let leading = self
+            .lines
+            .iter()
+            .take(fuzz)
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        let trailing = self.lines[leading..]
+            .iter()
+            .rev()
+            .take(fuzz)
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        Hunk {
+            old_start: self.old_start + leading,
+            lines: self.lines[leading..self.lines.len() - trailing].to_vec(),
+        }
+    }
+}
+
+/// Finds the unified diffs in `text`, ignoring the prose and code fences around them.
+pub fn parse(text: &str) -> Vec<FilePatch> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if let (Some(old), Some(new)) = (
+            line.strip_prefix("--- "),
+            lines.get(i + 1).and_then(|next| next.strip_prefix("+++ ")),
+        ) {
+            let deletes = header_path(new, "b/") == DEV_NULL;
+            let path = match deletes {
+                true => header_path(old, "a/"),
+                false => header_path(new, "b/"),
+            };
+            patches.push(FilePatch {
+                path: PathBuf::from(path),
+                deletes,
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+
+        let (Some(patch), Some(old_start)) = (patches.last_mut(), hunk_start(line)) else {
+            i += 1;
+            continue;
+        };
+        i += 1;
+
+        let mut hunk = Hunk {
+            old_start,
+            lines: Vec::new(),
+        };
+        while let Some(&line) = lines.get(i) {
+            let starts_file = line.starts_with("--- ")
+                && lines
+                    .get(i + 1)
+                    .is_some_and(|next| next.starts_with("+++ "));
+            if starts_file || line.starts_with("@@") || line.starts_with("```") {
+                break;
+            }
+            let parsed = match line.chars().next() {
+                Some(' ') => Line::Context(line[1..].to_string()),
+                // Models (and editors) often strip the space of empty context lines
+                None => Line::Context(String::new()),
+                Some('-') => Line::Removed(line[1..].to_string()),
+                Some('+') => Line::Added(line[1..].to_string()),
+                Some('\\') => {
+                    i += 1;
+                    continue;
+                }
+                Some(_) => break,
+            };
+            hunk.lines.push(parsed);
+            i += 1;
+        }
+        // Blank lines after a hunk are more likely prose spacing than context
+        while matches!(hunk.lines.last(), Some(Line::Context(text)) if text.is_empty()) {
+            hunk.lines.pop();
+        }
+        // An empty old range is numbered after the line it follows, not from the line it starts at
+        if hunk.old_lines().is_empty() {
+            hunk.old_start += 1;
+        }
+        patch.hunks.push(hunk);
+    }
+
+    patches.retain(|patch| !patch.hunks.is_empty());
+    patches
+}
+
+/// Path of a `--- a/path` or `+++ b/path` header line, without the `prefix` and any timestamp.
+fn header_path<'a>(header: &'a str, prefix: &str) -> &'a str {
+    let path = header.split('\t').next().unwrap_or(header).trim();
+    path.strip_prefix(prefix).unwrap_or(path)
+}
+
+/// Old start line of a `@@ -a,b +c,d @@` header.
+fn hunk_start(line: &str) -> Option<usize> {
+    let range = line.strip_prefix("@@ -")?.split_whitespace().next()?;
+    range.split(',').next()?.parse().ok()
+}
+
+impl FilePatch {
+    /// Whether the patch creates a file (its hunks only add lines).
+    pub fn creates_file(&self) -> bool {
+        self.hunks.iter().all(|hunk| hunk.old_lines().is_empty())
+    }
+
+    /// Whether the patch deletes its file (`+++ /dev/null`).
+    pub fn deletes_file(&self) -> bool {
+        self.deletes
+    }
+
+    /// Applies the patch to `original`, returning the patched text.
+    pub fn apply(&self, original: &str) -> Result<String> {
+        let mut lines: Vec<String> = original.lines().map(str::to_string).collect();
+        // Offset between the line numbers of the hunks and where they actually applied
+        let mut drift: isize = 0;
+        // Hunks apply in order, so each one is searched after the previous
+        let mut cursor = 0;
+
+        for (n, hunk) in self.hunks.iter().enumerate() {
+            let applied = (0..=MAX_FUZZ).find_map(|fuzz| {
+                let hunk = hunk.fuzzed(fuzz);
+                let expected = (hunk.old_start as isize - 1 + drift).max(0) as usize;
+                find(&lines, &hunk.old_lines(), expected, cursor).map(|at| (hunk, at))
+            });
+            let Some((hunk, at)) = applied else {
+                bail!(
+                    "Hunk {} of the patch to {} doesn't match the file",
+                    n + 1,
+                    self.path.display()
+                );
+            };
+
+            let old_len = hunk.old_lines().len();
+            let new_lines: Vec<String> = hunk.new_lines().into_iter().map(str::to_string).collect();
+            let new_len = new_lines.len();
+            drift =
+                at as isize - (hunk.old_start as isize - 1) + new_len as isize - old_len as isize;
+            lines.splice(at..at + old_len, new_lines);
+            cursor = at + new_len;
+        }
+
+        let mut text = lines.join("\n");
+        if !lines.is_empty() && (original.is_empty() || original.ends_with('\n')) {
+            text.push('\n');
+        }
+        Ok(text)
+    }
+}
+
+/// Position of `needle` in `lines` at or after `cursor` closest to `expected`, ignoring trailing
+/// whitespace.
+fn find(lines: &[String], needle: &[&str], expected: usize, cursor: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(expected.clamp(cursor, lines.len()));
+    }
+    let matches_at = |at: usize| {
+        at >= cursor
+            && at + needle.len() <= lines.len()
+            && lines[at..at + needle.len()]
+                .iter()
+                .zip(needle)
+                .all(|(line, expected)| line.trim_end() == expected.trim_end())
+    };
+    (0..=lines.len()).find_map(|distance| {
+        [expected.checked_sub(distance), Some(expected + distance)]
+            .into_iter()
+            .flatten()
+            .find(|&at| matches_at(at))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines `line 1` to `line {count}`, each ending with a newline.
+    fn numbered(count: usize) -> String {
+        (1..=count).map(|n| format!("line {n}\n")).collect()
+    }
+
+    fn patch(text: &str) -> FilePatch {
+        let mut patches = parse(text);
+        assert_eq!(patches.len(), 1);
+        patches.remove(0)
+    }
+
+    #[test]
+    fn applies_at_the_given_lines() {
+        let patch = patch("--- a/f\n+++ b/f\n@@ -2,3 +2,3 @@\n line 2\n-line 3\n+three\n line 4\n");
+        assert_eq!(patch.path, PathBuf::from("f"));
+        let expected = numbered(5).replace("line 3\n", "three\n");
+        assert_eq!(patch.apply(&numbered(5)).unwrap(), expected);
+    }
+
+    #[test]
+    fn finds_hunks_away_from_their_line_numbers() {
+        // The lines are at 12-14, not 2-4
+        let patch = patch("--- a/f\n+++ b/f\n@@ -2,3 +2,3 @@\n line 12\n-line 13\n+13\n line 14\n");
+        let expected = numbered(20).replace("line 13\n", "13\n");
+        assert_eq!(patch.apply(&numbered(20)).unwrap(), expected);
+    }
+
+    #[test]
+    fn carries_the_drift_of_a_hunk_to_the_next() {
+        // The first hunk adds two lines and is off by five, which the second header ignores
+        let patch = patch(
+            "--- a/f\n+++ b/f\n\
+             @@ -1,2 +1,4 @@\n line 6\n+new a\n+new b\n line 7\n\
+             @@ -20,3 +22,3 @@\n line 25\n-line 26\n+26\n line 27\n",
+        );
+        let expected = numbered(30)
+            .replace("line 6\n", "line 6\nnew a\nnew b\n")
+            .replace("line 26\n", "26\n");
+        assert_eq!(patch.apply(&numbered(30)).unwrap(), expected);
+    }
+
+    #[test]
+    fn drops_mismatched_context_up_to_the_fuzz() {
+        // The first and last context lines are wrong, with two on each end the hunk still applies
+        let patch = patch(
+            "--- a/f\n+++ b/f\n@@ -3,6 +3,6 @@\n wrong\n line 4\n line 5\n-line 6\n+six\n line 7\n wrong too\n",
+        );
+        let expected = numbered(10).replace("line 6\n", "six\n");
+        assert_eq!(patch.apply(&numbered(10)).unwrap(), expected);
+    }
+
+    #[test]
+    fn refuses_hunks_past_the_fuzz() {
+        let patch = patch(
+            "--- a/f\n+++ b/f\n@@ -3,3 +3,3 @@\n wrong\n wrong\n wrong\n-line 6\n+six\n line 7\n",
+        );
+        let error = patch.apply(&numbered(10)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Hunk 1 of the patch to f doesn't match the file"
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_whitespace() {
+        let patch = patch("--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n line 1\n-line 2\n+two\n");
+        assert_eq!(
+            patch.apply("line 1  \nline 2\t\n").unwrap(),
+            "line 1\ntwo\n"
+        );
+    }
+
+    #[test]
+    fn keeps_a_missing_final_newline() {
+        let patch = patch("--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n line 1\n-line 2\n+two\n");
+        assert_eq!(patch.apply("line 1\nline 2").unwrap(), "line 1\ntwo");
+    }
+
+    #[test]
+    fn creations_are_applied_to_nothing() {
+        let patch =
+            patch("--- /dev/null\n+++ b/src/new.rs\n@@ -0,0 +1,2 @@\n+fn a() {}\n+fn b() {}\n");
+        assert_eq!(patch.path, PathBuf::from("src/new.rs"));
+        assert!(patch.creates_file() && !patch.deletes_file());
+        assert_eq!(patch.apply("").unwrap(), "fn a() {}\nfn b() {}\n");
+    }
+
+    #[test]
+    fn insertions_follow_the_line_of_their_empty_range() {
+        let patch = patch("--- a/f\n+++ b/f\n@@ -2,0 +3 @@\n+new\n");
+        assert_eq!(patch.apply("a\nb\nc\n").unwrap(), "a\nb\nnew\nc\n");
+    }
+
+    #[test]
+    fn deletions_keep_the_old_path() {
+        let patch = patch("--- a/src/old.rs\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-fn a() {}\n-\n");
+        assert_eq!(patch.path, PathBuf::from("src/old.rs"));
+        assert!(patch.deletes_file());
+    }
+
+    #[test]
+    fn headers_lose_their_timestamps() {
+        let patch = patch(
+            "--- a/f.txt\t2024-01-01 00:00:00\n+++ b/f.txt\t2024-01-02 00:00:00\n@@ -1 +1 @@\n-a\n+b\n",
+        );
+        assert_eq!(patch.path, PathBuf::from("f.txt"));
+    }
+
+    #[test]
+    fn skips_the_prose_around_the_diffs() {
+        let text = "Here is the fix:\n\n```diff\n--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+b\n```\n\nThat's it.\n";
+        let patch = patch(text);
+        assert_eq!(patch.apply("a\n").unwrap(), "b\n");
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Builds a [`Command`] that runs `command` through the platform shell, so quoting and pipes in
+/// the user-provided string behave as they would when typed in a terminal.
+pub fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+/// Spawns `command`, streams `text` to its stdin and relays its stdout and stderr to ours.
+pub fn pipe_to(command: &str, text: &str) -> Result<()> {
+    let mut child = shell_command(command)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run pipe command: {command}"))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    match stdin.write_all(text.as_bytes()) {
+        Ok(()) => {}
+        // The command may legitimately exit without reading all of its input
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to write to pipe command: {command}"))
+        }
+    }
+    drop(stdin);
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for pipe command: {command}"))?;
+    if !status.success() {
+        bail!("Pipe command `{command}` exited with {status}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Plugins: external commands contributing file filters, content transforms and output formats,
+//! for needs too specific to land in llmbundle, such as proprietary formats or the redaction
+//! rules of an organization.
+//!
+//! Each `--plugin <COMMAND>` is run through the shell once per run and answers the requests
+//! written to its stdin on its stdout, one JSON object per line. The first request is
+//! `{"type": "hello", "version": 1}`, answered with the hooks the plugin provides, such as
+//! `{"filter": true, "transform": false, "format": false}`. Then, for the hooks it provides:
+//!
+//! - `{"type": "filter", "path": "src/main.rs"}` for each matched file, answered with
+//!   `{"include": true}` to keep it in the bundle
+//! - `{"type": "transform", "path": "src/main.rs", "content": "..."}` for each file read,
+//!   answered with its new `{"content": "..."}`
+//! - `{"type": "format", "files": [{"path": "src/main.rs", "content": "..."}]}` once bundled,
+//!   answered with the `{"text": "..."}` sent in place of the bundle
+//!
+//! Any request can be answered with `{"error": "message"}` instead. The plugins hook in the order
+//! given, and only the last one providing a format renders the bundle.
+
+use crate::bundle::Bundle;
+use crate::json::Value;
+use crate::{paths, pipe};
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+/// Version of the protocol sent in the `hello` request
+const VERSION: u64 = 1;
+
+/// The plugins of the run, started by [`start`]
+static PLUGINS: OnceLock<Vec<Plugin>> = OnceLock::new();
+
+struct Plugin {
+    command: String,
+    filter: bool,
+    transform: bool,
+    format: bool,
+    /// The requests are answered one at a time, while the files are read in parallel
+    process: Mutex<Process>,
+}
+
+struct Process {
+    // Kept so the plugin isn't waited for, exiting once its stdin is closed with ours
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// Starts the plugins run by `commands` and asks them which hooks they provide.
+pub fn start(commands: &[String]) -> Result<()> {
+    let plugins = commands
+        .iter()
+        .map(|command| Plugin::start(command))
+        .collect::<Result<_>>()?;
+    let _ = PLUGINS.set(plugins);
+    Ok(())
+}
+
+/// Whether a plugin changes the content of the files, so their counts need them read.
+pub fn transforms() -> bool {
+    plugins().any(|plugin| plugin.transform)
+}
+
+/// Whether a plugin renders the bundle.
+pub fn formats() -> bool {
+    plugins().any(|plugin| plugin.format)
+}
+
+/// Keeps the `files` every filtering plugin includes, in order.
+pub fn filter(files: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut files = files;
+    for plugin in plugins().filter(|plugin| plugin.filter) {
+        let mut kept = Vec::with_capacity(files.len());
+        for path in files {
+            let response = plugin.request(Value::object([
+                ("type", "filter".into()),
+                ("path", paths::display(&path).into()),
+            ]))?;
+            match response.get("include") {
+                Some(Value::Bool(true)) => kept.push(path),
+                Some(Value::Bool(false)) => {
+                    log::debug!(path:% = path.display(), plugin = plugin.command.as_str(); "Filtered out");
+                }
+                _ => bail!(
+                    "Plugin `{}` answered a filter without `include`",
+                    plugin.command
+                ),
+            }
+        }
+        files = kept;
+    }
+    Ok(files)
+}
+
+/// `content` of the file at `path` after every transforming plugin, as the transform of the
+/// bundler takes it.
+pub fn transform(path: &Path, content: String) -> Result<String, String> {
+    plugins()
+        .filter(|plugin| plugin.transform)
+        .try_fold(content, |content, plugin| {
+            let response = plugin.request(Value::object([
+                ("type", "transform".into()),
+                ("path", paths::display(path).into()),
+                ("content", content.into()),
+            ]))?;
+            match response.get("content").and_then(Value::as_str) {
+                Some(content) => Ok(content.to_string()),
+                None => bail!(
+                    "Plugin `{}` answered a transform without `content`",
+                    plugin.command
+                ),
+            }
+        })
+        .map_err(|e: anyhow::Error| format!("{e:#}"))
+}
+
+/// The text of `bundle` rendered by the last plugin providing a format, if any.
+pub fn format(bundle: &Bundle) -> Result<Option<String>> {
+    let Some(plugin) = plugins().filter(|plugin| plugin.format).last() else {
+        return Ok(None);
+    };
+    let files = bundle.files.iter().map(|file| {
+        Value::object([
+            ("path", paths::display(&file.path).into()),
+            ("content", file.content.as_str().into()),
+        ])
+    });
+    let response = plugin.request(Value::object([
+        ("type", "format".into()),
+        ("files", Value::Array(files.collect())),
+    ]))?;
+    match response.get("text").and_then(Value::as_str) {
+        Some(text) => Ok(Some(text.to_string())),
+        None => bail!(
+            "Plugin `{}` answered a format without `text`",
+            plugin.command
+        ),
+    }
+}
+
+fn plugins() -> impl Iterator<Item = &'static Plugin> {
+    PLUGINS.get().into_iter().flatten()
+}
+
+impl Plugin {
+    fn start(command: &str) -> Result<Plugin> {
+        let mut child = pipe::shell_command(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run plugin `{command}`"))?;
+        let process = Process {
+            stdin: child.stdin.take().expect("stdin is piped"),
+            stdout: BufReader::new(child.stdout.take().expect("stdout is piped")),
+            _child: child,
+        };
+        let mut plugin = Plugin {
+            command: command.to_string(),
+            filter: false,
+            transform: false,
+            format: false,
+            process: Mutex::new(process),
+        };
+        let hello = plugin.request(Value::object([
+            ("type", "hello".into()),
+            ("version", VERSION.into()),
+        ]))?;
+        let provides = |hook| matches!(hello.get(hook), Some(Value::Bool(true)));
+        (plugin.filter, plugin.transform, plugin.format) = (
+            provides("filter"),
+            provides("transform"),
+            provides("format"),
+        );
+        log::info!(
+            plugin = command, filter = plugin.filter, transform = plugin.transform, format = plugin.format;
+            "Started plugin"
+        );
+        Ok(plugin)
+    }
+
+    /// Sends `request` and reads the response, failing if it's an error.
+    fn request(&self, request: Value) -> Result<Value> {
+        let mut process = self.process.lock().unwrap_or_else(|e| e.into_inner());
+        let mut line = request.to_string();
+        line.push('\n');
+        process
+            .stdin
+            .write_all(line.as_bytes())
+            .and_then(|()| process.stdin.flush())
+            .with_context(|| format!("Failed to write to plugin `{}`", self.command))?;
+
+        line.clear();
+        let read = process
+            .stdout
+            .read_line(&mut line)
+            .with_context(|| format!("Failed to read from plugin `{}`", self.command))?;
+        if read == 0 {
+            bail!("Plugin `{}` exited", self.command);
+        }
+        let response = Value::parse(&line)
+            .with_context(|| format!("Plugin `{}` answered invalid JSON", self.command))?;
+        if let Some(error) = response.get("error") {
+            bail!(
+                "Plugin `{}` failed: {}",
+                self.command,
+                error.as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(response)
+    }
+}
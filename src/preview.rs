@@ -0,0 +1,52 @@
+//! Counts the files each pattern matches with `--preview`, without reading them, to iterate on
+//! the patterns quickly on big trees.
+
+use crate::failure::Failure;
+use crate::{bundle, paths, BundleArgs};
+use anyhow::Result;
+use colored::Colorize;
+
+pub fn run(args: &BundleArgs) -> Result<()> {
+    let walked = bundle::walk(args)?;
+    // Matched in NFC like the bundle does
+    let candidates: Vec<_> = walked
+        .iter()
+        .map(|path| paths::normalized(path).into_owned())
+        .collect();
+    let (positives, _) = bundle::build_glob_sets(&args.patterns)?;
+    let matching: Vec<_> = candidates
+        .iter()
+        .filter(|path| positives.is_match(path))
+        .collect();
+
+    for pattern in &args.patterns {
+        let (positive, negative) = bundle::build_glob_sets(std::slice::from_ref(pattern))?;
+        let pattern = pattern.trim();
+        if pattern.starts_with('!') {
+            let count = matching
+                .iter()
+                .filter(|path| negative.is_match(path))
+                .count();
+            println!("{count:>8}  {pattern} {}", "excluded".dimmed());
+            continue;
+        }
+        let count = candidates
+            .iter()
+            .filter(|path| positive.is_match(path))
+            .count();
+        match count {
+            0 => println!("{count:>8}  {pattern} {}", "matches no files".yellow()),
+            _ => println!("{count:>8}  {pattern}"),
+        }
+    }
+
+    let files = bundle::select(args, &walked)?;
+    println!(
+        "{}",
+        format!("{:>8}  files would be bundled", files.len()).bold()
+    );
+    if files.is_empty() {
+        return Err(Failure::NoMatches.into());
+    }
+    Ok(())
+}
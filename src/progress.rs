@@ -0,0 +1,88 @@
+//! Progress line on stderr for scans and reads that take a while, so big repositories don't look
+//! like they hang. Nothing is drawn for quick runs, when stderr isn't a terminal or when messages
+//! are logged.
+
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long an operation runs before its progress is shown
+const DELAY: Duration = Duration::from_millis(500);
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Progress {
+    label: &'static str,
+    total: Option<usize>,
+    count: AtomicUsize,
+    bytes: AtomicU64,
+    start: Instant,
+    /// When the line was last drawn, if it was
+    drawn: Mutex<Option<Instant>>,
+    enabled: bool,
+}
+
+impl Progress {
+    /// Progress of processing `total` files, or an unknown number of them.
+    pub fn new(label: &'static str, total: Option<usize>) -> Progress {
+        Progress {
+            label,
+            total,
+            count: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            start: Instant::now(),
+            drawn: Mutex::new(None),
+            enabled: std::io::stderr().is_terminal() && log::max_level() < log::LevelFilter::Info,
+        }
+    }
+
+    /// Counts a processed file of `bytes` bytes.
+    pub fn inc(&self, bytes: u64) {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        let bytes = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if !self.enabled || self.start.elapsed() < DELAY {
+            return;
+        }
+        // Another thread is drawing, this count will be shown next time
+        let Ok(mut drawn) = self.drawn.try_lock() else {
+            return;
+        };
+        if drawn.is_some_and(|drawn| drawn.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        *drawn = Some(Instant::now());
+
+        let mut line = match self.total {
+            Some(total) => format!("{} {count}/{total} files", self.label),
+            None => format!("{} {count} files", self.label),
+        };
+        if bytes > 0 {
+            let per_second = bytes as f64 / self.start.elapsed().as_secs_f64();
+            line.push_str(&format!(
+                ", {} ({}/s)",
+                size(bytes as f64),
+                size(per_second)
+            ));
+        }
+        let _ = write!(std::io::stderr(), "\r\x1b[K{line}");
+    }
+
+    /// Clears the line if it was drawn.
+    pub fn finish(&self) {
+        if self.drawn.lock().is_ok_and(|drawn| drawn.is_some()) {
+            let _ = write!(std::io::stderr(), "\r\x1b[K");
+        }
+    }
+}
+
+/// `bytes` in the largest unit keeping it above 1, like `1.5 MB`.
+pub fn size(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
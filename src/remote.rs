@@ -0,0 +1,268 @@
+//! Remote git repositories given as the root, like `--root https://github.com/org/repo#branch`:
+//! shallow-cloned into the user's cache directory the first time, fetched again on the next runs,
+//! then bundled like the directory of the clone. Reviewing an unfamiliar project takes a single
+//! command.
+//!
+//! The downloads (these clones and fetches, and the LFS objects) go through [`fetch`], which
+//! retries them and limits how many run at once, while the local files are read as they are.
+//! They're run on threads rather than on an async (tokio) backend: each one waits for a `git` or
+//! `curl` process, which a blocked thread does as well as a task would, and the LFS objects are
+//! already fetched concurrently by the threads reading the files, [`MAX_FETCHES`] at a time.
+
+use crate::{blake3, git, image};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Schemes of the repository URLs given as the root
+const SCHEMES: [&str; 4] = ["https://", "http://", "ssh://", "git://"];
+
+/// Downloads running at once at most, out of the threads reading files in parallel
+const MAX_FETCHES: usize = 4;
+/// Times a failed download is tried again
+const RETRIES: u32 = 2;
+/// Wait before the first retry, doubled before each next one
+const BACKOFF: Duration = Duration::from_millis(500);
+
+static FETCHES: Limit = Limit::new(MAX_FETCHES);
+
+/// A repository given as the root
+pub struct Remote {
+    url: String,
+    /// Branch or tag given after `#`, the default branch otherwise
+    branch: Option<String>,
+}
+
+/// The local copy of the repository or the container image `root` names, `None` if it's a
+/// local directory.
+pub fn local_copy(root: &str) -> Result<Option<PathBuf>> {
+    if let Some(reference) = root.strip_prefix(image::SCHEME) {
+        return image::checkout(reference).map(Some);
+    }
+    parse(root).map(|remote| remote.checkout()).transpose()
+}
+
+/// The remote repository `root` names, `None` if it's a local directory.
+fn parse(root: &str) -> Option<Remote> {
+    if !SCHEMES.iter().any(|scheme| root.starts_with(scheme)) {
+        return None;
+    }
+    let (url, branch) = match root.split_once('#') {
+        // Not taken for an option of git
+        Some((url, branch)) => (
+            url,
+            Some(branch).filter(|branch| !branch.is_empty() && !branch.starts_with('-')),
+        ),
+        None => (root, None),
+    };
+    Some(Remote {
+        url: url.to_string(),
+        branch: branch.map(str::to_string),
+    })
+}
+
+impl Remote {
+    /// The directory of the clone of the repository, up to date with its branch unless it can't
+    /// be fetched, cloning it the first time.
+    fn checkout(&self) -> Result<PathBuf> {
+        let cache = cache_dir()
+            .context("Failed to find the cache directory, set XDG_CACHE_HOME or HOME")?
+            .join("repos");
+        std::fs::create_dir_all(&cache)
+            .with_context(|| format!("Failed to create {}", cache.display()))?;
+        let name = self.dir_name();
+        let dir = cache.join(&name);
+        if dir.join(".git").is_dir() {
+            // Before the logger is set up, with the rest of the command line
+            if let Err(e) = self.update(&dir) {
+                eprintln!(
+                    "Failed to update the clone of {}, bundling it as last fetched: {e:#}",
+                    self.url
+                );
+            }
+            return Ok(dir);
+        }
+
+        eprintln!("Cloning {}...", self.url);
+        // Cloned aside, then moved in place, so an interrupted clone isn't taken for a whole one
+        let partial = format!("{name}.partial-{}", std::process::id());
+        let mut args = vec!["clone", "--quiet", "--depth", "1", "--single-branch"];
+        if let Some(branch) = &self.branch {
+            args.extend(["--branch", branch]);
+        }
+        args.extend(["--", &self.url, &partial]);
+        fetch(|| {
+            let _ = std::fs::remove_dir_all(cache.join(&partial));
+            git::output(&cache, &args)
+        })
+        .with_context(|| format!("Failed to clone {}", self.url))?;
+        if let Err(e) = std::fs::rename(cache.join(&partial), &dir) {
+            let _ = std::fs::remove_dir_all(cache.join(&partial));
+            // Cloned meanwhile by another run
+            if !dir.join(".git").is_dir() {
+                return Err(e)
+                    .with_context(|| format!("Failed to move the clone to {}", dir.display()));
+            }
+        }
+        Ok(dir)
+    }
+
+    /// Fetches the last commit of the branch into the clone in `dir`, and checks it out.
+    fn update(&self, dir: &Path) -> Result<()> {
+        let branch = self.branch.as_deref().unwrap_or("HEAD");
+        fetch(|| git::output(dir, &["fetch", "--quiet", "--depth", "1", "origin", branch]))?;
+        git::output(dir, &["reset", "--quiet", "--hard", "FETCH_HEAD"])?;
+        Ok(())
+    }
+
+    /// Name of the clone in the cache: the one of the repository, told apart from the other
+    /// clones of the same name by the hash of the URL and branch.
+    fn dir_name(&self) -> String {
+        let repo = self
+            .url
+            .trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(".git");
+        let repo: String = repo
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let key = format!(
+            "{}#{}",
+            self.url,
+            self.branch.as_deref().unwrap_or_default()
+        );
+        format!("{repo}-{}", &blake3::hex(key.as_bytes())[..12])
+    }
+}
+
+/// `llmbundle` in the user's cache directory, following the XDG base directory spec.
+pub fn cache_dir() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".cache")))?;
+    Some(dir.join("llmbundle"))
+}
+
+/// Runs the download `f` once fewer than [`MAX_FETCHES`] others are running, trying it again
+/// [`RETRIES`] times with a growing wait when it fails, as network errors are often transient.
+pub fn fetch<T>(f: impl FnMut() -> Result<T>) -> Result<T> {
+    FETCHES.run(|| retry(RETRIES, BACKOFF, f))
+}
+
+/// Calls `f` until it succeeds, at most `retries` more times after the first failure, waiting
+/// `backoff` before the first retry and twice as long before each next one.
+fn retry<T>(retries: u32, backoff: Duration, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut wait = backoff;
+    for attempt in 1.. {
+        match f() {
+            Err(e) if attempt <= retries => {
+                log::debug!("Download failed, trying again in {wait:?}: {e:#}");
+                sleep(wait);
+                wait *= 2;
+            }
+            result => return result,
+        }
+    }
+    unreachable!("the attempts only stop by returning")
+}
+
+/// A counting semaphore, capping how many threads run a section at once.
+struct Limit {
+    running: Mutex<usize>,
+    freed: Condvar,
+    max: usize,
+}
+
+impl Limit {
+    const fn new(max: usize) -> Limit {
+        Limit {
+            running: Mutex::new(0),
+            freed: Condvar::new(),
+            max,
+        }
+    }
+
+    /// Calls `f` once fewer than `max` other threads are in it.
+    fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        {
+            let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+            let mut running = self
+                .freed
+                .wait_while(running, |running| *running >= self.max)
+                .unwrap_or_else(|e| e.into_inner());
+            *running += 1;
+        }
+        // Released even if `f` panics, so the other threads don't wait forever
+        let _slot = Slot(self);
+        f()
+    }
+}
+
+struct Slot<'a>(&'a Limit);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn retries_until_success() {
+        let mut attempts = 0;
+        let result = retry(2, Duration::ZERO, || {
+            attempts += 1;
+            match attempts {
+                3 => Ok(attempts),
+                _ => anyhow::bail!("attempt {attempts} failed"),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_the_retries() {
+        let mut attempts = 0;
+        let result: Result<()> = retry(2, Duration::ZERO, || {
+            attempts += 1;
+            anyhow::bail!("attempt {attempts} failed")
+        });
+        assert_eq!(result.unwrap_err().to_string(), "attempt 3 failed");
+    }
+
+    #[test]
+    fn limits_the_concurrent_runs() {
+        let limit = Limit::new(2);
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    limit.run(|| {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        sleep(Duration::from_millis(20));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                });
+            }
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+}
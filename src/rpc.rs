@@ -0,0 +1,138 @@
+//! JSON-RPC server for editor plugins, meant to run as a long-lived child process.
+//!
+//! The tree is walked once and the file list is kept between requests, so plugins can ask for
+//! bundles on every keystroke. Call `refresh` after files are created or deleted.
+//!
+//! Methods (`patterns` is an array of globs, `max_tokens` an optional budget):
+//! - `list-files {patterns}`: `{files}`
+//! - `bundle {patterns, max_tokens}`: `{text, files, dropped, tokens}`
+//! - `stats {patterns, max_tokens}`: `{files, lines, words, characters, tokens}`
+//! - `apply {text}`: writes the files found in `text` (see [`parse::files`]) under the root, returns
+//!   `{written}` with their paths
+//! - `refresh`: re-walks the tree, returns `{files}` with the number of files found
+
+use crate::json::Value;
+use crate::jsonrpc::{self, Error};
+use crate::serve::{self, ServeArgs};
+use crate::{bundle, parse, tokens};
+use anyhow::{Context, Result};
+use llmbundle_core::paths;
+use std::path::Path;
+
+pub fn run(args: &ServeArgs) -> Result<()> {
+    log::info!("Serving JSON-RPC on stdin/stdout for root: {}", args.root);
+
+    let mut candidates = bundle::walk(&args.bundle_args(Vec::new(), None))?;
+
+    jsonrpc::serve_stdio(|method, params| {
+        log::info!("RPC request: {method}");
+        match method {
+            "refresh" => {
+                candidates = bundle::walk(&args.bundle_args(Vec::new(), None))?;
+                Ok(Value::object([("files", candidates.len().into())]))
+            }
+            "list-files" => {
+                let bundle_args = args.bundle_args(patterns(params)?, None);
+                let files: Vec<_> = bundle::select(&bundle_args, &candidates)?
+                    .iter()
+                    .map(|path| paths::display(path))
+                    .collect();
+                Ok(Value::object([("files", files.into())]))
+            }
+            "bundle" | "stats" => {
+                let bundle_args = args.bundle_args(patterns(params)?, max_tokens(params)?);
+                let bundle = serve::bundle(&bundle_args, Some(&candidates))?;
+                let tokens: usize = bundle.files.iter().map(|f| f.token_count).sum();
+
+                if method == "stats" {
+                    let counts = tokens::count(&bundle.text);
+                    return Ok(Value::object([
+                        ("files", bundle.files.len().into()),
+                        ("lines", counts.lines.into()),
+                        ("words", counts.words.into()),
+                        ("characters", counts.chars.into()),
+                        ("tokens", tokens.into()),
+                    ]));
+                }
+
+                let files = bundle
+                    .files
+                    .iter()
+                    .map(|file| {
+                        Value::object([
+                            ("path", paths::display(&file.path).into()),
+                            ("lines", file.line_count.into()),
+                            ("tokens", file.token_count.into()),
+                        ])
+                    })
+                    .collect();
+                let dropped = bundle
+                    .dropped
+                    .iter()
+                    .map(|path| paths::display(path))
+                    .collect::<Vec<_>>();
+                Ok(Value::object([
+                    ("text", bundle.text.into()),
+                    ("files", Value::Array(files)),
+                    ("dropped", dropped.into()),
+                    ("tokens", tokens.into()),
+                ]))
+            }
+            "apply" => {
+                let text = params
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| Error::invalid_params("Missing text"))?;
+                let root = Path::new(&args.root);
+                let files = parse::files(text);
+                // Check every path before writing anything so a bad block doesn't leave the tree
+                // half-applied
+                let targets = files
+                    .iter()
+                    .map(|(path, _)| bundle::resolve(root, path))
+                    .collect::<Result<Vec<_>>>()?;
+
+                let mut written = Vec::new();
+                for ((path, content), target) in files.iter().zip(targets) {
+                    if let Some(parent) = target.parent() {
+                        std::fs::create_dir_all(parent).with_context(|| {
+                            format!("Failed to create {}", paths::display(parent))
+                        })?;
+                    }
+                    std::fs::write(&target, content)
+                        .with_context(|| format!("Failed to write {}", paths::display(&target)))?;
+                    if !candidates.contains(path) {
+                        candidates.push(path.clone());
+                    }
+                    written.push(paths::display(path));
+                }
+                Ok(Value::object([("written", written.into())]))
+            }
+            method => Err(Error::method_not_found(method)),
+        }
+    })
+}
+
+fn patterns(params: &Value) -> Result<Vec<String>, Error> {
+    match params.get("patterns") {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(patterns)) => patterns
+            .iter()
+            .map(|p| p.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::invalid_params("patterns must be an array of strings")),
+        Some(_) => Err(Error::invalid_params(
+            "patterns must be an array of strings",
+        )),
+    }
+}
+
+fn max_tokens(params: &Value) -> Result<Option<usize>, Error> {
+    match params.get("max_tokens") {
+        None | Some(Value::Null) => Ok(None),
+        Some(max_tokens) => max_tokens
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| Error::invalid_params("max_tokens must be a positive integer")),
+    }
+}
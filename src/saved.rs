@@ -0,0 +1,133 @@
+//! Named bundle definitions stored in the project, so a context can be reproduced (or shared with
+//! teammates through version control) with a single `llmbundle load <name>`.
+
+use crate::bundle::{self, Bundle};
+use crate::json::Value;
+use crate::Cli;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::path::PathBuf;
+
+const DIR: &str = ".llmbundle/bundles";
+
+#[derive(Debug, Args)]
+pub struct SaveArgs {
+    /// Name to save the bundle definition under
+    name: String,
+
+    /// Also store the rendered bundle, so `load --cached` reproduces it byte for byte even after
+    /// the files change
+    #[arg(long, help = "Store the rendered output along with the definition")]
+    render: bool,
+
+    /// Patterns and flags of the bundle, exactly as they would be passed to `llmbundle`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct LoadArgs {
+    /// Name of the saved bundle
+    name: String,
+
+    /// Use the output stored with `save --render` instead of bundling the files again
+    #[arg(long, help = "Reuse the stored output")]
+    cached: bool,
+
+    /// Extra flags appended to the saved ones, e.g. `--output stdout`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+pub fn save(args: &SaveArgs) -> Result<()> {
+    let (definition, rendered) = paths(&args.name)?;
+    let cli = Cli::parse_args(args.args.clone())?;
+
+    std::fs::create_dir_all(DIR).with_context(|| format!("Failed to create {DIR}"))?;
+
+    if args.render {
+        if cli.command.is_some() {
+            bail!("Only bundles can be rendered, not subcommands");
+        }
+        let bundle = bundle::bundle(&cli.run.bundle)?;
+        bundle::warn_dropped(&bundle);
+        std::fs::write(&rendered, &bundle.text)
+            .with_context(|| format!("Failed to write {}", rendered.display()))?;
+    } else if rendered.exists() {
+        // Drop the output of a previous definition so `load --cached` can't serve a stale bundle
+        std::fs::remove_file(&rendered)
+            .with_context(|| format!("Failed to remove {}", rendered.display()))?;
+    }
+
+    let json = Value::object([("args", args.args.clone().into())]);
+    std::fs::write(&definition, format!("{json}\n"))
+        .with_context(|| format!("Failed to write {}", definition.display()))?;
+
+    println!("Saved bundle {} to {}", args.name, definition.display());
+    Ok(())
+}
+
+pub fn load(args: &LoadArgs) -> Result<()> {
+    let mut saved = arguments(&args.name)?;
+    saved.extend(args.args.iter().cloned());
+    let cli = Cli::parse_args(saved)?;
+
+    if args.cached {
+        return crate::emit(&cli.run, Bundle::from_text(rendered(&args.name)?));
+    }
+
+    crate::run(cli)
+}
+
+/// Patterns and flags saved under `name`.
+pub fn arguments(name: &str) -> Result<Vec<String>> {
+    let (definition, _) = paths(name)?;
+    let json = std::fs::read_to_string(&definition)
+        .with_context(|| format!("No saved bundle named {name}"))?;
+    Value::parse(&json)
+        .ok()
+        .and_then(|json| {
+            json.get("args")?
+                .as_array()?
+                .iter()
+                .map(|arg| arg.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .with_context(|| format!("Invalid saved bundle {}", definition.display()))
+}
+
+/// Names of the saved bundles, sorted.
+pub fn names() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(DIR) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            Some(name.strip_suffix(".json")?.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Output stored with `save --render` for the bundle named `name`.
+pub fn rendered(name: &str) -> Result<String> {
+    let (_, rendered) = paths(name)?;
+    std::fs::read_to_string(&rendered).with_context(|| {
+        format!("Bundle {name} was saved without its output, save it again with --render")
+    })
+}
+
+/// Paths of the definition and of the rendered output of the bundle named `name`.
+fn paths(name: &str) -> Result<(PathBuf, PathBuf)> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid bundle name {name:?}");
+    }
+    let dir = PathBuf::from(DIR);
+    Ok((
+        dir.join(format!("{name}.json")),
+        dir.join(format!("{name}.txt")),
+    ))
+}
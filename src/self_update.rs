@@ -0,0 +1,127 @@
+//! Replaces the running binary with the one of the latest GitHub release, for installs made by
+//! downloading a release binary rather than with cargo.
+//!
+//! Each release is expected to have a `llmbundle-<os>-<arch>` asset per platform (`.exe` on
+//! Windows), named after Rust's `std::env::consts`, and a `SHA256SUMS` asset listing their hashes
+//! in the `sha256sum` format, which the download is checked against before being installed.
+//!
+//! Only that checksum is checked, no signature: it catches a corrupted or truncated download, but
+//! not a tampered release, whose `SHA256SUMS` would be changed along with the binary.
+
+use crate::http::Request;
+use crate::json::Value;
+use crate::sha256;
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use std::path::Path;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/chicoferreira/llmbundle/releases/latest";
+const CHECKSUMS: &str = "SHA256SUMS";
+
+#[derive(Debug, Args)]
+pub struct SelfUpdateArgs {
+    /// Only tell whether a newer release is available
+    #[arg(long, help = "Check for a newer release without installing it")]
+    check: bool,
+
+    /// Install the latest release even if it's not newer than the running version
+    #[arg(long, help = "Reinstall the latest release")]
+    force: bool,
+}
+
+pub fn run(args: &SelfUpdateArgs) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let response = Request::get(LATEST_RELEASE)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "llmbundle")
+        .send()?;
+    let release = Value::parse(&response).context("Invalid response from GitHub")?;
+    let tag = release
+        .get("tag_name")
+        .and_then(Value::as_str)
+        .context("GitHub response is missing the release tag")?;
+    let latest = tag.trim_start_matches('v');
+
+    if !args.force && version(latest) <= version(current) {
+        println!("llmbundle {current} is up to date.");
+        return Ok(());
+    }
+    if args.check {
+        println!(
+            "llmbundle {} is available (running {current}).",
+            latest.bold()
+        );
+        return Ok(());
+    }
+
+    let name = format!(
+        "llmbundle-{}-{}{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::EXE_SUFFIX
+    );
+    let asset_url = |asset: &str| -> Result<String> {
+        release
+            .get("assets")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .find(|candidate| candidate.get("name").and_then(Value::as_str) == Some(asset))
+            .and_then(|candidate| candidate.get("browser_download_url")?.as_str())
+            .map(str::to_string)
+            .with_context(|| format!("Release {tag} has no {asset} asset"))
+    };
+
+    let checksums = Request::get(asset_url(CHECKSUMS)?).send()?;
+    let expected = checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(hash, _)| hash.to_lowercase())
+        .with_context(|| format!("{CHECKSUMS} of release {tag} has no hash for {name}"))?;
+
+    eprintln!("Downloading {name} {tag}...");
+    let binary = Request::get(asset_url(&name)?).send_bytes()?;
+    let actual = sha256::hex(&binary);
+    if actual != expected {
+        bail!(
+            "The checksum of the downloaded {name} doesn't match {CHECKSUMS} ({actual} instead of \
+             {expected}), leaving the installed binary as is"
+        );
+    }
+
+    let exe = std::env::current_exe().context("Failed to locate the running binary")?;
+    install(&exe, &binary)?;
+    println!("Updated llmbundle from {current} to {}.", latest.bold());
+    Ok(())
+}
+
+/// Replaces `exe` with `binary`, writing it next to it first so a failure leaves it untouched.
+fn install(exe: &Path, binary: &[u8]) -> Result<()> {
+    let new = exe.with_extension("new");
+    std::fs::write(&new, binary).with_context(|| format!("Failed to write {}", new.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make {} executable", new.display()))?;
+    }
+    // Windows can't replace a running binary, but can rename it
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("old");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old)
+            .with_context(|| format!("Failed to move {} aside", exe.display()))?;
+    }
+    std::fs::rename(&new, exe).with_context(|| format!("Failed to replace {}", exe.display()))
+}
+
+/// The numbers of a `major.minor.patch` version, to compare versions.
+fn version(version: &str) -> Vec<u64> {
+    version
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
@@ -0,0 +1,88 @@
+use crate::bundle::{self, Bundle, Conflicts, Format, Sort};
+use crate::{errors, http_server, lfs, mcp, rpc, BundleArgs};
+use anyhow::Result;
+use clap::{ArgGroup, Args};
+use std::path::PathBuf;
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("mode").required(true).args(["mcp", "http", "rpc"])))]
+pub struct ServeArgs {
+    /// Serve tools over the Model Context Protocol on stdin/stdout, for Claude Desktop and other
+    /// MCP clients
+    #[arg(long, help = "Run as an MCP server on stdin/stdout")]
+    mcp: bool,
+
+    /// Serve bundles over HTTP on the given address, e.g. `127.0.0.1:7878`
+    #[arg(long, value_name = "ADDR", help = "Run as an HTTP server")]
+    http: Option<String>,
+
+    /// Serve JSON-RPC on stdin/stdout for editor plugins, walking the tree once instead of on
+    /// every request
+    #[arg(long, help = "Run as a JSON-RPC server on stdin/stdout")]
+    rpc: bool,
+
+    /// Root directory requests are resolved against
+    #[arg(long, default_value = ".", help = "Root directory for file search")]
+    pub root: String,
+
+    /// Maximum directory depth to traverse (optional)
+    #[arg(long, help = "Set the maximum depth for directory traversal")]
+    pub max_depth: Option<usize>,
+}
+
+impl ServeArgs {
+    /// Arguments to bundle the files matching `patterns` under the served root.
+    pub fn bundle_args(&self, patterns: Vec<String>, max_tokens: Option<usize>) -> BundleArgs {
+        BundleArgs {
+            patterns,
+            max_depth: self.max_depth,
+            root: self.root.clone(),
+            repo_root: false,
+            rev: None,
+            between: None,
+            diff: false,
+            commits: None,
+            max_tokens,
+            format: Format::Default,
+            checksums: false,
+            blame: None,
+            with_git_log: false,
+            conflicts: Conflicts::Flag,
+            lfs: lfs::Lfs::Note,
+            lfs_fetch: false,
+            strict: false,
+            sort: Sort::Path,
+            reverse: false,
+            group_by_dir: None,
+            // The client asked for them, and there's no one to ask
+            max_files: 0,
+        }
+    }
+}
+
+/// Bundles the files a request's `args` match, among `candidates` when the tree was walked
+/// already, then reports the files that couldn't be read: a server logs them with each request
+/// rather than keeping them for a report at exit.
+pub fn bundle(args: &BundleArgs, candidates: Option<&[PathBuf]>) -> Result<Bundle> {
+    let bundle = match candidates {
+        Some(candidates) => {
+            bundle::select(args, candidates).and_then(|files| bundle::bundle_files(args, &files))
+        }
+        None => bundle::bundle(args),
+    };
+    errors::report();
+    bundle
+}
+
+pub fn run(args: &ServeArgs) -> Result<()> {
+    if args.mcp {
+        return mcp::run(args);
+    }
+    if args.rpc {
+        return rpc::run(args);
+    }
+    if let Some(addr) = &args.http {
+        return http_server::run(args, addr);
+    }
+    unreachable!("clap requires a server mode")
+}
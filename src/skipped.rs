@@ -0,0 +1,123 @@
+//! Report of the files left out of a bundle and why, to debug a file missing from it.
+
+use crate::bundle::{self, Bundle};
+use crate::{paths, BundleArgs};
+use anyhow::Result;
+use colored::Colorize;
+use ignore::WalkBuilder;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Prints the files under the root that didn't make it into `bundle`, by reason. Files simply not
+/// matching the patterns aren't listed.
+pub fn report(args: &BundleArgs, bundle: &Bundle) -> Result<()> {
+    let ignored: Vec<String> = ignored(args)?;
+    let excluded: Vec<String> = excluded(args)?;
+    let unreadable: Vec<String> = bundle
+        .unreadable
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let dropped: Vec<String> = bundle
+        .dropped
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
+    let categories = [
+        ("Ignored by an ignore file or hidden", ignored),
+        ("Excluded by a negative pattern", excluded),
+        ("Unreadable, bundled empty", unreadable),
+        ("Over the token budget", dropped),
+    ];
+    if categories.iter().all(|(_, paths)| paths.is_empty()) {
+        eprintln!("{}", "No files were skipped.".green());
+        return Ok(());
+    }
+    for (reason, paths) in categories {
+        if paths.is_empty() {
+            continue;
+        }
+        eprintln!("{}", format!("{reason} ({}):", paths.len()).yellow());
+        for path in paths {
+            eprintln!("{} {path}", "-".yellow());
+        }
+    }
+    Ok(())
+}
+
+/// Entries the walker leaves out, whole directories being listed once with a trailing slash.
+fn ignored(args: &BundleArgs) -> Result<Vec<String>> {
+    let root = Path::new(&args.root);
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let mut dirs = Vec::new();
+    for entry in WalkBuilder::new(root).max_depth(args.max_depth).build() {
+        let entry = entry?;
+        // The children of the deepest directories are left out by --max-depth, not ignored
+        let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+        if is_dir && args.max_depth.is_none_or(|depth| entry.depth() < depth) {
+            dirs.push(entry.path().to_owned());
+        }
+        visited.insert(entry.into_path());
+    }
+
+    let mut ignored = Vec::new();
+    for dir in dirs {
+        for child in std::fs::read_dir(&dir)? {
+            let path = child?.path();
+            if visited.contains(&path) || path.file_name().is_some_and(|name| name == ".git") {
+                continue;
+            }
+            let mut name = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            if path.is_dir() {
+                name.push('/');
+            }
+            ignored.push(name);
+        }
+    }
+    ignored.sort();
+    Ok(ignored)
+}
+
+/// Files matching a positive pattern but also a negative one, with the negative pattern.
+fn excluded(args: &BundleArgs) -> Result<Vec<String>> {
+    let negatives = args
+        .patterns
+        .iter()
+        .filter(|pattern| pattern.trim().starts_with('!'))
+        .map(|pattern| {
+            Ok((
+                pattern,
+                bundle::build_glob_sets(std::slice::from_ref(pattern))?.1,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if negatives.is_empty() {
+        return Ok(Vec::new());
+    }
+    let positives: Vec<String> = args
+        .patterns
+        .iter()
+        .filter(|pattern| !pattern.trim().starts_with('!'))
+        .cloned()
+        .collect();
+    let (positives, _) = bundle::build_glob_sets(&positives)?;
+
+    let mut excluded = Vec::new();
+    for path in bundle::walk(args)? {
+        let path = paths::normalized(&path);
+        if !positives.is_match(&path) {
+            continue;
+        }
+        if let Some((pattern, _)) = negatives.iter().find(|(_, set)| set.is_match(&path)) {
+            excluded.push(format!("{} ({})", path.display(), pattern.trim()));
+        }
+    }
+    // The walk is parallel
+    excluded.sort();
+    Ok(excluded)
+}
@@ -0,0 +1,57 @@
+//! Remote access over SSH through the system's `ssh` binary, so the user's keys, agent and
+//! `~/.ssh/config` apply as usual.
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Writes `text` to `path` on `host` (`[user@]host`), replacing the file if it exists.
+pub fn write(host: &str, path: &str, text: &str) -> Result<()> {
+    let mut child = Command::new("ssh")
+        .arg("--")
+        .arg(host)
+        .arg(format!("cat > {}", quote_path(path)))
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run ssh, is it installed?")?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())
+        .with_context(|| format!("Failed to send the bundle to {host}"))?;
+
+    let status = child.wait().context("Failed to wait for ssh")?;
+    if !status.success() {
+        bail!("Failed to write {path} on {host}, ssh exited with {status}");
+    }
+    Ok(())
+}
+
+/// Quotes `arg` for the remote POSIX shell.
+pub fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Quotes `path` for the remote POSIX shell like [`quote`], except for a leading `~/`, which the
+/// shell expands to the home directory only when it's unquoted.
+fn quote_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", quote(rest)),
+        None => quote(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_paths_but_their_home_directory() {
+        assert_eq!(quote_path("~/bundle.txt"), "~/'bundle.txt'");
+        assert_eq!(quote_path("~/my $dir/it's.txt"), r"~/'my $dir/it'\''s.txt'");
+        assert_eq!(quote_path("/tmp/~/bundle.txt"), "'/tmp/~/bundle.txt'");
+        assert_eq!(quote_path("~user/bundle.txt"), "'~user/bundle.txt'");
+    }
+}
@@ -0,0 +1,266 @@
+//! Per-project state kept between runs in the `.llmbundle/state` directory of the bundled root:
+//! the manifest of the last bundle (hash and token count of each file), along with the content of
+//! its files when bundled with `--merge-base`, and the hash of the last bundle copied to the
+//! clipboard. The [history](crate::history) of the commands run is kept in the one of the current
+//! directory, where they are run again.
+//!
+//! The state is personal, so the directory is kept out of version control even when the rest of
+//! `.llmbundle` is shared.
+
+use crate::bundle::Bundle;
+use crate::clipboard::ClipboardSelection;
+use crate::json::Value;
+use crate::{blake3, paths};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Path of the state directory within the root
+pub const DIR: &str = ".llmbundle/state";
+
+/// Algorithm of the hashes of the manifest
+const HASH: &str = "blake3";
+
+/// What the last bundle of the project contained.
+pub struct Manifest {
+    /// Unix timestamp of the bundle
+    pub time: u64,
+    /// Canonical directory the paths of the files are relative to
+    pub root: PathBuf,
+    /// BLAKE3 of the bundle text
+    pub hash: String,
+    pub files: Vec<FileState>,
+}
+
+pub struct FileState {
+    pub path: PathBuf,
+    /// BLAKE3 of the file content
+    pub hash: String,
+    pub tokens: usize,
+}
+
+impl Manifest {
+    pub fn new(root: &Path, bundle: &Bundle) -> Manifest {
+        Manifest {
+            time: now(),
+            root: canonical(root),
+            hash: blake3::hex(bundle.text.as_bytes()),
+            files: bundle
+                .files
+                .iter()
+                .map(|file| FileState {
+                    path: file.path.clone(),
+                    hash: file.hash.clone(),
+                    tokens: file.token_count,
+                })
+                .collect(),
+        }
+    }
+
+    /// Hash of `path` in the manifest, if it was bundled.
+    pub fn hash(&self, path: &Path) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|file| paths::normalized(&file.path) == paths::normalized(path))
+            .map(|file| file.hash.as_str())
+    }
+
+    /// Whether the manifest is of a bundle of the files under `root`.
+    pub fn is_of(&self, root: &Path) -> bool {
+        self.root == canonical(root)
+    }
+
+    /// Content `path` had when bundled, if it was and the content is still stored.
+    pub fn content(&self, path: &Path) -> Option<String> {
+        std::fs::read_to_string(object_path(&self.root, self.hash(path)?)).ok()
+    }
+
+    fn to_json(&self) -> Value {
+        Value::object([
+            ("time", self.time.into()),
+            ("root", self.root.display().to_string().into()),
+            ("algorithm", HASH.into()),
+            ("hash", self.hash.as_str().into()),
+            (
+                "files",
+                Value::object(self.files.iter().map(|file| {
+                    (
+                        paths::display(&file.path),
+                        Value::object([
+                            ("hash", file.hash.as_str().into()),
+                            ("tokens", file.tokens.into()),
+                        ]),
+                    )
+                })),
+            ),
+        ])
+    }
+
+    fn from_json(json: &Value) -> Option<Manifest> {
+        let Value::Object(files) = json.get("files")? else {
+            return None;
+        };
+        Some(Manifest {
+            time: json.get("time")?.as_u64()?,
+            root: PathBuf::from(json.get("root")?.as_str()?),
+            hash: json.get("hash")?.as_str()?.to_string(),
+            files: files
+                .iter()
+                .map(|(path, file)| {
+                    Some(FileState {
+                        path: PathBuf::from(path),
+                        hash: file.get("hash")?.as_str()?.to_string(),
+                        tokens: file.get("tokens")?.as_u64()? as usize,
+                    })
+                })
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+/// The state directory of the files under `root`.
+pub fn dir(root: &Path) -> PathBuf {
+    root.join(DIR)
+}
+
+/// Creates the state directory of `root` if needed.
+pub fn create_dir(root: &Path) -> Result<()> {
+    let dir = dir(root);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        std::fs::write(&gitignore, "*\n")
+            .with_context(|| format!("Failed to write {}", gitignore.display()))?;
+    }
+    Ok(())
+}
+
+/// Manifest of the last bundle of the files under `root`, if any.
+pub fn manifest(root: &Path) -> Result<Option<Manifest>> {
+    let path = manifest_path(root);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let json = Value::parse(&contents)
+        .ok()
+        .with_context(|| format!("Invalid manifest in {}", path.display()))?;
+    // Saved with SHA-256 by older versions, so none of its hashes would match
+    if json.get("algorithm").and_then(Value::as_str) != Some(HASH) {
+        log::debug!("The manifest has the hashes of an older version, ignoring it");
+        return Ok(None);
+    }
+    Manifest::from_json(&json)
+        .with_context(|| format!("Invalid manifest in {}", path.display()))
+        .map(Some)
+}
+
+/// Remembers `bundle` of the files under `root` as their last bundle, and with `contents` stores
+/// the content of its files so changes made to them since can be merged.
+pub fn save_manifest(root: &Path, bundle: &Bundle, contents: bool) -> Result<()> {
+    create_dir(root)?;
+    let manifest = Manifest::new(root, bundle);
+
+    let objects = dir(root).join("objects");
+    if contents {
+        std::fs::create_dir_all(&objects)
+            .with_context(|| format!("Failed to create {}", objects.display()))?;
+        for (file, state) in bundle.files.iter().zip(&manifest.files) {
+            let path = object_path(root, &state.hash);
+            if !path.exists() {
+                std::fs::write(&path, &file.content)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+    }
+    // Only the files of the last bundle are kept
+    if let Ok(entries) = std::fs::read_dir(&objects) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if !contents || !manifest.files.iter().any(|file| *file.hash == *name) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    let path = manifest_path(root);
+    std::fs::write(&path, manifest.to_json().to_string())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// BLAKE3 of the last bundle of the files under `root` copied to the clipboard, if any, and the
+/// selection it was copied to.
+pub fn last_copy(root: &Path) -> Option<(String, ClipboardSelection)> {
+    let line = std::fs::read_to_string(last_copy_path(root)).ok()?;
+    let (hash, selection) = line.trim().split_once(' ')?;
+    Some((
+        hash.to_string(),
+        ClipboardSelection::from_str(selection, false).ok()?,
+    ))
+}
+
+/// Remembers the BLAKE3 of the bundle just copied to the clipboard along with the selection it
+/// was copied to, or forgets the last one.
+pub fn set_last_copy(root: &Path, copy: Option<(&str, ClipboardSelection)>) -> Result<()> {
+    let path = last_copy_path(root);
+    match copy {
+        Some((hash, selection)) => {
+            create_dir(root)?;
+            let selection = selection
+                .to_possible_value()
+                .expect("selections aren't skipped");
+            std::fs::write(&path, format!("{hash} {}\n", selection.get_name()))
+                .with_context(|| format!("Failed to write {}", path.display()))
+        }
+        None => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Current Unix timestamp.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The year, month and day (UTC) of the Unix timestamp `secs`.
+pub fn civil_date(secs: u64) -> (i64, i64, i64) {
+    let days = (secs / 86400) as i64;
+
+    // Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// `path` made absolute, or as is if it doesn't exist.
+pub fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).map_or_else(|_| path.to_path_buf(), |path| paths::simplify(&path))
+}
+
+fn object_path(root: &Path, hash: &str) -> PathBuf {
+    dir(root).join("objects").join(hash)
+}
+
+fn manifest_path(root: &Path) -> PathBuf {
+    dir(root).join("manifest.json")
+}
+
+fn last_copy_path(root: &Path) -> PathBuf {
+    dir(root).join("last-copy")
+}
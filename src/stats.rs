@@ -0,0 +1,77 @@
+//! Prints the size of each file a bundle would contain, to find the ones worth excluding before
+//! hitting the context window of a model.
+
+use crate::{bundle, index, paths, BundleArgs};
+use anyhow::Result;
+use clap::Args;
+use colored::Colorize;
+use llmbundle_core::FileStats;
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    bundle: BundleArgs,
+}
+
+pub fn run(args: &StatsArgs) -> Result<()> {
+    // The index has the counts of the files, but not which ones the budget leaves out or can't
+    // be read
+    if args.bundle.max_tokens.is_none() && !args.bundle.strict {
+        let files = bundle::find_files(&args.bundle)?;
+        if let Some(stats) = index::stats(&args.bundle, &files)? {
+            print(stats);
+            return Ok(());
+        }
+    }
+
+    let bundle = bundle::bundle(&args.bundle)?;
+    print(
+        bundle
+            .files
+            .iter()
+            .map(|file| FileStats {
+                path: file.path.clone(),
+                line_count: file.line_count,
+                word_count: file.word_count,
+                char_count: file.char_count,
+                token_count: file.token_count,
+            })
+            .collect(),
+    );
+    bundle::warn_dropped(&bundle);
+    Ok(())
+}
+
+fn print(mut files: Vec<FileStats>) {
+    if files.is_empty() {
+        println!("{}", "No files matched.".red());
+        return;
+    }
+    files.sort_by(|a, b| b.token_count.cmp(&a.token_count).then(a.path.cmp(&b.path)));
+
+    println!(
+        "{}",
+        format!("{:>8} {:>8} {:>10}  file", "tokens", "lines", "characters").bold()
+    );
+    for file in &files {
+        println!(
+            "{:>8} {:>8} {:>10}  {}",
+            file.token_count,
+            file.line_count,
+            file.char_count,
+            paths::display(&file.path)
+        );
+    }
+    let total = |count: fn(&FileStats) -> usize| files.iter().map(count).sum::<usize>();
+    println!(
+        "{}",
+        format!(
+            "{:>8} {:>8} {:>10}  {} files",
+            total(|file| file.token_count),
+            total(|file| file.line_count),
+            total(|file| file.char_count),
+            files.len()
+        )
+        .bold()
+    );
+}
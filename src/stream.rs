@@ -0,0 +1,131 @@
+//! `--stream`: writes the bundle to stdout and the `file:` outputs as the files are read, so the
+//! memory used stays the same whatever the size of the bundle.
+
+use crate::bundle::Sort;
+use crate::failure::Failure;
+use crate::output::Output;
+use crate::{bundle, interrupt, plugin, temp, RunArgs};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::io::{BufWriter, Write};
+
+pub fn run(args: &RunArgs) -> Result<()> {
+    let outputs = match args.output.is_empty() {
+        true => vec![Output::Stdout],
+        false => args.output.clone(),
+    };
+    if !outputs
+        .iter()
+        .all(|output| matches!(output, Output::Stdout | Output::File(_)))
+    {
+        bail!("--stream only writes to stdout and file:<path> outputs");
+    }
+    if plugin::formats() {
+        bail!("--stream can't write the bundle a plugin renders, which needs every file read");
+    }
+    if args.bundle.sort == Sort::Tokens && args.memory_limit.is_none() {
+        bail!(
+            "--stream can't sort by tokens without --memory-limit, which needs every file read \
+             before writing the first"
+        );
+    }
+
+    let files = bundle::find_files(&args.bundle)?;
+    if files.is_empty() {
+        return Err(Failure::NoMatches.into());
+    }
+    bundle::confirm_count(&args.bundle, &files)?;
+
+    // The files replace the previous ones once the bundle is complete
+    let mut replacements = Vec::new();
+    for output in &outputs {
+        if let Output::File(path) = output {
+            replacements.push(BufWriter::new(temp::Replacement::create(path)?));
+        }
+    }
+    let to_stdout = outputs.contains(&Output::Stdout);
+    let mut stdout = to_stdout.then(|| BufWriter::new(std::io::stdout().lock()));
+    let mut sinks: Vec<&mut dyn Write> = replacements
+        .iter_mut()
+        .map(|replacement| replacement as &mut dyn Write)
+        .chain(stdout.as_mut().map(|stdout| stdout as &mut dyn Write))
+        .collect();
+
+    let guard = interrupt::catch();
+    let streamed = bundle::files_bundler(&args.bundle, &files)?
+        .memory_limit(args.memory_limit)
+        .stream(&files, &mut Tee(&mut sinks))
+        .context("Failed to write the bundle")?;
+    drop(guard);
+    drop(sinks);
+    drop(stdout);
+    if to_stdout {
+        // Like the bundles written at once with `println!`
+        let mut stdout = std::io::stdout();
+        stdout.write_all(b"\n").and_then(|()| stdout.flush())?;
+    }
+    if interrupt::interrupted() {
+        // Leaving the files as they were rather than with part of the bundle
+        drop(replacements);
+        eprintln!("Interrupted after writing {} files.", streamed.files.len());
+        std::process::exit(interrupt::EXIT_CODE);
+    }
+    for replacement in replacements {
+        replacement
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("Failed to write the bundle")?
+            .commit()?;
+    }
+
+    if !args.quiet && !to_stdout {
+        let tokens: usize = streamed.files.iter().map(|file| file.token_count).sum();
+        for output in &outputs {
+            if let Output::File(path) = output {
+                println!(
+                    "Streamed {} to {} totalling {}.",
+                    format!("{} files", streamed.files.len()).bold(),
+                    path.display(),
+                    format!("{tokens} tokens").bold()
+                );
+            }
+        }
+    }
+    bundle::warn_left_out(&streamed.dropped, args.bundle.max_tokens);
+    if !streamed.unreadable.is_empty() {
+        return Err(bundle::unreadable_error(&streamed.unreadable));
+    }
+    Ok(())
+}
+
+/// Parses a size like `512MB`, `64K` or `1GB`, in bytes without a unit.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let (amount, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let amount: u64 = amount.parse().map_err(|_| format!("invalid size: {s}"))?;
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" | "KI" => 10,
+        "M" | "MI" => 20,
+        "G" | "GI" => 30,
+        _ => return Err(format!("invalid size unit in {s}, expected KB, MB or GB")),
+    };
+    amount
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size too large: {s}"))
+}
+
+/// Writes to every sink
+struct Tee<'a, 'b>(&'a mut [&'b mut dyn Write]);
+
+impl Write for Tee<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for sink in self.0.iter_mut() {
+            sink.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.iter_mut().try_for_each(|sink| sink.flush())
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Creates a new, uniquely named file in the system temp directory, which only the user can read
+/// on Unix since the directory is shared with the other users.
+pub fn create(extension: &str) -> Result<(PathBuf, File)> {
+    create_in(&std::env::temp_dir(), "llmbundle", extension, true)
+}
+
+/// Creates a new file in `dir` named after `prefix`, unique to this process and attempt, readable
+/// by the user only when `private`, and with the default permissions otherwise.
+fn create_in(dir: &Path, prefix: &str, extension: &str, private: bool) -> Result<(PathBuf, File)> {
+    let mut options = File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    for attempt in 0u32.. {
+        let name = format!(
+            "{prefix}-{}-{nanos:x}-{attempt}.{extension}",
+            std::process::id()
+        );
+        let path = dir.join(name);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to create temp file {}", path.display()))
+            }
+        }
+    }
+
+    unreachable!("ran out of temp file names")
+}
+
+/// Writes `bytes` to `path` through a [`Replacement`].
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut replacement = Replacement::create(path)?;
+    replacement
+        .write_all(bytes)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    replacement.commit()
+}
+
+/// A file written next to the one it replaces and renamed over it once complete, so an
+/// interrupted run or a full disk leaves the previous file, never a truncated one. Dropped without
+/// [`Replacement::commit`], the file written so far is removed.
+pub struct Replacement {
+    /// The file replaced, through its symlinks
+    target: PathBuf,
+    temp: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl Replacement {
+    pub fn create(path: &Path) -> Result<Replacement> {
+        // Renaming over a symlink would replace it instead of the file it points to
+        let target = match path.symlink_metadata() {
+            Ok(metadata) if metadata.is_symlink() => std::fs::canonicalize(path)
+                .with_context(|| format!("Failed to resolve {}", path.display()))?,
+            _ => path.to_owned(),
+        };
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = target
+            .file_name()
+            .with_context(|| format!("Not a file path: {}", path.display()))?
+            .to_string_lossy();
+        // Hidden, and in the same directory since a rename can't cross filesystems
+        let (temp, file) = create_in(dir, &format!(".{name}"), "tmp", false)?;
+        let replacement = Replacement {
+            target,
+            temp,
+            file,
+            committed: false,
+        };
+        if let Ok(metadata) = std::fs::metadata(&replacement.target) {
+            std::fs::set_permissions(&replacement.temp, metadata.permissions()).with_context(
+                || {
+                    format!(
+                        "Failed to set the permissions of {}",
+                        replacement.temp.display()
+                    )
+                },
+            )?;
+        }
+        Ok(replacement)
+    }
+
+    /// Replaces the file with the one written, once it's on disk.
+    pub fn commit(mut self) -> Result<()> {
+        self.file
+            .flush()
+            .and_then(|()| self.file.sync_all())
+            .with_context(|| format!("Failed to write {}", self.target.display()))?;
+        std::fs::rename(&self.temp, &self.target)
+            .with_context(|| format!("Failed to write {}", self.target.display()))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for Replacement {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for Replacement {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn temp_files_are_only_readable_by_the_user() {
+        let (path, file) = create("txt").unwrap();
+        let mode = file.metadata().unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,143 @@
+//! `--timings`: the time taken by each phase of the run and by the slowest files, printed to
+//! stderr once done.
+
+use colored::Colorize;
+use llmbundle_core::FileTimings;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of files listed as the slowest
+const SLOWEST: usize = 10;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Walk,
+    Filter,
+    /// Wall time of reading the files, the three phases below being summed over the threads
+    Reading,
+    Read,
+    Transform,
+    Tokenize,
+    Format,
+    Output,
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Walk => "walk",
+            Phase::Filter => "filter",
+            Phase::Reading => "reading",
+            Phase::Read => "read",
+            Phase::Transform => "transform",
+            Phase::Tokenize => "tokenize",
+            Phase::Format => "format",
+            Phase::Output => "output",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Timings {
+    phases: BTreeMap<Phase, Duration>,
+    started: BTreeMap<Phase, Instant>,
+    files: Vec<(PathBuf, Duration)>,
+}
+
+/// Whether `--timings` was given, checked before locking so the reading threads don't contend
+/// for nothing
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TIMINGS: Mutex<Option<Timings>> = Mutex::new(None);
+
+pub fn enable() {
+    *lock() = Some(Timings::default());
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `f`, adding the time it took to `phase`.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    add(phase, start.elapsed());
+    result
+}
+
+pub fn start(phase: Phase) {
+    with(|timings| {
+        timings.started.insert(phase, Instant::now());
+    });
+}
+
+/// Adds the time since `phase` was started to it.
+pub fn stop(phase: Phase) {
+    with(|timings| {
+        if let Some(start) = timings.started.remove(&phase) {
+            *timings.phases.entry(phase).or_default() += start.elapsed();
+        }
+    });
+}
+
+pub fn add(phase: Phase, duration: Duration) {
+    with(|timings| *timings.phases.entry(phase).or_default() += duration);
+}
+
+/// Adds the time a thread spent on the file at `path`.
+pub fn file(path: &Path, file: &FileTimings) {
+    with(|timings| {
+        for (phase, duration) in [
+            (Phase::Read, file.read),
+            (Phase::Transform, file.transform),
+            (Phase::Tokenize, file.tokenize),
+        ] {
+            *timings.phases.entry(phase).or_default() += duration;
+        }
+        timings.files.push((path.to_owned(), file.total()));
+    });
+}
+
+/// Prints the phases that ran and the slowest files, if enabled.
+pub fn report() {
+    let Some(mut timings) = lock().take() else {
+        return;
+    };
+    eprintln!("{}", "Timings".blue().bold());
+    for (phase, duration) in &timings.phases {
+        if *phase != Phase::Reading {
+            eprintln!("  {:<10} {duration:>10.1?}", phase.name());
+        }
+    }
+    if let Some(reading) = timings.phases.get(&Phase::Reading) {
+        eprintln!(
+            "The read, transform and tokenize times are summed over the reading threads ({}), \
+             which took {reading:.1?} in all.",
+            rayon::current_num_threads()
+        );
+    }
+
+    if timings.files.is_empty() {
+        return;
+    }
+    timings
+        .files
+        .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    eprintln!("{}", "Slowest files".blue().bold());
+    for (path, duration) in timings.files.iter().take(SLOWEST) {
+        eprintln!("  {duration:>10.1?}  {}", path.display());
+    }
+}
+
+fn with(f: impl FnOnce(&mut Timings)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(timings) = lock().as_mut() {
+        f(timings);
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Timings>> {
+    TIMINGS.lock().unwrap_or_else(|e| e.into_inner())
+}
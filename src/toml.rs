@@ -0,0 +1,272 @@
+//! Parser for the subset of TOML used by the configuration files: tables, dotted keys, strings,
+//! integers, floats, booleans and arrays, read into a [`Value`] object.
+
+use crate::json::Value;
+use anyhow::{bail, Context, Result};
+
+/// Line each table and key was defined on, by path from the root
+pub type Lines = Vec<(Vec<String>, usize)>;
+
+pub fn parse(input: &str) -> Result<(Value, Lines)> {
+    let mut root = Value::Object(Vec::new());
+    let mut lines = Lines::new();
+    // Path of the table the following keys go into
+    let mut table: Vec<String> = Vec::new();
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+
+    loop {
+        parser.skip_blank();
+        let Some(c) = parser.peek() else {
+            return Ok((root, lines));
+        };
+        let line = parser.line;
+        let result = if c == b'[' {
+            parser.pos += 1;
+            parser.keys().and_then(|keys| {
+                parser.expect(b']')?;
+                // Create the table even if it stays empty
+                lookup(&mut root, &keys)?;
+                lines.push((keys.clone(), line));
+                table = keys;
+                Ok(())
+            })
+        } else {
+            parser.keys().and_then(|keys| {
+                parser.expect(b'=')?;
+                let value = parser.value()?;
+                let (last, parents) = keys.split_last().expect("keys aren't empty");
+                let path: Vec<String> = table.iter().chain(parents).cloned().collect();
+                let Value::Object(members) = lookup(&mut root, &path)? else {
+                    unreachable!("lookup returns tables");
+                };
+                if members.iter().any(|(key, _)| key == last) {
+                    bail!("duplicate key `{last}`");
+                }
+                members.push((last.clone(), value));
+                lines.push((path.into_iter().chain([last.clone()]).collect(), line));
+                Ok(())
+            })
+        };
+        result
+            .and_then(|()| parser.end_of_line())
+            .with_context(|| format!("Invalid TOML on line {line}"))?;
+    }
+}
+
+/// The table at `path` under `root`, creating the missing ones.
+fn lookup<'a>(root: &'a mut Value, path: &[String]) -> Result<&'a mut Value> {
+    let mut table = root;
+    for key in path {
+        let Value::Object(members) = table else {
+            unreachable!("only tables are traversed");
+        };
+        let index = match members.iter().position(|(k, _)| k == key) {
+            Some(index) => index,
+            None => {
+                members.push((key.clone(), Value::Object(Vec::new())));
+                members.len() - 1
+            }
+        };
+        table = &mut members[index].1;
+        if !matches!(table, Value::Object(_)) {
+            bail!("`{key}` is not a table");
+        }
+    }
+    Ok(table)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == b'\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    /// Skips spaces and tabs.
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ' | b'\t' | b'\r' | b'\n') => {
+                    self.bump();
+                }
+                Some(b'#') => {
+                    while !matches!(self.peek(), None | Some(b'\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        self.skip_spaces();
+        if self.peek() != Some(c) {
+            bail!("expected `{}`", c as char);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_spaces();
+        match self.peek() {
+            None | Some(b'\n' | b'#') => Ok(()),
+            Some(b'\r') if self.input.get(self.pos + 1) == Some(&b'\n') => Ok(()),
+            Some(_) => bail!("unexpected characters after the value"),
+        }
+    }
+
+    /// A dotted key, like `profile.review` or `"key with spaces"`.
+    fn keys(&mut self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some(b'"') => self.basic_string()?,
+                Some(b'\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        bail!("expected a key");
+                    }
+                    String::from_utf8_lossy(&self.input[start..self.pos]).into_owned()
+                }
+            };
+            keys.push(key);
+            self.skip_spaces();
+            if self.peek() != Some(b'.') {
+                return Ok(keys);
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(b'"') => self.basic_string().map(Value::String),
+            Some(b'\'') => self.literal_string().map(Value::String),
+            Some(b'[') => self.array(),
+            Some(b't' | b'f') => {
+                let rest = &self.input[self.pos..];
+                if rest.starts_with(b"true") {
+                    self.pos += 4;
+                    Ok(Value::Bool(true))
+                } else if rest.starts_with(b"false") {
+                    self.pos += 5;
+                    Ok(Value::Bool(false))
+                } else {
+                    bail!("expected a value");
+                }
+            }
+            Some(c) if c.is_ascii_digit() || matches!(c, b'+' | b'-') => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || matches!(c, b'+' | b'-' | b'.' | b'_'))
+                {
+                    self.pos += 1;
+                }
+                let number = String::from_utf8_lossy(&self.input[start..self.pos]).replace('_', "");
+                number
+                    .parse()
+                    .map(Value::Number)
+                    .with_context(|| format!("invalid number `{number}`"))
+            }
+            _ => bail!("expected a value"),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_blank();
+            match self.bump() {
+                Some(b',') => {}
+                Some(b']') => return Ok(Value::Array(values)),
+                _ => bail!("expected `,` or `]` in array"),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.bump() {
+                None | Some(b'\n') => bail!("unterminated string"),
+                Some(b'"') => break,
+                Some(b'\\') => match self.bump() {
+                    Some(b'n') => bytes.push(b'\n'),
+                    Some(b't') => bytes.push(b'\t'),
+                    Some(b'r') => bytes.push(b'\r'),
+                    Some(b'"') => bytes.push(b'"'),
+                    Some(b'\\') => bytes.push(b'\\'),
+                    Some(b'u') => {
+                        let hex = self
+                            .input
+                            .get(self.pos..self.pos + 4)
+                            .context("invalid escape")?;
+                        self.pos += 4;
+                        let c = u32::from_str_radix(&String::from_utf8_lossy(hex), 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .context("invalid escape")?;
+                        bytes.extend(c.to_string().as_bytes());
+                    }
+                    _ => bail!("invalid escape"),
+                },
+                Some(c) => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).context("invalid UTF-8 in string")
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                None | Some(b'\n') => bail!("unterminated string"),
+                Some(b'\'') => break,
+                Some(_) => {}
+            }
+        }
+        String::from_utf8(self.input[start..self.pos - 1].to_vec())
+            .context("invalid UTF-8 in string")
+    }
+}
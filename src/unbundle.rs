@@ -0,0 +1,104 @@
+//! Turns a bundle back into files on disk, making the format round-trippable.
+
+use crate::clipboard::{self, ClipboardSelection};
+use crate::{bundle, parse};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use llmbundle_core::paths;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Args)]
+pub struct UnbundleArgs {
+    /// Bundle to read: a file, `-` for stdin or `clipboard`, unless a file of that name exists
+    source: String,
+
+    /// Directory the files are written into, keeping their relative paths
+    #[arg(long, default_value = ".", help = "Target directory")]
+    into: PathBuf,
+
+    /// Replace files that already exist instead of refusing to unbundle
+    #[arg(long, help = "Overwrite existing files")]
+    overwrite: bool,
+
+    /// List the files that would be written without touching the disk
+    #[arg(long, help = "Only list the files")]
+    dry_run: bool,
+}
+
+pub fn run(args: &UnbundleArgs) -> Result<()> {
+    let text = read_source(&args.source)?;
+    let corrupted = bundle::corrupted(&text);
+    if !corrupted.is_empty() {
+        bail!(
+            "The checksums of {} don't match their content, the bundle was truncated or altered \
+             on the way",
+            join_paths(&corrupted)
+        );
+    }
+    let files = parse::files(&text);
+    if files.is_empty() {
+        bail!("No files found in {}", args.source);
+    }
+
+    // Check every path before writing anything, so a bad bundle doesn't leave a partial tree
+    let mut targets = Vec::with_capacity(files.len());
+    for (path, _) in &files {
+        let target = bundle::resolve(&args.into, path)?;
+        if target.exists() && !args.overwrite && !args.dry_run {
+            bail!(
+                "{} already exists, pass --overwrite to replace it",
+                paths::display(&target)
+            );
+        }
+        targets.push(target);
+    }
+
+    for ((path, content), target) in files.iter().zip(&targets) {
+        println!("{} {}", "+".green(), paths::display(path));
+        if args.dry_run {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", paths::display(parent)))?;
+        }
+        std::fs::write(target, content)
+            .with_context(|| format!("Failed to write {}", paths::display(target)))?;
+    }
+
+    let action = if args.dry_run { "Would write" } else { "Wrote" };
+    println!(
+        "{action} {} to {}.",
+        format!("{} files", files.len()).bold(),
+        paths::display(&args.into)
+    );
+    Ok(())
+}
+
+/// Reads text from a file, from stdin (`-`) or from the clipboard (`clipboard`, when there's no
+/// file of that name, which is read instead).
+pub fn read_source(source: &str) -> Result<String> {
+    match source {
+        "-" => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read stdin")?;
+            Ok(text)
+        }
+        "clipboard" if !Path::new(source).exists() => {
+            clipboard::read(ClipboardSelection::Clipboard, None)
+        }
+        path => std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}")),
+    }
+}
+
+pub fn join_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| paths::display(path))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
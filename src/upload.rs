@@ -0,0 +1,212 @@
+use crate::http::Request;
+use crate::json::Value;
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+
+/// Endpoint creating the gists
+const GISTS: &str = "https://api.github.com/gists";
+
+/// Uploads `text` as a secret GitHub gist and returns its URL.
+///
+/// The token is read from `GITHUB_TOKEN` or `GH_TOKEN` and needs the `gist` scope.
+pub fn gist(text: &str) -> Result<String> {
+    let token = std::env::var("GITHUB_TOKEN")
+        .or_else(|_| std::env::var("GH_TOKEN"))
+        .context("GITHUB_TOKEN or GH_TOKEN must be set to upload a gist")?;
+    gist_to(GISTS, &token, text)
+}
+
+/// Uploads `text` as a gist through the API at `url`, the JSON payload going in the body of the
+/// request however large the bundle.
+fn gist_to(url: &str, token: &str, text: &str) -> Result<String> {
+    let body = Value::object([
+        ("description", "Bundle created by llmbundle".into()),
+        ("public", false.into()),
+        (
+            "files",
+            Value::object([("bundle.txt", Value::object([("content", text.into())]))]),
+        ),
+    ]);
+
+    let response = Request::post(url)
+        .header("Authorization", &format!("Bearer {token}"))
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "llmbundle")
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .context("Failed to upload gist")?;
+
+    let response = Value::parse(&response).context("Invalid response from GitHub")?;
+    response
+        .get("html_url")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .context("GitHub response is missing the gist URL")
+}
+
+#[derive(Clone, Debug)]
+pub enum PasteService {
+    /// <https://0x0.st>, supports expiration
+    ZeroXZero,
+    /// <https://paste.rs>
+    PasteRs,
+    /// Any endpoint accepting the text as a raw POST body and answering with the paste URL
+    Custom(String),
+}
+
+impl std::str::FromStr for PasteService {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0x0" | "0x0.st" => Ok(PasteService::ZeroXZero),
+            "paste.rs" => Ok(PasteService::PasteRs),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Ok(PasteService::Custom(url.to_string()))
+            }
+            _ => Err("expected 0x0, paste.rs or an http(s) URL".to_string()),
+        }
+    }
+}
+
+/// Parses a duration like `30m`, `12h`, `1d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (amount, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration: {s}"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" | "" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration unit in {s}, expected s, m, h, d or w"
+            ))
+        }
+    };
+    Ok(Duration::from_secs(amount * seconds))
+}
+
+/// Uploads `text` to a paste service and returns the URL of the paste.
+pub fn paste(text: &str, service: &PasteService, expire: Option<Duration>) -> Result<String> {
+    let request = match service {
+        PasteService::ZeroXZero => {
+            let mut fields = vec![("file", Some("bundle.txt"), text.to_string())];
+            if let Some(expire) = expire {
+                // 0x0.st takes the expiration in hours
+                let hours = expire.as_secs().div_ceil(60 * 60).max(1);
+                fields.push(("expires", None, hours.to_string()));
+            }
+            multipart(Request::post("https://0x0.st"), &fields)
+        }
+        PasteService::PasteRs => {
+            if expire.is_some() {
+                eprintln!("paste.rs does not support expiration, ignoring --expire.");
+            }
+            Request::post("https://paste.rs/")
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(text)
+        }
+        PasteService::Custom(url) => {
+            if expire.is_some() {
+                eprintln!("Custom paste services don't support expiration, ignoring --expire.");
+            }
+            Request::post(url.as_str())
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(text)
+        }
+    };
+
+    let response = request
+        .header("User-Agent", "llmbundle")
+        .send()
+        .context("Failed to upload paste")?;
+    let url = response.trim();
+    if url.is_empty() {
+        bail!("Paste service returned an empty response");
+    }
+    Ok(url.to_string())
+}
+
+/// Encodes `fields` (name, optional file name, value) as a `multipart/form-data` body.
+fn multipart(request: Request, fields: &[(&str, Option<&str>, String)]) -> Request {
+    const BOUNDARY: &str = "llmbundle-form-boundary-7MA4YWxkTrZu0gW";
+
+    let mut body = String::new();
+    for (name, file_name, value) in fields {
+        body.push_str(&format!("--{BOUNDARY}\r\n"));
+        match file_name {
+            Some(file_name) => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{name}\"; filename=\"{file_name}\"\r\n\
+                 Content-Type: text/plain; charset=utf-8\r\n"
+            )),
+            None => body.push_str(&format!(
+                "Content-Disposition: form-data; name=\"{name}\"\r\n"
+            )),
+        }
+        body.push_str(&format!("\r\n{value}\r\n"));
+    }
+    body.push_str(&format!("--{BOUNDARY}--\r\n"));
+
+    request
+        .header(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::serve_once;
+
+    /// A bundle larger than the curl config files allow on a line
+    fn large_bundle() -> String {
+        "[file name]: src/main.rs\n\"quoted\" \\ text\n".repeat(40_000)
+    }
+
+    #[test]
+    fn uploads_large_gists() {
+        let (url, server) = serve_once(r#"{"html_url": "https://gist.github.com/1"}"#);
+        let text = large_bundle();
+        let gist = gist_to(&url, "token", &text).unwrap();
+        assert_eq!(gist, "https://gist.github.com/1");
+        let body = String::from_utf8(server.join().unwrap()).unwrap();
+        let payload = Value::parse(&body).unwrap();
+        let files = payload
+            .get("files")
+            .and_then(|files| files.get("bundle.txt"));
+        let content = files.and_then(|file| file.get("content"));
+        assert_eq!(content.and_then(Value::as_str), Some(text.as_str()));
+    }
+
+    #[test]
+    fn uploads_large_pastes() {
+        let (url, server) = serve_once("https://paste.example/1\n");
+        let text = large_bundle();
+        let paste = paste(&text, &PasteService::Custom(url), None).unwrap();
+        assert_eq!(paste, "https://paste.example/1");
+        assert_eq!(server.join().unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn uploads_large_multipart_forms() {
+        let (url, server) = serve_once("ok");
+        let text = large_bundle();
+        let fields = [
+            ("file", Some("bundle.txt"), text.clone()),
+            ("expires", None, "1".to_string()),
+        ];
+        let response = multipart(Request::post(url), &fields).send().unwrap();
+        assert_eq!(response, "ok");
+        let body = String::from_utf8(server.join().unwrap()).unwrap();
+        assert!(body.contains("filename=\"bundle.txt\"\r\n"));
+        assert!(body.contains(&format!("\r\n\r\n{text}\r\n--")));
+        assert!(body.ends_with("--\r\n"));
+    }
+}
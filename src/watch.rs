@@ -0,0 +1,120 @@
+//! Re-generates the bundle whenever the matched files change, by polling their modification
+//! times and sizes.
+//!
+//! Each poll walks the root again and reads the metadata of every matched file, as there's no
+//! file system notification API available to the build. On large trees the polls are spaced out
+//! so they keep taking a small share of a core.
+
+use crate::{bundle, errors, RunArgs};
+use anyhow::Result;
+use colored::Colorize;
+use llmbundle_core::ReadCache;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Wait between two polls at least
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// The wait is this many times as long as the last scan when that's longer, so scanning takes
+/// a fifth of the time at most
+const IDLE_FACTOR: u32 = 4;
+/// How long the files must stay untouched before re-bundling, so a burst of saves (or a
+/// `git checkout`) results in one bundle instead of many
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+pub fn run(args: &RunArgs) -> Result<()> {
+    let start = Instant::now();
+    let mut snapshot = scan(args)?;
+    let mut interval = poll_interval(start.elapsed());
+    let cache = Arc::new(ReadCache::new());
+    emit(args, &cache);
+    eprintln!("{}", "Watching for changes, press Ctrl-C to stop".blue());
+
+    loop {
+        sleep(interval);
+        let start = Instant::now();
+        let scanned = scan(args);
+        interval = poll_interval(start.elapsed());
+        let mut current = match scanned {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("{} {e:#}", "Failed to scan files:".red());
+                continue;
+            }
+        };
+        if current == snapshot {
+            continue;
+        }
+
+        // Wait for the files to settle
+        loop {
+            sleep(DEBOUNCE);
+            let Ok(settled) = scan(args) else {
+                break;
+            };
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+
+        print_delta(&snapshot, &current);
+        snapshot = current;
+        emit(args, &cache);
+    }
+}
+
+/// Bundles and sends the result to the selected outputs, only reporting failures so a broken
+/// file or a busy clipboard doesn't end the session. Only the files that changed since the last
+/// bundle are read.
+fn emit(args: &RunArgs, cache: &Arc<ReadCache>) {
+    let bundle = bundle::bundle_cached(&args.bundle, cache);
+    if let Err(e) = bundle.and_then(|bundle| crate::emit(args, bundle)) {
+        eprintln!("{} {e:#}", "Failed to bundle:".red());
+    }
+    errors::report();
+}
+
+/// How long to wait before the next poll after a scan that took `scan`.
+fn poll_interval(scan: Duration) -> Duration {
+    POLL_INTERVAL.max(scan * IDLE_FACTOR)
+}
+
+fn scan(args: &RunArgs) -> Result<Snapshot> {
+    let root = Path::new(&args.bundle.root);
+    let files = bundle::find_files(&args.bundle)?;
+    Ok(files
+        .into_iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(root.join(&path)).ok();
+            let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+            let len = metadata.map(|m| m.len()).unwrap_or_default();
+            (path, (modified, len))
+        })
+        .collect())
+}
+
+fn print_delta(before: &Snapshot, after: &Snapshot) {
+    let mut lines = Vec::new();
+    for (path, stat) in after {
+        match before.get(path) {
+            None => lines.push((path, "+".green())),
+            Some(old) if old != stat => lines.push((path, "~".yellow())),
+            Some(_) => {}
+        }
+    }
+    for path in before.keys().filter(|path| !after.contains_key(*path)) {
+        lines.push((path, "-".red()));
+    }
+    lines.sort_by(|a, b| a.0.cmp(b.0));
+
+    eprintln!();
+    eprintln!("{}", "Files changed".blue().bold());
+    for (path, marker) in lines {
+        eprintln!("{marker} {}", path.display());
+    }
+}
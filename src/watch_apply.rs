@@ -0,0 +1,80 @@
+//! Watches the clipboard for LLM responses and offers to apply them, so the whole loop is copying
+//! the context, copying the answer and confirming.
+
+use crate::apply::{self, Prompt};
+use crate::clipboard::{self, ClipboardSelection};
+use crate::{blake3, state};
+use anyhow::Result;
+use clap::Args;
+use colored::Colorize;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Args)]
+pub struct WatchApplyArgs {
+    /// Directory the paths in the responses are relative to
+    #[arg(long, default_value = ".", help = "Root directory of the working tree")]
+    root: PathBuf,
+}
+
+pub fn run(args: &WatchApplyArgs) -> Result<()> {
+    let mut prompt = Prompt::open("clipboard")?;
+    // What's on the clipboard already was copied before starting, it isn't a new response
+    let mut seen = blake3::hex(read().unwrap_or_default().as_bytes());
+    eprintln!(
+        "{}",
+        "Watching the clipboard for responses, press Ctrl-C to stop".blue()
+    );
+
+    loop {
+        sleep(POLL_INTERVAL);
+        let Some(text) = read() else {
+            continue;
+        };
+        let hash = blake3::hex(text.as_bytes());
+        if hash == seen {
+            continue;
+        }
+        seen = hash;
+        // Bundles copied by llmbundle have the same format as the responses
+        if state::last_copy(&args.root).is_some_and(|(hash, _)| hash == seen) {
+            continue;
+        }
+        let proposals = apply::proposals(&text);
+        if proposals.is_empty() {
+            continue;
+        }
+        apply::warn_corrupted(&text);
+
+        println!();
+        println!(
+            "{}",
+            format!(
+                "Found a response changing {} files on the clipboard",
+                proposals.len()
+            )
+            .blue()
+            .bold()
+        );
+        let options = apply::Options {
+            yes: false,
+            dry_run: false,
+            merge: true,
+            interactive: false,
+        };
+        if let Err(e) = apply::apply(&args.root, proposals, Some(&mut prompt), &options) {
+            eprintln!("{} {e:#}", "Failed to apply:".red());
+        }
+        eprintln!("{}", "Watching the clipboard again".blue());
+    }
+}
+
+/// Reads the clipboard, only reporting failures so a busy clipboard doesn't end the session.
+fn read() -> Option<String> {
+    clipboard::read(ClipboardSelection::Clipboard, None)
+        .inspect_err(|e| eprintln!("{} {e:#}", "Failed to read the clipboard:".red()))
+        .ok()
+}
@@ -0,0 +1,249 @@
+//! `llmbundle config wizard`: asks for the usual settings (patterns, format, output, budget,
+//! model, secrets to leave out and profiles) and writes them to `.llmbundle.toml`, for people who
+//! would rather not learn the keys.
+
+use crate::ask::Backend;
+use crate::bundle::{self, Format};
+use crate::output::Output;
+use crate::{config, init};
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Files that commonly hold credentials, offered as excludes
+const SECRETS: &[&str] = &[
+    "**/.env",
+    "**/.env.*",
+    "**/*.pem",
+    "**/*.key",
+    "**/id_rsa*",
+    "**/*.p12",
+    "**/credentials*",
+];
+
+#[derive(Debug, Args)]
+pub struct WizardArgs {
+    /// Directory of the project
+    #[arg(long, default_value = ".", help = "Root directory of the project")]
+    root: PathBuf,
+}
+
+pub fn run(args: &WizardArgs) -> Result<()> {
+    let path = args.root.join(config::FILE_NAME);
+    let mut prompt = Prompt {
+        input: std::io::stdin().lock(),
+    };
+    let detected = init::detect(&args.root);
+    if !detected.names.is_empty() {
+        println!("Detected a {} project.", detected.names.join(" and "));
+    }
+
+    let mut text = String::from(
+        "# llmbundle configuration, see https://github.com/chicoferreira/llmbundle#configuration\n\n",
+    );
+
+    let patterns = prompt.list(
+        "Patterns of the files to bundle, separated by spaces (empty for every file)",
+        &detected.patterns,
+    )?;
+    if !patterns.is_empty() {
+        text.push_str(&init::array("patterns", &strs(&patterns)));
+    }
+    let mut exclude = prompt.list(
+        "Patterns of the files to leave out, separated by spaces",
+        &detected.exclude,
+    )?;
+    if prompt.confirm(
+        "Leave out the files that usually hold secrets (.env, keys, certificates)?",
+        true,
+    )? {
+        for secret in SECRETS {
+            if !exclude.iter().any(|pattern| pattern == secret) {
+                exclude.push(secret.to_string());
+            }
+        }
+    }
+    if !exclude.is_empty() {
+        text.push_str(&init::array("exclude", &strs(&exclude)));
+    }
+
+    let format = prompt.choice::<Format>("Bundle format", Some(Format::default()))?;
+    if let Some(format) = format.filter(|format| *format != Format::default()) {
+        text.push_str(&format!("format = {}\n", init::string(&name(format))));
+    }
+    let output = prompt.parsed::<Output>(
+        "Default output (stdout, clipboard, editor, file:<path>...), empty for the clipboard in a \
+         terminal and stdout otherwise",
+    )?;
+    if let Some(output) = output {
+        text.push_str(&format!("output = {}\n", init::string(&output)));
+    }
+    if let Some(max_tokens) = prompt.parsed::<usize>("Token budget, empty for none")? {
+        text.push_str(&format!("max_tokens = {max_tokens}\n"));
+    }
+    if prompt.confirm("Add the SHA-256 of each file to its header?", false)? {
+        text.push_str("checksums = true\n");
+    }
+
+    let backend = prompt.choice::<Backend>("Backend of `llmbundle ask`, empty to skip", None)?;
+    if let Some(backend) = backend {
+        text.push_str(&format!(
+            "\n[ask]\nbackend = {}\n",
+            init::string(&name(backend))
+        ));
+        if let Some(model) = prompt.parsed::<String>("Model, empty for the backend's default")? {
+            text.push_str(&format!("model = {}\n", init::string(&model)));
+        }
+    }
+
+    loop {
+        let Some(profile) = prompt.parsed::<String>("Name of a profile to add, empty to finish")?
+        else {
+            break;
+        };
+        if !profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+        {
+            println!("{}", "Use letters, digits, - and _ only.".yellow());
+            continue;
+        }
+        text.push_str(&format!("\n[profile.{profile}]\n"));
+        let patterns = prompt.list("Patterns of the profile, separated by spaces", &[])?;
+        if !patterns.is_empty() {
+            text.push_str(&init::array("patterns", &strs(&patterns)));
+        }
+        if let Some(max_tokens) = prompt.parsed::<usize>("Token budget, empty for the default")? {
+            text.push_str(&format!("max_tokens = {max_tokens}\n"));
+        }
+    }
+
+    println!("\n{text}");
+    let question = match path.exists() {
+        true => format!("Replace {}?", path.display()),
+        false => format!("Write {}?", path.display()),
+    };
+    if !prompt.confirm(&question, !path.exists())? {
+        println!("Nothing written.");
+        return Ok(());
+    }
+    std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote {}.", path.display().to_string().bold());
+    Ok(())
+}
+
+fn strs(values: &[String]) -> Vec<&str> {
+    values.iter().map(String::as_str).collect()
+}
+
+fn name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .expect("no skipped variants")
+        .get_name()
+        .to_string()
+}
+
+/// Asks questions on stdout and reads the answers from `input`, asking again until they're valid.
+struct Prompt<R> {
+    input: R,
+}
+
+impl<R: BufRead> Prompt<R> {
+    /// The trimmed answer to `question`, failing at the end of the input.
+    fn answer(&mut self, question: &str) -> Result<String> {
+        print!("{} ", question.bold());
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        if self.input.read_line(&mut answer)? == 0 {
+            println!();
+            bail!("No answer given, nothing written");
+        }
+        Ok(answer.trim().to_string())
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool> {
+        let hint = if default { "[Y/n]" } else { "[y/N]" };
+        loop {
+            match self
+                .answer(&format!("{question} {hint}"))?
+                .to_lowercase()
+                .as_str()
+            {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => println!("{}", "Answer y or n.".yellow()),
+            }
+        }
+    }
+
+    /// Patterns separated by spaces, `default` being kept on an empty answer and `-` clearing it.
+    fn list(&mut self, question: &str, default: &[&str]) -> Result<Vec<String>> {
+        let question = match default.is_empty() {
+            true => question.to_string(),
+            false => format!("{question}, - for none\n  [{}]", default.join(" ")),
+        };
+        loop {
+            let answer = self.answer(&question)?;
+            let values: Vec<String> = match answer.as_str() {
+                "" => default.iter().map(|value| value.to_string()).collect(),
+                "-" => Vec::new(),
+                _ => answer.split_whitespace().map(str::to_string).collect(),
+            };
+            match values.iter().try_for_each(|value| check_pattern(value)) {
+                Ok(()) => return Ok(values),
+                Err(e) => println!("{}", format!("{e:#}").yellow()),
+            }
+        }
+    }
+
+    /// One of the values of `T`, or `default` on an empty answer.
+    fn choice<T: ValueEnum>(&mut self, question: &str, default: Option<T>) -> Result<Option<T>> {
+        let names: Vec<String> = T::value_variants().iter().cloned().map(name).collect();
+        let hint = match &default {
+            Some(default) => format!("({}) [{}]", names.join(", "), name(default.clone())),
+            None => format!("({})", names.join(", ")),
+        };
+        loop {
+            let answer = self.answer(&format!("{question} {hint}"))?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match T::from_str(&answer, true) {
+                Ok(value) => return Ok(Some(value)),
+                Err(_) => println!(
+                    "{}",
+                    format!("Expected one of {}.", names.join(", ")).yellow()
+                ),
+            }
+        }
+    }
+
+    /// The answer parsed as a `T`, or nothing on an empty answer. The answer is returned as
+    /// typed since it goes in the file.
+    fn parsed<T: FromStr>(&mut self, question: &str) -> Result<Option<String>>
+    where
+        T::Err: std::fmt::Display,
+    {
+        loop {
+            let answer = self.answer(question)?;
+            if answer.is_empty() {
+                return Ok(None);
+            }
+            match answer.parse::<T>() {
+                Ok(_) => return Ok(Some(answer)),
+                Err(e) => println!("{}", format!("Invalid answer: {e}.").yellow()),
+            }
+        }
+    }
+}
+
+fn check_pattern(pattern: &str) -> Result<()> {
+    let resolved = bundle::resolve_pattern(pattern.trim_start_matches('!'))?;
+    globset::Glob::new(&resolved).with_context(|| format!("Invalid pattern `{pattern}`"))?;
+    Ok(())
+}